@@ -0,0 +1,187 @@
+use std::{
+    env,
+    fs,
+    path::{Path, PathBuf},
+    process::{self, Command}
+};
+
+
+/// A way of running a program
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Backend {
+    /// `karmai`
+    Interpreter,
+
+    /// An executable built from the IR of `karmac` with `llc` and `cc`
+    Native,
+
+    /// The IR of `karmac`, run by `lli`
+    Jit,
+}
+
+/// The programs the backends use
+pub struct Tools {
+    pub karmai: String,
+    pub karmac: String,
+    pub lli: String,
+    pub llc: String,
+    pub cc: String,
+}
+
+/// A program ready to be run by a backend. The files it was built into are
+/// removed when it is dropped.
+pub struct Prepared {
+    program: String,
+    arguments: Vec<PathBuf>,
+    temporary: Vec<PathBuf>,
+}
+
+
+impl Backend {
+    pub const ALL: [Backend; 3] = [Backend::Interpreter, Backend::Native, Backend::Jit];
+
+    pub fn from_name(name: &str) -> Option<Backend> {
+        Backend::ALL.iter().cloned().find(|backend| backend.name() == name)
+    }
+
+    /// A list of backends such as `karmai,jit`, or `all`
+    pub fn from_names(names: &str) -> Result<Vec<Backend>, String> {
+        if names == "all" {
+            return Ok(Backend::ALL.to_vec());
+        }
+
+        names.split(',')
+            .map(|name| Backend::from_name(name).ok_or_else(|| format!("Unknown backend: {}", name)))
+            .collect()
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Backend::Interpreter => "karmai",
+            Backend::Native => "native",
+            Backend::Jit => "jit",
+        }
+    }
+}
+
+
+impl Tools {
+    /// The tools in the path, or the ones named by `$KARMAI`, `$KARMAC`,
+    /// `$LLI`, `$LLC` and `$CC`
+    pub fn new() -> Tools {
+        let tool = |variable: &str, default: &str| env::var(variable).unwrap_or_else(|_| default.to_owned());
+
+        Tools {
+            karmai: tool("KARMAI", "karmai"),
+            karmac: tool("KARMAC", "karmac"),
+            lli: tool("LLI", "lli"),
+            llc: tool("LLC", "llc"),
+            cc: tool("CC", "cc"),
+        }
+    }
+
+    /// Take an argument if it names a tool
+    pub fn parse_flag(&mut self, argument: &str) -> bool {
+        let tool = if let Some(path) = argument.strip_prefix("--karmai=") {
+            (&mut self.karmai, path)
+        } else if let Some(path) = argument.strip_prefix("--karmac=") {
+            (&mut self.karmac, path)
+        } else if let Some(path) = argument.strip_prefix("--lli=") {
+            (&mut self.lli, path)
+        } else if let Some(path) = argument.strip_prefix("--llc=") {
+            (&mut self.llc, path)
+        } else if let Some(path) = argument.strip_prefix("--cc=") {
+            (&mut self.cc, path)
+        } else {
+            return false;
+        };
+
+        *tool.0 = tool.1.to_owned();
+        true
+    }
+
+
+    /// Build a program for a backend, which isn't part of what is measured
+    pub fn prepare(&self, backend: Backend, program: &Path) -> Result<Prepared, String> {
+        let mut prepared = Prepared { program: String::new(), arguments: Vec::new(), temporary: Vec::new() };
+
+        match backend {
+            Backend::Interpreter => {
+                prepared.program = self.karmai.clone();
+                prepared.arguments.push(program.to_owned());
+            }
+
+            Backend::Jit => {
+                let ir = prepared.temporary(program, "ll");
+                self.compile(program, &ir)?;
+
+                prepared.program = self.lli.clone();
+                prepared.arguments.push(ir);
+            }
+
+            Backend::Native => {
+                let ir = prepared.temporary(program, "ll");
+                let object = prepared.temporary(program, "o");
+                let executable = prepared.temporary(program, "out");
+
+                self.compile(program, &ir)?;
+                run_tool(Command::new(&self.llc).arg("-O3").arg("-filetype=obj").arg("-o").arg(&object).arg(&ir))?;
+                run_tool(Command::new(&self.cc).arg("-no-pie").arg("-o").arg(&executable).arg(&object))?;
+
+                prepared.program = executable.to_string_lossy().into_owned();
+            }
+        }
+
+        Ok(prepared)
+    }
+
+    /// Write the IR `karmac` generates for a program to a file
+    fn compile(&self, program: &Path, ir: &Path) -> Result<(), String> {
+        let compiled = Command::new(&self.karmac).arg(program).output()
+            .map_err(|e| format!("could not run {}: {}", self.karmac, e))?;
+
+        if !compiled.status.success() {
+            return Err(format!("{} failed: {}", self.karmac, String::from_utf8_lossy(&compiled.stdout).trim()));
+        }
+
+        fs::write(ir, &compiled.stdout).map_err(|e| format!("{}: {}", ir.display(), e))
+    }
+}
+
+
+impl Prepared {
+    /// A command that runs the program once
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.arguments);
+        command
+    }
+
+    /// A path in the temporary directory, removed together with the program
+    fn temporary(&mut self, program: &Path, extension: &str) -> PathBuf {
+        let name = program.file_stem().map_or("program".into(), |stem| stem.to_string_lossy());
+        let path = env::temp_dir().join(format!("bench-{}-{}.{}", process::id(), name, extension));
+
+        self.temporary.push(path.clone());
+        path
+    }
+}
+
+impl Drop for Prepared {
+    fn drop(&mut self) {
+        for path in &self.temporary {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+
+fn run_tool(command: &mut Command) -> Result<(), String> {
+    let output = command.output().map_err(|e| format!("could not run {:?}: {}", command, e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("{:?} failed: {}", command, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
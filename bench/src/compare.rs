@@ -0,0 +1,166 @@
+use report::{Measurement, Report};
+
+
+/// How the time of a program with a backend changed between two reports
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub program: String,
+    pub backend: String,
+
+    /// The mean seconds before and after
+    pub baseline: f64,
+    pub current: f64,
+
+    /// The probability of a difference at least this large if the time didn't
+    /// change, or `None` with fewer than two runs on either side
+    pub p: Option<f64>,
+    pub verdict: Verdict
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Verdict {
+    Regression,
+    Improvement,
+    Unchanged,
+
+    /// Too few runs to tell whether the time changed
+    Unknown
+}
+
+
+impl Comparison {
+    /// The change relative to the baseline, 0.1 for 10% slower
+    pub fn change(&self) -> f64 {
+        self.current / self.baseline - 1.0
+    }
+}
+
+impl Verdict {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Verdict::Regression => "regression",
+            Verdict::Improvement => "improvement",
+            Verdict::Unchanged => "unchanged",
+            Verdict::Unknown => "too few runs",
+        }
+    }
+}
+
+
+/// Compare every measurement found in both reports. A change counts once it
+/// is larger than `threshold`, relative to the baseline, and significant at
+/// the level `significance` by Welch's t-test.
+pub fn compare(baseline: &Report, current: &Report, threshold: f64, significance: f64) -> Vec<Comparison> {
+    baseline.measurements.iter().filter_map(|before| {
+        let after = current.find(&before.program, &before.backend)?;
+        let p = welch_p(before, after);
+
+        let change = after.mean() / before.mean() - 1.0;
+        let verdict = match p {
+            None => Verdict::Unknown,
+            Some(p) if p < significance && change > threshold => Verdict::Regression,
+            Some(p) if p < significance && change < -threshold => Verdict::Improvement,
+            Some(_) => Verdict::Unchanged
+        };
+
+        Some(Comparison {
+            program: before.program.clone(),
+            backend: before.backend.clone(),
+            baseline: before.mean(),
+            current: after.mean(),
+            p,
+            verdict
+        })
+    }).collect()
+}
+
+
+/// The two-sided p-value of Welch's t-test on the times of two measurements
+pub fn welch_p(first: &Measurement, second: &Measurement) -> Option<f64> {
+    if first.times.len() < 2 || second.times.len() < 2 {
+        return None;
+    }
+
+    let first_variance = first.stddev().powi(2) / first.times.len() as f64;
+    let second_variance = second.stddev().powi(2) / second.times.len() as f64;
+    let variance = first_variance + second_variance;
+
+    // runs that always take the same time differ for certain, or not at all
+    if variance == 0.0 {
+        return Some(if first.mean() == second.mean() { 1.0 } else { 0.0 });
+    }
+
+    let t = (second.mean() - first.mean()) / variance.sqrt();
+    let freedom = variance.powi(2) / (first_variance.powi(2) / (first.times.len() - 1) as f64
+                                      + second_variance.powi(2) / (second.times.len() - 1) as f64);
+
+    Some(incomplete_beta(freedom / (freedom + t * t), freedom / 2.0, 0.5))
+}
+
+
+/// The regularized incomplete beta function, which gives the tails of
+/// Student's t-distribution
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+
+    // the continued fraction converges quickly on this side
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// The continued fraction of the incomplete beta function, by Lentz's method
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    let tiny = 1e-300;
+    let not_zero = |value: f64| if value.abs() < tiny { tiny } else { value };
+
+    let mut c = 1.0;
+    let mut d = 1.0 / not_zero(1.0 - (a + b) * x / (a + 1.0));
+    let mut fraction = d;
+
+    for m in 1..300 {
+        let m = m as f64;
+
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / not_zero(1.0 + even * d);
+        c = not_zero(1.0 + even / c);
+        fraction *= d * c;
+
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / not_zero(1.0 + odd * d);
+        c = not_zero(1.0 + odd / c);
+        fraction *= d * c;
+
+        if (d * c - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+
+    fraction
+}
+
+/// The logarithm of the gamma function, by Lanczos' approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46, -86.505_320_329_416_77, 24.014_098_240_830_91,
+        -1.231_739_572_450_155, 0.001_208_650_973_866_179, -0.000_005_395_239_384_953
+    ];
+
+    let mut y = x;
+    let series = COEFFICIENTS.iter().fold(1.000_000_000_190_015, |series, coefficient| {
+        y += 1.0;
+        series + coefficient / y
+    });
+
+    let t = x + 5.5;
+    (x + 0.5) * t.ln() - t + (2.506_628_274_631_000_5 * series / x).ln()
+}
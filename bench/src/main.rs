@@ -1,40 +1,403 @@
+#[macro_use]
+extern crate serde_json;
 
 use std::{
     env,
-    time,
-    process
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    process::{self, Command}
 };
 
+mod backend;
+mod compare;
+mod manifest;
+mod measure;
+mod report;
+mod table;
+
+use backend::{Backend, Tools};
+use compare::Verdict;
+use manifest::Benchmark;
+use measure::measure;
+use report::{Measurement, Report};
+
+
+const USAGE: &str = "\
+Usage: bench run [options] [MANIFEST]
+       bench compare [options] BASELINE CURRENT
+       bench table [options] RESULTS
+
+Runs every program listed in a manifest (default: benchmarks.txt) with each
+backend, and writes how long the runs took as JSON. A manifest has a program
+on each line as `name path input`, where the input is a line of text, or
+`<file` for the contents of a file.
+
+Options:
+    --backends=<list>        karmai, native and jit, separated by commas
+                             (default: all)
+    --warmup=<n>             Runs of each program that aren't measured
+                             (default: 1)
+    --runs=<n>               Measured runs of each program (default: 5)
+    --output=<file>          Write the results to a file instead of standard
+                             output
+    --karmai=<path>          The interpreter (default: karmai, or $KARMAI)
+    --karmac=<path>          The compiler (default: karmac, or $KARMAC)
+    --lli=<path>             Runs IR for jit (default: lli, or $LLI)
+    --llc=<path>             Builds IR for native (default: llc, or $LLC)
+    --cc=<path>              Links native (default: cc, or $CC)
+
+`compare` prints how the mean time of every program and backend changed
+between two results, and exits with 1 if some got slower. Options:
+    --threshold=<percent>    How much slower counts as a regression
+                             (default: 5)
+    --significance=<p>       How unlikely the difference must be by chance,
+                             by Welch's t-test (default: 0.05)
+
+`table` rewrites the table of results in a README, between `<!-- benchmarks
+-->` and `<!-- /benchmarks -->`. Options:
+    --manifest=<file>        Where the files and inputs of the programs are
+                             listed (default: benchmarks.txt)
+    --readme=<file>          The README (default: ../README.md)
+";
+
+
+struct Options {
+    manifest: String,
+    backends: Vec<Backend>,
+    warmup: usize,
+    runs: usize,
+    output: Option<String>,
+    tools: Tools
+}
+
+
 fn main() {
-    let args = env::args().skip(1);
+    let mut arguments: Vec<String> = env::args().skip(1).collect();
+    if arguments.iter().any(|argument| argument == "--help" || argument == "-h") {
+        print!("{}", USAGE);
+        return;
+    }
 
-    
-    let mut command = if cfg!(target_os = "windows") {
-        let mut command = process::Command::new("cmd");
-        command.arg("/C");
-        command
-    } else {
-        let mut command = process::Command::new("sh");
-        command.arg("-c");
-        command
+    let command = if arguments.is_empty() { String::new() } else { arguments.remove(0) };
+    let result = match command.as_str() {
+        "run" => parse_arguments(arguments).and_then(|options| run(&options)),
+        "compare" => compare(arguments),
+        "table" => table(arguments),
+        _ => {
+            eprint!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    match result {
+        Ok(code) => process::exit(code),
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            process::exit(2);
+        }
+    }
+}
+
+
+fn parse_arguments(arguments: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        manifest: "benchmarks.txt".to_owned(),
+        backends: Backend::ALL.to_vec(),
+        warmup: 1,
+        runs: 5,
+        output: None,
+        tools: Tools::new()
     };
 
-    let command_string = args.fold(String::new(), |acc, arg| format!("{} {}", acc, arg));
-    command.arg(command_string);
+    for argument in arguments {
+        if let Some(names) = argument.strip_prefix("--backends=") {
+            options.backends = Backend::from_names(names)?;
+        } else if let Some(count) = argument.strip_prefix("--warmup=") {
+            options.warmup = count.parse().map_err(|_| format!("Invalid number of runs: {}", count))?;
+        } else if let Some(count) = argument.strip_prefix("--runs=") {
+            options.runs = count.parse().ok().filter(|&runs| runs > 0)
+                .ok_or_else(|| format!("Invalid number of runs: {}", count))?;
+        } else if let Some(path) = argument.strip_prefix("--output=") {
+            options.output = Some(path.to_owned());
+        } else if options.tools.parse_flag(&argument) {
+            continue;
+        } else if argument.starts_with('-') {
+            return Err(format!("Unknown option: {}", argument));
+        } else {
+            options.manifest = argument;
+        }
+    }
+
+    Ok(options)
+}
+
+
+/// Measure every benchmark with every backend. Returns 1 if some of them
+/// couldn't be measured.
+fn run(options: &Options) -> Result<i32, String> {
+    let benchmarks = manifest::read(Path::new(&options.manifest))?;
+
+    let mut report = Report { commit: commit(), warmup: options.warmup, measurements: Vec::new() };
+    let mut failed = false;
+
+    for benchmark in &benchmarks {
+        for &backend in &options.backends {
+            match measure_benchmark(benchmark, backend, options) {
+                Ok(measurement) => {
+                    eprintln!("{:<16} {:<8} {:>10.4} s ± {:.4}, median {:.4}{}",
+                              benchmark.name, backend.name(), measurement.mean(), measurement.stddev(), measurement.median(),
+                              measurement.peak_rss.map_or(String::new(), |rss| format!(", {:.1} MB", rss as f64 / 1e6)));
+                    report.measurements.push(measurement);
+                }
+
+                Err(message) => {
+                    eprintln!("{:<16} {:<8} failed: {}", benchmark.name, backend.name(), message);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    match options.output {
+        Some(ref path) => File::create(path).and_then(|mut file| report.write(&mut file))
+            .map_err(|e| format!("{}: {}", path, e))?,
+        None => report.write(&mut io::stdout()).and_then(|_| io::stdout().flush()).map_err(|e| e.to_string())?
+    }
+
+    Ok(if failed { 1 } else { 0 })
+}
+
+fn measure_benchmark(benchmark: &Benchmark, backend: Backend, options: &Options) -> Result<Measurement, String> {
+    let prepared = options.tools.prepare(backend, &benchmark.program)?;
+    let mut measurement = Measurement {
+        program: benchmark.name.clone(),
+        backend: backend.name().to_owned(),
+        times: Vec::new(),
+        peak_rss: None
+    };
+
+    for index in 0..options.warmup + options.runs {
+        let run = measure(&mut prepared.command(), &benchmark.input)?;
+        if run.exit_code != 0 {
+            return Err(format!("exited with code {}", run.exit_code));
+        }
+
+        if index >= options.warmup {
+            measurement.times.push(run.seconds);
+            measurement.peak_rss = measurement.peak_rss.max(run.peak_rss);
+        }
+    }
+
+    Ok(measurement)
+}
+
+/// `bench compare`: report the regressions from one results file to another
+fn compare(arguments: Vec<String>) -> Result<i32, String> {
+    let mut paths = Vec::new();
+    let mut threshold = 0.05;
+    let mut significance = 0.05;
+
+    for argument in arguments {
+        if let Some(percent) = argument.strip_prefix("--threshold=") {
+            threshold = percent.parse::<f64>().ok().filter(|&percent| percent >= 0.0)
+                .ok_or_else(|| format!("Invalid threshold: {}", percent))? / 100.0;
+        } else if let Some(level) = argument.strip_prefix("--significance=") {
+            significance = level.parse().ok().filter(|&level| level > 0.0 && level < 1.0)
+                .ok_or_else(|| format!("Invalid significance level: {}", level))?;
+        } else if argument.starts_with('-') {
+            return Err(format!("Unknown option: {}", argument));
+        } else {
+            paths.push(argument);
+        }
+    }
+
+    if paths.len() != 2 {
+        return Err("Expected a baseline and a current results file".to_owned());
+    }
+
+    let baseline = read_report(&paths[0])?;
+    let current = read_report(&paths[1])?;
+    let comparisons = compare::compare(&baseline, &current, threshold, significance);
+
+    println!("{:<16} {:<8} {:>10} {:>10} {:>8} {:>8}", "program", "backend", "baseline", "current", "change", "p");
+    for comparison in &comparisons {
+        println!("{:<16} {:<8} {:>10.4} {:>10.4} {:>+7.1}% {:>8} {}",
+                 comparison.program, comparison.backend, comparison.baseline, comparison.current, comparison.change() * 100.0,
+                 comparison.p.map_or("-".to_owned(), |p| format!("{:.3}", p)), comparison.verdict.name());
+    }
+
+    for measurement in &baseline.measurements {
+        if current.find(&measurement.program, &measurement.backend).is_none() {
+            println!("{:<16} {:<8} missing from {}", measurement.program, measurement.backend, paths[1]);
+        }
+    }
+
+    let regressions = comparisons.iter().filter(|comparison| comparison.verdict == Verdict::Regression).count();
+    Ok(if regressions > 0 { 1 } else { 0 })
+}
+
+/// `bench table`: put the results into the README
+fn table(arguments: Vec<String>) -> Result<i32, String> {
+    let mut results = None;
+    let mut manifest = "benchmarks.txt".to_owned();
+    let mut readme = "../README.md".to_owned();
+
+    for argument in arguments {
+        if let Some(path) = argument.strip_prefix("--manifest=") {
+            manifest = path.to_owned();
+        } else if let Some(path) = argument.strip_prefix("--readme=") {
+            readme = path.to_owned();
+        } else if argument.starts_with('-') {
+            return Err(format!("Unknown option: {}", argument));
+        } else {
+            results = Some(argument);
+        }
+    }
+
+    let results = results.ok_or("No results file in arguments")?;
+    let report = read_report(&results)?;
+    let benchmarks = manifest::read(Path::new(&manifest))?;
+
+    let directory = Path::new(&readme).parent().unwrap_or_else(|| Path::new(""));
+    let directory = if directory == Path::new("") { Path::new(".") } else { directory };
+    let table = table::table(&report, Path::new(&results), &benchmarks, directory);
+
+    let text = fs::read_to_string(&readme).map_err(|e| format!("{}: {}", readme, e))?;
+    let text = table::replace(&text, &table).map_err(|e| format!("{}: {}", readme, e))?;
+    fs::write(&readme, text).map_err(|e| format!("{}: {}", readme, e))?;
+
+    Ok(0)
+}
+
+fn read_report(path: &str) -> Result<Report, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    Report::read(&text).map_err(|e| format!("{}: {}", path, e))
+}
 
-    let start = time::Instant::now();
 
-    command.status().unwrap();
+/// The commit checked out where `bench` runs
+fn commit() -> Option<String> {
+    let output = Command::new("git").args(["rev-parse", "HEAD"]).output().ok()?;
 
-    let seconds = seconds_since(start);
-    println!("\nseconds: {}", seconds);
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    } else {
+        None
+    }
 }
 
 
-fn seconds_since(instant: time::Instant) -> f64 {
-    let now = time::Instant::now();
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use compare::{self, Verdict};
+    use manifest::{self, Benchmark};
+    use report::{Measurement, Report};
+    use table;
+
+    fn measurement(program: &str, backend: &str, times: &[f64]) -> Measurement {
+        Measurement { program: program.to_owned(), backend: backend.to_owned(), times: times.to_vec(), peak_rss: None }
+    }
+
+    #[test]
+    fn parse_manifest() {
+        let benchmarks = manifest::parse("# name program input\n\nsum ../karma/sum.kar 123456789\nhello  hello.kar\n", Path::new("bench")).unwrap();
+        assert_eq!(benchmarks, vec![
+            Benchmark { name: "sum".to_owned(), program: Path::new("bench/../karma/sum.kar").to_owned(), input: b"123456789\n".to_vec(), input_file: None },
+            Benchmark { name: "hello".to_owned(), program: Path::new("bench/hello.kar").to_owned(), input: vec![], input_file: None },
+        ]);
+
+        assert!(manifest::parse("sum\n", Path::new("")).is_err());
+        assert!(manifest::parse("sum sum.kar\nsum sum.kar 1\n", Path::new("")).is_err());
+        assert!(manifest::parse("sum sum.kar <missing.in\n", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn summarize_runs() {
+        let measurement = Measurement { program: "sum".to_owned(), backend: "jit".to_owned(), times: vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0], peak_rss: Some(4096) };
+        assert_eq!((measurement.mean(), measurement.median()), (5.0, 4.5));
+        assert!((measurement.stddev() - 2.138).abs() < 1e-3, "{}", measurement.stddev());
+
+        let report = Report { commit: None, warmup: 1, measurements: vec![measurement] };
+        let mut json = Vec::new();
+        report.write(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json, json!({
+            "version": 1,
+            "commit": null,
+            "warmup": 1,
+            "results": [{
+                "program": "sum", "backend": "jit", "mean": 5.0, "median": 4.5, "stddev": 2.138089935299395, "peak_rss": 4096,
+                "times": [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]
+            }]
+        }));
+    }
+
+    #[test]
+    fn read_written_results() {
+        let report = Report {
+            commit: Some("b57b62f".to_owned()),
+            warmup: 2,
+            measurements: vec![measurement("sum", "karmai", &[0.25, 0.5]), measurement("say \"hi\"", "jit", &[1e-5])]
+        };
+
+        let mut json = Vec::new();
+        report.write(&mut json).unwrap();
+        assert_eq!(Report::read(&String::from_utf8(json).unwrap()).unwrap(), report);
+
+        assert!(Report::read("{\"version\": 2, \"warmup\": 0, \"results\": []}").is_err());
+        assert!(Report::read("{\"version\": 1, \"warmup\": 0, \"results\": [{\"program\": \"sum\"}]}").is_err());
+        assert!(Report::read("{\"version\": 1, \"warmup\": 0, \"results\": []").is_err());
+    }
+
+    #[test]
+    fn flag_significant_regressions() {
+        let before = [1.0, 1.1, 0.9, 1.0, 1.05];
+        let slower = [1.2, 1.25, 1.15, 1.3, 1.2];
+        let noisy = [1.02, 1.12, 0.88, 1.01, 1.04];
+
+        let p = compare::welch_p(&measurement("sum", "jit", &before), &measurement("sum", "jit", &slower)).unwrap();
+        assert!((p - 0.001244).abs() < 1e-5, "{}", p);
+        let p = compare::welch_p(&measurement("sum", "jit", &before), &measurement("sum", "jit", &noisy)).unwrap();
+        assert!((p - 0.9394).abs() < 1e-3, "{}", p);
+
+        let baseline = Report { commit: None, warmup: 1, measurements: vec![
+            measurement("sum", "karmai", &before), measurement("sum", "jit", &before),
+            measurement("fib", "karmai", &slower), measurement("fib", "jit", &[1.0])
+        ] };
+        let current = Report { commit: None, warmup: 1, measurements: vec![
+            measurement("sum", "karmai", &slower), measurement("sum", "jit", &noisy),
+            measurement("fib", "karmai", &before), measurement("fib", "jit", &[2.0])
+        ] };
+
+        let verdicts: Vec<Verdict> = compare::compare(&baseline, &current, 0.05, 0.05).iter().map(|comparison| comparison.verdict).collect();
+        assert_eq!(verdicts, vec![Verdict::Regression, Verdict::Unchanged, Verdict::Improvement, Verdict::Unknown]);
+
+        let verdicts: Vec<Verdict> = compare::compare(&baseline, &current, 0.5, 0.05).iter().map(|comparison| comparison.verdict).collect();
+        assert_eq!(verdicts[0], Verdict::Unchanged);
+    }
+
+    #[test]
+    fn rewrite_readme_table() {
+        let report = Report { commit: Some("b57b62f186fee3abb".to_owned()), warmup: 1, measurements: vec![
+            measurement("sum", "karmai", &[0.25, 0.75]), measurement("sum", "jit", &[0.125, 0.125]), measurement("fib", "karmai", &[0.5, 0.5])
+        ] };
+        let benchmarks = manifest::parse("sum sum.kar 123\n", Path::new("")).unwrap();
+
+        let table = table::table(&report, Path::new("results.json"), &benchmarks, Path::new("."));
+        assert_eq!(table, "\
+| Benchmark | File      | Input | karmai (seconds) | jit (seconds)   |
+| --------- | --------- | ----- | ---------------- | --------------- |
+| sum       | `sum.kar` | `123` | 0.5000 ± 0.3536  | 0.1250 ± 0.0000 |
+| fib       |           |       | 0.5000 ± 0.0000  | -               |
 
-    let duration = now - instant;
+Medians and standard deviations of 2 runs after 1 to warm up, from `results.json`.
+");
 
-    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+        let readme = "## Benchmarks\n\n<!-- benchmarks -->\nold\n<!-- /benchmarks -->\n\n## Next\n";
+        assert_eq!(table::replace(readme, "new\n").unwrap(), "## Benchmarks\n\n<!-- benchmarks -->\nnew\n<!-- /benchmarks -->\n\n## Next\n");
+        assert!(table::replace("## Benchmarks\n", "new\n").is_err());
+    }
 }
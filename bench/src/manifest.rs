@@ -0,0 +1,73 @@
+use std::{
+    fs,
+    path::{Path, PathBuf}
+};
+
+
+/// A program to measure, and the input it reads
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Benchmark {
+    pub name: String,
+    pub program: PathBuf,
+    pub input: Vec<u8>,
+
+    /// The file the input was read from, if not from the manifest
+    pub input_file: Option<PathBuf>
+}
+
+
+/// Read the benchmarks listed in a manifest, one on each line as
+/// `name program input`. The input is the rest of the line followed by a
+/// newline, or the contents of a file for `<file`. Paths are relative to the
+/// manifest, and empty lines and lines starting with `#` are skipped.
+pub fn read(path: &Path) -> Result<Vec<Benchmark>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    parse(&text, directory).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn parse(text: &str, directory: &Path) -> Result<Vec<Benchmark>, String> {
+    let mut benchmarks: Vec<Benchmark> = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, rest) = split_field(line);
+        let (program, input) = split_field(rest);
+
+        let name = name.to_owned();
+        if program.is_empty() {
+            return Err(format!("line {}: no program for {}", index + 1, name));
+        }
+        let program = directory.join(program);
+
+        let input_file = input.strip_prefix('<').map(|file| directory.join(file));
+        let input = if let Some(ref file) = input_file {
+            fs::read(file).map_err(|e| format!("line {}: {}: {}", index + 1, file.display(), e))?
+        } else if !input.is_empty() {
+            format!("{}\n", input).into_bytes()
+        } else {
+            Vec::new()
+        };
+
+        if benchmarks.iter().any(|benchmark| benchmark.name == name) {
+            return Err(format!("line {}: {} is listed twice", index + 1, name));
+        }
+
+        benchmarks.push(Benchmark { name, program, input, input_file });
+    }
+
+    Ok(benchmarks)
+}
+
+/// The first word of some text, and the text after it
+fn split_field(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim_start()),
+        None => (text, "")
+    }
+}
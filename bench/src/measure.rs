@@ -0,0 +1,85 @@
+use std::{
+    io::Write,
+    process::{Child, Command, Stdio},
+    thread,
+    time::Instant
+};
+
+
+/// How long one run of a program took, and the most memory it used
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Run {
+    pub seconds: f64,
+
+    /// In bytes, where the platform reports it
+    pub peak_rss: Option<u64>,
+    pub exit_code: i32
+}
+
+
+/// Run a command to the end with some input, discarding its output
+pub fn measure(command: &mut Command, input: &[u8]) -> Result<Run, String> {
+    let start = Instant::now();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("could not run {:?}: {}", command, e))?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+
+    let (exit_code, peak_rss) = wait(&mut child)?;
+    let seconds = start.elapsed().as_secs_f64();
+    let _ = writer.join();
+
+    Ok(Run { seconds, peak_rss, exit_code })
+}
+
+
+/// Wait for a child with `wait4`, which returns the same usage `getrusage`
+/// does, for only that child
+#[cfg(target_os = "linux")]
+fn wait(child: &mut Child) -> Result<(i32, Option<u64>), String> {
+    use std::io;
+
+    /// `struct rusage`, of which only the peak resident set size is read
+    #[repr(C)]
+    struct Usage {
+        user_time: [i64; 2],
+        system_time: [i64; 2],
+
+        /// In kilobytes
+        max_rss: i64,
+        rest: [i64; 13]
+    }
+
+    extern "C" {
+        fn wait4(pid: i32, status: *mut i32, options: i32, usage: *mut Usage) -> i32;
+    }
+
+    let mut status = 0;
+    let mut usage = Usage { user_time: [0; 2], system_time: [0; 2], max_rss: 0, rest: [0; 13] };
+
+    if unsafe { wait4(child.id() as i32, &mut status, 0, &mut usage) } < 0 {
+        return Err(io::Error::last_os_error().to_string());
+    }
+
+    // a program killed by a signal exits with 128 and the signal, as in a shell
+    let exit_code = match status & 0x7f {
+        0 => (status >> 8) & 0xff,
+        signal => 128 + signal
+    };
+
+    Ok((exit_code, Some(usage.max_rss as u64 * 1024)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn wait(child: &mut Child) -> Result<(i32, Option<u64>), String> {
+    let status = child.wait().map_err(|e| e.to_string())?;
+    Ok((status.code().unwrap_or(-1), None))
+}
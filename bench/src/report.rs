@@ -0,0 +1,127 @@
+use std::io::{self, Write};
+
+use serde_json::{self, Value};
+
+
+/// The version of the results `Report::write` produces
+const VERSION: u32 = 1;
+
+
+/// The runs of one program with one backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub program: String,
+    pub backend: String,
+
+    /// The seconds each run took, after the warmup
+    pub times: Vec<f64>,
+
+    /// The most memory any run used, in bytes
+    pub peak_rss: Option<u64>
+}
+
+/// Everything measured in one invocation of `bench`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// The commit the programs were measured at, if known
+    pub commit: Option<String>,
+
+    /// How many runs of each program were left out before measuring
+    pub warmup: usize,
+    pub measurements: Vec<Measurement>
+}
+
+
+impl Measurement {
+    pub fn mean(&self) -> f64 {
+        self.times.iter().sum::<f64>() / self.times.len() as f64
+    }
+
+    pub fn median(&self) -> f64 {
+        let mut times = self.times.clone();
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let middle = times.len() / 2;
+        if times.len().is_multiple_of(2) {
+            (times[middle - 1] + times[middle]) / 2.0
+        } else {
+            times[middle]
+        }
+    }
+
+    /// The sample standard deviation, 0 for a single run
+    pub fn stddev(&self) -> f64 {
+        if self.times.len() < 2 {
+            return 0.0;
+        }
+
+        let mean = self.mean();
+        let squares: f64 = self.times.iter().map(|time| (time - mean) * (time - mean)).sum();
+        (squares / (self.times.len() - 1) as f64).sqrt()
+    }
+}
+
+
+impl Report {
+    /// Read results written by `write`
+    pub fn read(text: &str) -> Result<Report, String> {
+        let json: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+
+        let version = json.get("version").and_then(Value::as_u64);
+        if version != Some(VERSION as u64) {
+            return Err(format!("results version {} is not supported, only {}",
+                               version.map_or("none".to_owned(), |version| version.to_string()), VERSION));
+        }
+
+        let invalid = |field: &str| format!("invalid or missing `{}`", field);
+        let commit = json.get("commit").and_then(Value::as_str).map(str::to_owned);
+        let warmup = json.get("warmup").and_then(Value::as_u64).ok_or_else(|| invalid("warmup"))? as usize;
+
+        let mut measurements = Vec::new();
+        for result in json.get("results").and_then(Value::as_array).ok_or_else(|| invalid("results"))? {
+            let text = |field: &str| result.get(field).and_then(Value::as_str).map(str::to_owned).ok_or_else(|| invalid(field));
+            let times = result.get("times").and_then(Value::as_array)
+                .and_then(|times| times.iter().map(Value::as_f64).collect::<Option<Vec<f64>>>())
+                .filter(|times| !times.is_empty())
+                .ok_or_else(|| invalid("times"))?;
+
+            measurements.push(Measurement {
+                program: text("program")?,
+                backend: text("backend")?,
+                times,
+                peak_rss: result.get("peak_rss").and_then(Value::as_u64)
+            });
+        }
+
+        Ok(Report { commit, warmup, measurements })
+    }
+
+    /// The measurement of a program with a backend
+    pub fn find(&self, program: &str, backend: &str) -> Option<&Measurement> {
+        self.measurements.iter().find(|measurement| measurement.program == program && measurement.backend == backend)
+    }
+
+    /// Write the report as JSON, with the statistics of every measurement next
+    /// to the times they came from
+    pub fn write(&self, output: &mut dyn Write) -> io::Result<()> {
+        let results: Vec<Value> = self.measurements.iter().map(|measurement| json!({
+            "program": measurement.program,
+            "backend": measurement.backend,
+            "mean": measurement.mean(),
+            "median": measurement.median(),
+            "stddev": measurement.stddev(),
+            "peak_rss": measurement.peak_rss,
+            "times": measurement.times
+        })).collect();
+
+        let report = json!({
+            "version": VERSION,
+            "commit": self.commit,
+            "warmup": self.warmup,
+            "results": results
+        });
+
+        serde_json::to_writer_pretty(&mut *output, &report)?;
+        writeln!(output)
+    }
+}
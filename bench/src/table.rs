@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+
+use manifest::Benchmark;
+use report::Report;
+
+
+/// The lines around the table in a README
+pub const START: &str = "<!-- benchmarks -->";
+pub const END: &str = "<!-- /benchmarks -->";
+
+
+/// A Markdown table of the median time of every program with every backend,
+/// read from the results file at `results`. The files and inputs of the
+/// programs are taken from the manifest, with the files relative to
+/// `directory`.
+pub fn table(report: &Report, results: &Path, benchmarks: &[Benchmark], directory: &Path) -> String {
+    let mut programs: Vec<&str> = Vec::new();
+    let mut backends: Vec<&str> = Vec::new();
+    for measurement in &report.measurements {
+        if !programs.contains(&measurement.program.as_str()) {
+            programs.push(&measurement.program);
+        }
+        if !backends.contains(&measurement.backend.as_str()) {
+            backends.push(&measurement.backend);
+        }
+    }
+
+    let mut rows = vec![["Benchmark", "File", "Input"].iter().map(|&title| title.to_owned())
+        .chain(backends.iter().map(|backend| format!("{} (seconds)", backend)))
+        .collect::<Vec<_>>()];
+
+    for &program in &programs {
+        let benchmark = benchmarks.iter().find(|benchmark| benchmark.name == program);
+        let file = benchmark.map_or(String::new(), |benchmark| format!("`{}`", relative(&benchmark.program, directory)));
+        let input = benchmark.map_or(String::new(), |benchmark| match benchmark.input_file {
+            Some(ref file) => format!("`{}`", relative(file, directory)),
+            None if benchmark.input.is_empty() => String::new(),
+            None => format!("`{}`", String::from_utf8_lossy(&benchmark.input).trim_end())
+        });
+
+        let times = backends.iter().map(|backend| match report.find(program, backend) {
+            Some(measurement) => format!("{:.4} ± {:.4}", measurement.median(), measurement.stddev()),
+            None => "-".to_owned()
+        });
+
+        rows.push(vec![program.to_owned(), file, input].into_iter().chain(times).collect());
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap())
+        .collect();
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells.iter().zip(&widths)
+            .map(|(cell, &width)| format!("{}{}", cell, " ".repeat(width - cell.chars().count())))
+            .collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+
+    let mut table = line(&rows[0]);
+    table += &line(&widths.iter().map(|&width| "-".repeat(width)).collect::<Vec<_>>());
+    for row in &rows[1..] {
+        table += &line(row);
+    }
+
+    let runs = report.measurements.first().map_or(0, |measurement| measurement.times.len());
+    table += &format!("\nMedians and standard deviations of {} runs after {} to warm up, from `{}`.\n",
+                      runs, report.warmup, relative(results, directory));
+
+    table
+}
+
+/// Replace the table between `START` and `END` in a README
+pub fn replace(readme: &str, table: &str) -> Result<String, String> {
+    let start = readme.find(START).ok_or_else(|| format!("no `{}` to put the table after", START))?;
+    let end = readme[start..].find(END).ok_or_else(|| format!("no `{}` after `{}`", END, START))? + start;
+
+    Ok(format!("{}{}\n{}{}", &readme[..start], START, table, &readme[end..]))
+}
+
+
+/// A path as seen from a directory, if both exist
+fn relative(path: &Path, directory: &Path) -> String {
+    let (path, directory) = match (path.canonicalize(), directory.canonicalize()) {
+        (Ok(path), Ok(directory)) => (path, directory),
+        _ => return path.display().to_string()
+    };
+
+    let common = path.components().zip(directory.components()).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in directory.components().skip(common) {
+        relative.push("..");
+    }
+    for component in path.components().skip(common) {
+        relative.push(component);
+    }
+
+    relative.display().to_string()
+}
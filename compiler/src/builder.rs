@@ -158,8 +158,8 @@ impl Builder {
     }
 
     /// Append instructions to a block
-    pub fn build_block<F>(&mut self, block: LLVMBasicBlockRef, build: F) 
-        where F: FnOnce(BlockBuilder)
+    pub fn build_block<F, T>(&mut self, block: LLVMBasicBlockRef, build: F) -> T
+        where F: FnOnce(BlockBuilder) -> T
         {
             let block_builder = unsafe { BlockBuilder::new(self, block) };
 
@@ -167,6 +167,12 @@ impl Builder {
         }
 
 
+    /// Add a value a phi node takes when entered from a block
+    pub fn add_incoming(&mut self, phi: LLVMValueRef, value: LLVMValueRef, block: LLVMBasicBlockRef) {
+        unsafe { llvm::LLVMAddIncoming(phi, [value].as_mut_ptr(), [block].as_mut_ptr(), 1) }
+    }
+
+
     /// Add a constant string
     pub fn constant_string(&mut self,
                            string: &str) -> LLVMValueRef {
@@ -199,6 +205,7 @@ pub enum Compare {
 
 
 impl<'a> BlockBuilder<'a> {
+    #[allow(mismatched_lifetime_syntaxes)]
     pub unsafe fn new(parent: &'a mut Builder, block: LLVMBasicBlockRef) -> BlockBuilder {
         let builder = llvm::LLVMCreateBuilderInContext(parent.context);
         llvm::LLVMPositionBuilderAtEnd(builder, block);
@@ -269,6 +276,10 @@ impl<'a> BlockBuilder<'a> {
         }
     }
 
+    pub fn phi(&mut self, value_type: LLVMTypeRef) -> LLVMValueRef {
+        unsafe { llvm::LLVMBuildPhi(self.builder, value_type, self.empty_str()) }
+    }
+
     pub fn load(&mut self, pointer_value: LLVMValueRef) -> LLVMValueRef {
         unsafe { llvm::LLVMBuildLoad(self.builder, pointer_value, self.empty_str()) }
     }
@@ -340,6 +351,12 @@ impl<'a> BlockBuilder<'a> {
                         target: LLVMTypeRef) -> LLVMValueRef {
         unsafe { llvm::LLVMBuildPointerCast(self.builder, value, target, self.empty_str()) }
     }
+
+    pub fn pointer_to_int(&mut self,
+                          value: LLVMValueRef,
+                          target: LLVMTypeRef) -> LLVMValueRef {
+        unsafe { llvm::LLVMBuildPtrToInt(self.builder, value, target, self.empty_str()) }
+    }
 }
 
 impl<'a> Drop for BlockBuilder<'a> {
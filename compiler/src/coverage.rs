@@ -0,0 +1,142 @@
+
+use llvm_sys::prelude::*;
+use builder::*;
+use karma_parser::*;
+
+
+/// The counters of a program compiled with `--coverage=`, which it appends
+/// to the file at exit in the format `karmai --coverage=` writes
+pub struct Counters {
+    /// Executions of every section, by sequence
+    pub sections: Vec<Vec<LLVMValueRef>>,
+
+    /// How often the skip ending a section kept and skipped the following
+    /// instruction
+    pub skips: Vec<Vec<Option<(LLVMValueRef, LLVMValueRef)>>>,
+
+    /// The function that writes the counters
+    pub write: LLVMValueRef
+}
+
+
+impl Counters {
+    /// Add a counter for every section and skip, and the function that writes
+    /// them to `path`
+    pub fn build(builder: &mut Builder, sequences: &[Sequence], path: &str) -> Counters {
+        let mut sections = Vec::new();
+        let mut skips = Vec::new();
+
+        for (sequence, instructions) in sequences.iter().enumerate() {
+            sections.push(instructions.iter().enumerate().map(|(section, _)| {
+                builder.add_global_variable(&format!("coverage_{}_{}", sequence, section), i64_value(0))
+            }).collect());
+
+            skips.push(instructions.iter().enumerate().map(|(section, instructions)| {
+                if instructions.last() == Some(&Instruction::SkipIfNotOne) {
+                    let kept = builder.add_global_variable(&format!("coverage_kept_{}_{}", sequence, section), i64_value(0));
+                    let skipped = builder.add_global_variable(&format!("coverage_skipped_{}_{}", sequence, section), i64_value(0));
+                    Some((kept, skipped))
+                } else {
+                    None
+                }
+            }).collect());
+        }
+
+        let write = builder.add_function("write_coverage", void_type(), &[]);
+        let counters = Counters { sections, skips, write };
+        counters.build_write(builder, path);
+
+        counters
+    }
+
+
+    /// Count an execution of a section
+    pub fn count_section(&self, builder: &mut BlockBuilder, sequence: usize, section: usize) {
+        increment(builder, self.sections[sequence][section], i64_value(1));
+    }
+
+    /// Count the outcome of the skip that ends a section, given whether the
+    /// popped value was one
+    pub fn count_skip(&self, builder: &mut BlockBuilder, sequence: usize, section: usize, is_one: LLVMValueRef) {
+        if let Some((kept, skipped)) = self.skips[sequence][section] {
+            let is_one = builder.zero_extend_int(is_one, i64_type());
+            let is_not_one = builder.sub(i64_value(1), is_one);
+
+            increment(builder, kept, is_one);
+            increment(builder, skipped, is_not_one);
+        }
+    }
+
+
+    /// Append a record for every section and skip of the lines to `path`,
+    /// or nothing if it cannot be opened
+    fn build_write(&self, builder: &mut Builder, path: &str) {
+        let path = builder.constant_string(&format!("{}\0", path));
+        let path = builder.add_global_variable("coverage_path", path);
+        let mode = builder.constant_string("a\0");
+        let mode = builder.add_global_variable("coverage_mode", mode);
+        let section_format = builder.constant_string("section %ld %ld %ld\n\0");
+        let section_format = builder.add_global_variable("coverage_section_format", section_format);
+        let skip_format = builder.constant_string("skip %ld %ld %ld %ld\n\0");
+        let skip_format = builder.add_global_variable("coverage_skip_format", skip_format);
+
+        let entry = builder.add_block(self.write, "entry");
+        let records = builder.add_block(self.write, "records");
+        let done = builder.add_block(self.write, "done");
+
+        let file = builder.build_block(entry, |mut b| {
+            let path = b.pointer_cast(path, i8_ptr_type());
+            let mode = b.pointer_cast(mode, i8_ptr_type());
+            let file = b.call_function("fopen", &[path, mode]);
+
+            let file_address = b.pointer_to_int(file, i64_type());
+            let failed = b.compare(file_address, Compare::Equal, i64_value(0));
+            b.conditional_branch(failed, done, records);
+
+            file
+        });
+
+        builder.build_block(records, |mut b| {
+            let section_format = b.pointer_cast(section_format, i8_ptr_type());
+            let skip_format = b.pointer_cast(skip_format, i8_ptr_type());
+
+            // the sentinels around the lines are not part of the source
+            for sequence in 1..self.sections.len() - 1 {
+                for section in 0..self.sections[sequence].len() {
+                    let location = [i64_value(sequence as i64), i64_value(section as i64)];
+
+                    let count = b.load(self.sections[sequence][section]);
+                    b.call_function("fprintf", &[file, section_format, location[0], location[1], count]);
+
+                    if let Some((kept, skipped)) = self.skips[sequence][section] {
+                        let kept = b.load(kept);
+                        let skipped = b.load(skipped);
+                        b.call_function("fprintf", &[file, skip_format, location[0], location[1], kept, skipped]);
+                    }
+                }
+            }
+
+            b.call_function("fclose", &[file]);
+            b.branch(done);
+        });
+
+        builder.build_block(done, |mut b| {
+            b.return_void();
+        });
+    }
+}
+
+
+/// Declare the C functions that write coverage files
+pub fn add_external_functions(builder: &mut Builder) {
+    builder.add_function("fopen", i8_ptr_type(), &[("", i8_ptr_type()), ("", i8_ptr_type())]);
+    builder.add_function("fclose", i32_type(), &[("", i8_ptr_type())]);
+    builder.add_function_var_arg("fprintf", i32_type(), &[("", i8_ptr_type()), ("", i8_ptr_type())]);
+}
+
+
+fn increment(builder: &mut BlockBuilder, counter: LLVMValueRef, by: LLVMValueRef) {
+    let count = builder.load(counter);
+    let count = builder.add(count, by);
+    builder.store(count, counter);
+}
@@ -2,6 +2,7 @@
 
 use llvm_sys::prelude::*;
 use builder::*;
+use karma_parser::Trap;
 use std::mem::size_of;
 
 const INITIAL_CAPACITY: i64 = 16;
@@ -39,10 +40,13 @@ pub struct Deque {
     pub remove_back: LLVMValueRef,
 
     resize: LLVMValueRef,
+
+    /// The most values the deque may hold, if limited
+    limit: Option<usize>,
 }
 
 impl Deque {
-    pub fn build(builder: &mut Builder) -> Deque {
+    pub fn build(builder: &mut Builder, limit: Option<usize>) -> Deque {
         let data = builder.add_global_variable("deque", i64_ptr_value());
 
         let back = builder.add_global_variable("deque_back", i64_value(0));
@@ -65,7 +69,8 @@ impl Deque {
             remove_front,
             remove_back,
 
-            resize
+            resize,
+            limit
         };
 
         deque.build_resize(builder);
@@ -220,6 +225,38 @@ impl Deque {
     }
 
 
+    /// Exit with the code of `Trap::DequeLimit` from `block` if the deque is
+    /// full, and continue at `next` otherwise
+    fn build_limit_check(&self,
+                         builder: &mut Builder,
+                         target_fn: LLVMValueRef,
+                         block: LLVMBasicBlockRef,
+                         next: LLVMBasicBlockRef) {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => {
+                builder.build_block(block, |mut block| {
+                    block.branch(next);
+                });
+                return;
+            }
+        };
+
+        let full = builder.add_block(target_fn, "full");
+
+        builder.build_block(block, |mut block| {
+            let length = block.load(self.length);
+            let is_full = block.compare(length, Compare::GreaterOrEqual, i64_value(limit as i64));
+            block.conditional_branch(is_full, full, next);
+        });
+
+        builder.build_block(full, |mut block| {
+            block.call_function("terminate", &[i32_value(Trap::DequeLimit.exit_code())]);
+            block.return_void();
+        });
+    }
+
+
     fn build_insert(&self, builder: &mut Builder) {
         self.build_insert_front(builder);
         self.build_insert_back(builder);
@@ -234,10 +271,13 @@ impl Deque {
     fn build_insert_front(&self, builder: &mut Builder) {
         let value = builder.get_param(self.insert_front, 0);
         let entry = builder.add_block(self.insert_front, "entry");
+        let check_capacity = builder.add_block(self.insert_front, "check_capacity");
         let exit = builder.add_block(self.insert_front, "exit");
         let grow = self.create_grow_block(builder, self.insert_front, exit);
 
-        builder.build_block(entry, |mut block| {
+        self.build_limit_check(builder, self.insert_front, entry, check_capacity);
+
+        builder.build_block(check_capacity, |mut block| {
             self.needs_grow(&mut block, grow, exit);
         });
 
@@ -255,10 +295,13 @@ impl Deque {
     fn build_insert_back(&self, builder: &mut Builder) {
         let value = builder.get_param(self.insert_back, 0);
         let entry = builder.add_block(self.insert_back, "entry");
+        let check_capacity = builder.add_block(self.insert_back, "check_capacity");
         let exit = builder.add_block(self.insert_back, "exit");
         let grow = self.create_grow_block(builder, self.insert_back, exit);
 
-        builder.build_block(entry, |mut block| {
+        self.build_limit_check(builder, self.insert_back, entry, check_capacity);
+
+        builder.build_block(check_capacity, |mut block| {
             self.needs_grow(&mut block, grow, exit);
         });
 
@@ -291,7 +334,7 @@ impl Deque {
         });
 
         builder.build_block(fail, |mut block| {
-            block.call_function("exit", &[i32_value(13)]);
+            block.call_function("terminate", &[i32_value(Trap::EmptyDeque.exit_code())]);
             block.return_value(i64_value(-1));
         });
     }
@@ -316,7 +359,7 @@ impl Deque {
         });
 
         builder.build_block(fail, |mut block| {
-            block.call_function("exit", &[i32_value(13)]);
+            block.call_function("terminate", &[i32_value(Trap::EmptyDeque.exit_code())]);
             block.return_value(i64_value(-1));
         });
     }
@@ -339,7 +382,7 @@ impl Deque {
                 on_true: LLVMBasicBlockRef,
                 on_false: LLVMBasicBlockRef) {
         let length = block.load(self.length);
-        let is_empty = block.compare(i64_value(0), Compare::Greater, length);
+        let is_empty = block.compare(i64_value(0), Compare::GreaterOrEqual, length);
         block.conditional_branch(is_empty, on_true, on_false);
     }
 
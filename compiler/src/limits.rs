@@ -0,0 +1,124 @@
+
+use llvm_sys::prelude::*;
+use builder::*;
+use karma_parser::*;
+
+
+/// The signal `alarm` sends once the time is up
+const SIGALRM: i32 = 14;
+
+
+/// The functions a program compiled with limits uses to count what it does,
+/// which exit with the code of the trap once it goes past a limit. The stack
+/// and deque check their own length.
+pub struct Checks {
+    /// Adds its argument to the instructions executed
+    count_steps: Option<LLVMValueRef>,
+
+    /// Adds its argument to the bytes printed, before they are printed
+    count_output: Option<LLVMValueRef>,
+
+    /// The handler of the alarm, and the seconds until it goes off
+    timeout: Option<(LLVMValueRef, i32)>
+}
+
+
+impl Checks {
+    /// Add the counters and functions the limits need
+    pub fn build(builder: &mut Builder, limits: &Limits) -> Checks {
+        let count_steps = limits.steps.map(|steps| {
+            build_counter(builder, "count_steps", "steps", steps, Trap::StepLimit)
+        });
+
+        let count_output = limits.output.map(|output| {
+            build_counter(builder, "count_output", "output_bytes", output, Trap::OutputLimit)
+        });
+
+        let timeout = limits.time.map(|time| {
+            // alarms go off after whole seconds
+            let seconds = time.as_secs_f64().ceil().max(1.0) as i32;
+            (builder.add_function("on_timeout", void_type(), &[("signal", i32_type())]), seconds)
+        });
+
+        Checks { count_steps, count_output, timeout }
+    }
+
+    /// Build the handler of the alarm, which writes what was printed so far
+    /// with `flush` and stops the program right away, since the signal can
+    /// arrive in the middle of anything
+    pub fn build_timeout(&self, builder: &mut Builder, flush: LLVMValueRef) {
+        if let Some((handler, _)) = self.timeout {
+            let entry = builder.add_block(handler, "entry");
+
+            builder.build_block(entry, |mut b| {
+                b.call(flush, &[]);
+                b.call_function("_exit", &[i32_value(Trap::TimeLimit.exit_code())]);
+                b.return_void();
+            });
+        }
+    }
+
+    /// Start the clock, at the start of the program
+    pub fn build_start(&self, builder: &mut BlockBuilder) {
+        if let Some((handler, seconds)) = self.timeout {
+            let handler = builder.pointer_cast(handler, i8_ptr_type());
+            builder.call_function("signal", &[i32_value(SIGALRM), handler]);
+            builder.call_function("alarm", &[i32_value(seconds)]);
+        }
+    }
+
+    /// Count an instruction, before it runs
+    pub fn count_step(&self, builder: &mut BlockBuilder) {
+        if let Some(count_steps) = self.count_steps {
+            builder.call(count_steps, &[i64_value(1)]);
+        }
+    }
+
+    /// Count bytes about to be printed
+    pub fn count_output(&self, builder: &mut BlockBuilder, bytes: LLVMValueRef) {
+        if let Some(count_output) = self.count_output {
+            builder.call(count_output, &[bytes]);
+        }
+    }
+}
+
+
+/// Declare the C functions that enforce limits
+pub fn add_external_functions(builder: &mut Builder) {
+    builder.add_function("signal", i8_ptr_type(), &[("", i32_type()), ("", i8_ptr_type())]);
+    builder.add_function("alarm", i32_type(), &[("", i32_type())]);
+    builder.add_function("_exit", void_type(), &[("", i32_type())]);
+}
+
+
+/// A function that adds to a global counter, and exits with the code of the
+/// trap if the total goes past the limit
+fn build_counter(builder: &mut Builder, name: &str, counter: &str, limit: u64, trap: Trap) -> LLVMValueRef {
+    let counter = builder.add_global_variable(counter, i64_value(0));
+    let function = builder.add_function(name, void_type(), &[("amount", i64_type())]);
+    let amount = builder.get_param(function, 0);
+
+    let entry = builder.add_block(function, "entry");
+    let within = builder.add_block(function, "within");
+    let exceeded = builder.add_block(function, "exceeded");
+
+    builder.build_block(entry, |mut b| {
+        let total = b.load(counter);
+        let total = b.add(total, amount);
+        b.store(total, counter);
+
+        let is_over = b.compare(total, Compare::Greater, i64_value(limit.min(i64::MAX as u64) as i64));
+        b.conditional_branch(is_over, exceeded, within);
+    });
+
+    builder.build_block(within, |mut b| {
+        b.return_void();
+    });
+
+    builder.build_block(exceeded, |mut b| {
+        b.call_function("terminate", &[i32_value(trap.exit_code())]);
+        b.return_void();
+    });
+
+    function
+}
@@ -15,39 +15,149 @@ use stack::Stack;
 mod deque;
 use deque::Deque;
 
+mod coverage;
+use coverage::Counters;
+
+mod limits;
+use limits::Checks;
+
+mod output;
+use output::Output;
+
 use std::env;
 use std::process;
 
 mod sequence_builder;
 use sequence_builder::*;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// The options given on the command line
+struct Arguments {
+    path: String,
+    passes: optimize::PassManager,
+    dialect: Dialect,
+    eof: Eof,
+    limits: Limits,
 
-    let code = if args.len() > 1 {
-        let code = parse_file(&args[1]).unwrap();
-        optimize::all(code)
-    } else {
-        println!("Error: no file specified in arguments!");
-        process::exit(1);
+    /// Print what each optimization pass changed
+    statistics: bool,
+
+    /// Check that no optimization pass changes what the program does
+    validate: bool,
+
+    /// The file the program appends the sections it covered to
+    coverage: Option<String>,
+}
+
+
+fn main() {
+    let Arguments { path, passes, dialect, eof, limits, statistics, validate, coverage } = match parse_arguments() {
+        Ok(arguments) => arguments,
+        Err(message) => {
+            println!("Error: {}", message);
+            process::exit(1);
+        }
     };
 
-    let mut builder = Builder::new();
+    let code = parse_file_with_dialect(&path, dialect).unwrap();
 
-    add_external_functions(&mut builder);
-    add_puti64(&mut builder);
+    if validate {
+        if let Err(counterexample) = passes.validate(&code) {
+            eprintln!("{}", counterexample);
+            process::exit(1);
+        }
+    }
 
-    let stack = Stack::build(&mut builder);
-    let deque = Deque::build(&mut builder);
+    let (code, pass_statistics) = passes.run(code);
 
-    create_main(&mut builder, &stack, &deque, code);
+    if statistics {
+        for pass in pass_statistics {
+            eprintln!("{}", pass);
+        }
+    }
 
+    match compile(code, eof, coverage.as_deref(), &limits) {
+        Some(ir) => println!("{}", ir),
+        None => process::exit(1)
+    }
+}
+
+
+fn parse_arguments() -> std::result::Result<Arguments, String> {
+    let mut path = None;
+    let mut passes = optimize::PassManager::default();
+    let mut dialect = Dialect::default();
+    let mut eof = Eof::default();
+    let mut limits = Limits::default();
+    let mut statistics = false;
+    let mut validate = false;
+    let mut coverage = None;
+
+    for argument in env::args().skip(1) {
+        if argument == "--stats" {
+            statistics = true;
+        } else if argument == "--validate" {
+            validate = true;
+        } else if let Some(name) = argument.strip_prefix("--dialect=") {
+            dialect = Dialect::from_name(name).map_err(|e| format!("{:?}", e))?;
+        } else if let Some(name) = argument.strip_prefix("--eof=") {
+            eof = Eof::from_name(name).map_err(|e| format!("{:?}", e))?;
+        } else if let Some(file) = argument.strip_prefix("--coverage=") {
+            coverage = Some(file.to_owned());
+        } else if limits.parse_flag(&argument).map_err(|e| format!("{:?}", e))?
+            || passes.parse_flag(&argument).map_err(|e| format!("{:?}", e))? {
+            continue;
+        } else if argument.starts_with('-') {
+            return Err(format!("Unknown option: {}", argument));
+        } else {
+            path = Some(argument);
+        }
+    }
+
+    // coverage is recorded for the sections as they are written
+    if coverage.is_some() {
+        passes = optimize::PassManager::new(0);
+    }
+
+    match path {
+        Some(path) => Ok(Arguments { path, passes, dialect, eof, limits, statistics, validate, coverage }),
+        None => Err("no file specified in arguments!".to_owned())
+    }
+}
+
+
+/// Generate LLVM IR for a program, `None` if the generated module is invalid.
+/// With a coverage file, the program appends what it covered to it at exit.
+/// Going past a limit exits with the code of its trap.
+fn compile(sequences: Vec<Sequence>, eof: Eof, coverage: Option<&str>, limits: &Limits) -> Option<String> {
+    let mut builder = Builder::new();
+
+    add_external_functions(&mut builder);
+    coverage::add_external_functions(&mut builder);
+    limits::add_external_functions(&mut builder);
+    output::add_external_functions(&mut builder);
+
+    let checks = Checks::build(&mut builder, limits);
+    let output = Output::build(&mut builder, &checks);
+    checks.build_timeout(&mut builder, output.flush);
+    add_read(&mut builder, eof, &output);
+    add_checked_division(&mut builder, "divide", Operator::Div);
+    add_checked_division(&mut builder, "remainder", Operator::Mod);
+
+    let stack = Stack::build(&mut builder, limits.stack);
+    let deque = Deque::build(&mut builder, limits.deque);
+
+    for operator in counted_loop_operators(&sequences) {
+        add_counted_loop(&mut builder, &operator);
+    }
+
+    let counters = coverage.map(|path| Counters::build(&mut builder, &sequences, path));
+    add_terminate(&mut builder, &output, counters.as_ref());
+    create_main(&mut builder, &stack, &deque, &output, sequences, counters.as_ref(), &checks);
 
     if builder.is_working() {
-        let ir = builder.as_string();
-        println!("{}", ir);
+        Some(builder.as_string())
     } else {
-        process::exit(1);
+        None
     }
 }
 
@@ -67,27 +177,206 @@ fn add_external_functions(builder: &mut Builder) {
     );
 
     builder.add_function("exit", void_type(), &[("", i32_type())]);
+    builder.add_function("terminate", void_type(), &[("code", i32_type())]);
 
     builder.add_function("getchar", i32_type(), &[]);
-    builder.add_function("putchar", i32_type(), &[("", i32_type())]);
-    builder.add_function_var_arg("printf", i32_type(), &[("", i8_ptr_type())]);
 }
 
-fn add_puti64(builder: &mut Builder) {
-    let puti64 = builder.add_function("puti64", void_type(), &[("value", i64_type())]);
-    let format = builder.constant_string("%ld\0");
-    let format = builder.add_global_variable("format", format);
-    let value = builder.get_param(puti64, 0);
+/// Read a character, or do what `eof` says once there are none left
+fn add_read(builder: &mut Builder, eof: Eof, output: &Output) {
+    let read = builder.add_function("read", i64_type(), &[]);
+
+    let entry = builder.add_block(read, "entry");
+    let flush = builder.add_block(read, "flush");
+    let get = builder.add_block(read, "get");
+    let character = builder.add_block(read, "character");
+    let end = builder.add_block(read, "end");
+
+    builder.build_block(entry, |mut b| {
+        output.build_before_read(&mut b, flush, get);
+    });
+
+    builder.build_block(flush, |mut b| {
+        b.call(output.flush, &[]);
+        b.branch(get);
+    });
+
+    let value = builder.build_block(get, |mut b| {
+        let value = b.call_function("getchar", &[]);
+        let value = b.cast_int(value, i64_type());
+        let is_end = b.compare(value, Compare::Equal, i64_value(-1));
+        b.conditional_branch(is_end, end, character);
+        value
+    });
+
+    builder.build_block(character, |mut b| {
+        b.return_value(value);
+    });
+
+    builder.build_block(end, |mut b| {
+        match eof {
+            Eof::Error => {
+                b.call_function("terminate", &[i32_value(Trap::EndOfInput.exit_code())]);
+                b.return_value(i64_value(0));
+            }
+
+            Eof::Value(value) => {
+                b.return_value(i64_value(value));
+            }
+        }
+    });
+}
+
+/// Exit with a code, writing what was printed and the coverage first. Every
+/// exit of the program goes through here rather than `atexit`, whose handlers
+/// `lli` never runs.
+fn add_terminate(builder: &mut Builder, output: &Output, coverage: Option<&Counters>) {
+    let terminate = builder.get_named_function("terminate");
+    let code = builder.get_param(terminate, 0);
+    let entry = builder.add_block(terminate, "entry");
 
-    let entry = builder.add_block(puti64, "entry");
     builder.build_block(entry, |mut b| {
-        let format = b.pointer_cast(format, i8_ptr_type());
-        b.call_function("printf", &[format, value]);
+        b.call(output.flush, &[]);
+
+        if let Some(counters) = coverage {
+            b.call(counters.write, &[]);
+        }
+
+        b.call_function("exit", &[code]);
+        b.return_void();
+    });
+}
+
+/// Division that exits with the code of the trap instead of being undefined
+fn add_checked_division(builder: &mut Builder, name: &str, operator: Operator) {
+    let function = builder.add_function(name, i64_type(), &[("lhs", i64_type()), ("rhs", i64_type())]);
+    let lhs = builder.get_param(function, 0);
+    let rhs = builder.get_param(function, 1);
+
+    let entry = builder.add_block(function, "entry");
+    let check_overflow = builder.add_block(function, "check_overflow");
+    let divide = builder.add_block(function, "divide");
+    let by_zero = builder.add_block(function, "by_zero");
+    let overflow = builder.add_block(function, "overflow");
+
+    builder.build_block(entry, |mut b| {
+        let is_zero = b.compare(rhs, Compare::Equal, i64_value(0));
+        b.conditional_branch(is_zero, by_zero, check_overflow);
+    });
+
+    builder.build_block(check_overflow, |mut b| {
+        let is_min = b.compare(lhs, Compare::Equal, i64_value(i64::MIN));
+        let is_minus_one = b.compare(rhs, Compare::Equal, i64_value(-1));
+        let overflows = b.bit_and(is_min, is_minus_one);
+        b.conditional_branch(overflows, overflow, divide);
+    });
+
+    builder.build_block(divide, |mut b| {
+        let result = match operator {
+            Operator::Div => b.div(lhs, rhs),
+            _ => b.modulo(lhs, rhs),
+        };
+
+        b.return_value(result);
+    });
+
+    builder.build_block(by_zero, |mut b| {
+        b.call_function("terminate", &[i32_value(Trap::DivisionByZero.exit_code())]);
+        b.return_value(i64_value(0));
+    });
+
+    builder.build_block(overflow, |mut b| {
+        b.call_function("terminate", &[i32_value(Trap::DivisionOverflow.exit_code())]);
+        b.return_value(i64_value(0));
+    });
+}
+
+/// The operators of all `CountedLoop`s in a program
+fn counted_loop_operators(sequences: &[Sequence]) -> Vec<Operator> {
+    let mut operators = Vec::new();
+
+    for instruction in sequences.iter().flat_map(|sequence| sequence.iter()).flat_map(|section| section.iter()) {
+        if let Instruction::CountedLoop(_, ref operator) = *instruction {
+            if !operators.contains(operator) {
+                operators.push(operator.clone());
+            }
+        }
+    }
+
+    operators
+}
+
+/// Run the iterations of a `CountedLoop` that do not exit it, taking the step
+/// as a parameter
+fn add_counted_loop(builder: &mut Builder, operator: &Operator) {
+    let function = builder.add_function(&counted_loop_function(operator), void_type(), &[("step", i64_type())]);
+    let step = builder.get_param(function, 0);
+
+    let entry = builder.add_block(function, "entry");
+    let skip = builder.add_block(function, "skip");
+    let start = builder.add_block(function, "start");
+    let check = builder.add_block(function, "check");
+    let iterate = builder.add_block(function, "iterate");
+    let done = builder.add_block(function, "done");
+
+    let (counter, bound) = builder.build_block(entry, |mut b| {
+        let counter = b.call_function("remove_front", &[]);
+        let bound = b.call_function("remove_front", &[]);
+
+        // the head exits without touching the stack
+        let first = b.add(counter, step);
+        let exits = b.compare(first, Compare::Greater, bound);
+        b.conditional_branch(exits, skip, start);
+
+        (counter, bound)
+    });
+
+    builder.build_block(skip, |mut b| {
+        b.call_function("insert_front", &[bound]);
+        b.call_function("insert_front", &[counter]);
+        b.return_void();
+    });
+
+    let top = builder.build_block(start, |mut b| {
+        let top = b.call_function("pop", &[]);
+        b.branch(check);
+
+        top
+    });
+
+    let (top_phi, counter_phi, next) = builder.build_block(check, |mut b| {
+        let top_phi = b.phi(i64_type());
+        let counter_phi = b.phi(i64_type());
+
+        let next = b.add(counter_phi, step);
+        let exits = b.compare(next, Compare::Greater, bound);
+        b.conditional_branch(exits, done, iterate);
+
+        (top_phi, counter_phi, next)
+    });
+
+    let next_top = builder.build_block(iterate, |mut b| {
+        let next_top = build_operation(&mut b, next, operator, top_phi);
+        b.branch(check);
+
+        next_top
+    });
+
+    builder.add_incoming(top_phi, top, start);
+    builder.add_incoming(top_phi, next_top, iterate);
+    builder.add_incoming(counter_phi, counter, start);
+    builder.add_incoming(counter_phi, next, iterate);
+
+    builder.build_block(done, |mut b| {
+        b.call_function("insert_front", &[bound]);
+        b.call_function("insert_front", &[counter_phi]);
+        b.call_function("push", &[top_phi]);
         b.return_void();
     });
 }
 
-fn create_main(builder: &mut Builder, stack: &Stack, deque: &Deque, sequences: Vec<Sequence>) {
+#[allow(clippy::unnecessary_mut_passed)]
+fn create_main(builder: &mut Builder, stack: &Stack, deque: &Deque, output: &Output, sequences: Vec<Sequence>, coverage: Option<&Counters>, checks: &Checks) {
     let main = builder.add_function("main", i32_type(), &mut []);
 
     let init_stack = builder.add_block(main, "init_stack");
@@ -98,6 +387,9 @@ fn create_main(builder: &mut Builder, stack: &Stack, deque: &Deque, sequences: V
     let panic = builder.add_block(main, "panic");
 
     builder.build_block(init_stack, |mut b| {
+        checks.build_start(&mut b);
+
+        output.build_constructor(&mut b);
         stack.build_constructor(&mut b);
         b.branch(init_deque);
     });
@@ -108,14 +400,16 @@ fn create_main(builder: &mut Builder, stack: &Stack, deque: &Deque, sequences: V
     });
 
     let sequence_blocks = SequenceBuilder::new(builder, main, panic, exit)
+        .with_coverage(coverage)
+        .with_checks(checks)
         .build(&sequences);
 
     builder.build_block(entry, |mut b| {
-        b.store(i64_value(0), sequence_blocks[1].jump_table.next_section);
-        b.branch(sequence_blocks[1].jump_table.block);
+        b.branch(sequence_blocks[1].sections[0]);
     });
 
     builder.build_block(exit, |mut b| {
+        b.call_function("terminate", &[i32_value(0)]);
         b.return_value(i32_value(0));
     });
     
@@ -125,3 +419,159 @@ fn create_main(builder: &mut Builder, stack: &Stack, deque: &Deque, sequences: V
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn compile_str(source: &str) -> String {
+        let sequences = optimize::all(parse_str(source).unwrap());
+        compile(sequences, Eof::default(), None, &Limits::default()).unwrap()
+    }
+
+    /// Run the IR of a program with `lli` (or `$LLI`) and no input, returning
+    /// its exit code and output
+    fn run_str(name: &str, source: &str) -> (Option<i32>, Vec<u8>) {
+        let path = env::temp_dir().join(format!("karmac-{}-{}.ll", process::id(), name));
+        fs::write(&path, compile_str(source)).unwrap();
+
+        let lli = env::var("LLI").unwrap_or_else(|_| "lli".to_owned());
+        let result = process::Command::new(lli).arg(&path).stdin(process::Stdio::null()).output().unwrap();
+        fs::remove_file(&path).unwrap();
+        (result.status.code(), result.stdout)
+    }
+
+    fn compile_example(name: &str) -> String {
+        let sequences = optimize::all(parse_file(format!("../karma/{}.kar", name)).unwrap());
+        compile(sequences, Eof::default(), None, &Limits::default()).unwrap()
+    }
+
+
+    #[test]
+    fn check_division_at_runtime() {
+        let ir = compile_str("?5/;");
+        assert!(ir.contains("call i64 @divide"));
+
+        let ir = compile_str("05/;");
+        assert!(ir.contains("call void @terminate(i32 15)"));
+        assert!(!ir.contains("call i64 @divide"));
+    }
+
+    #[test]
+    fn handle_end_of_input() {
+        let sequences = parse_str("?;").unwrap();
+
+        let ir = compile(sequences.clone(), Eof::Error, None, &Limits::default()).unwrap();
+        assert!(ir.contains("call void @terminate(i32 17)"));
+
+        let ir = compile(sequences, Eof::Value(0), None, &Limits::default()).unwrap();
+        assert!(ir.contains("ret i64 0"));
+        assert!(ir.contains("call i64 @read()"));
+    }
+
+    #[test]
+    fn trap_on_empty_deque() {
+        // `{` takes from the front of the deque and `]` from its back
+        assert_eq!(run_str("front", "{;"), (Some(13), vec![]));
+        assert_eq!(run_str("back", "];"), (Some(13), vec![]));
+        assert_eq!(run_str("emptied", "1}{{;"), (Some(13), vec![]));
+        assert_eq!(run_str("emptied_back", "1[]];"), (Some(13), vec![]));
+        assert_eq!(run_str("kept", "1}{;"), (Some(0), b"1".to_vec()));
+    }
+
+    #[test]
+    fn count_sections_and_skips() {
+        let ir = compile(parse_str("1@2;\n").unwrap(), Eof::default(), Some("karma.cov"), &Limits::default()).unwrap();
+
+        assert!(ir.contains("@coverage_1_0 = global i64 0"));
+        assert!(ir.contains("@coverage_kept_1_0 = global i64 0"));
+        assert!(ir.contains("@coverage_skipped_1_0 = global i64 0"));
+        assert!(ir.contains("karma.cov\\00"));
+        assert!(ir.contains("define void @terminate(i32 %code)"));
+        assert!(ir.contains("call void @write_coverage()\n  call void @exit(i32 %code)"));
+        assert!(!compile_str("1@2;").contains("coverage"));
+    }
+
+    #[test]
+    fn exit_at_limits() {
+        let mut limits = Limits::default();
+        for flag in &["--max-steps=100", "--max-time=0.5", "--max-stack=10", "--max-deque=20", "--max-output=30"] {
+            assert!(limits.parse_flag(flag).unwrap());
+        }
+
+        let ir = compile(parse_str("1;<").unwrap(), Eof::default(), None, &limits).unwrap();
+        assert!(ir.contains("call void @count_steps(i64 1)") && !ir.contains("@count_steps(i64 3)"));
+        assert!(ir.contains("call void @terminate(i32 18)"));
+        assert!(ir.contains("call void @flush()\n  call void @_exit(i32 19)"));
+        assert!(ir.contains("call i32 @alarm(i32 1)"));
+        assert!(ir.contains("call void @terminate(i32 20)"));
+        assert!(ir.contains("call void @terminate(i32 21)"));
+        assert!(ir.contains("call i32 (i8*, i64, i8*, ...) @snprintf"));
+        assert!(ir.contains("call void @terminate(i32 22)"));
+
+        let ir = compile_str("1;<");
+        assert!(!ir.contains("count_steps") && !ir.contains("call i32 @alarm(") && !ir.contains("terminate(i32 20)"));
+    }
+
+    #[test]
+    fn buffer_output() {
+        let ir = compile_str("?;9:\n");
+        assert!(ir.contains("call void @puti64(i64"));
+        assert!(ir.contains("call void @put_byte(i64 9)"));
+        assert!(ir.contains("call i64 @write(i32 1"));
+        assert!(ir.contains("call i32 @isatty(i32 0)"));
+        assert!(ir.contains("define void @terminate(i32 %code) {\nentry:\n  call void @flush()\n  call void @exit(i32 %code)"));
+        assert!(!ir.contains("@putchar") && !ir.contains("@printf") && !ir.contains("@atexit"));
+    }
+
+    #[test]
+    fn run_counted_loop_natively() {
+        let ir = compile_example("sum");
+        assert!(ir.contains("define void @counted_loop_add(i64 %step)"));
+        assert!(ir.contains("call void @counted_loop_add(i64 1)"));
+    }
+
+    #[test]
+    fn compile_examples_to_golden_ir() {
+        let mut examples: Vec<_> = fs::read_dir("../karma").unwrap().map(|entry| entry.unwrap().path()).collect();
+        examples.sort();
+        for example in examples.iter().filter(|path| path.extension().is_some_and(|e| e == "kar")) {
+            if example.with_extension("ll").exists() {
+                assert_golden_ir(example);
+            }
+        }
+    }
+
+    #[test]
+    fn branch_directly_to_known_sections() {
+        // a restart always lands in the first section of its sequence
+        assert_golden_ir(Path::new("snapshots/restart.kar"));
+        // the second line is either entered from the start or resumed after its ','
+        assert_golden_ir(Path::new("snapshots/resume_at_unknown_section.kar"));
+    }
+
+    /// Compare the IR compiled from a program to the `.ll` file next to it, or
+    /// write the file instead when `KARMAC_BLESS` is set
+    fn assert_golden_ir(source: &Path) {
+        let sequences = optimize::all(parse_file(source).unwrap());
+        let actual = compile(sequences, Eof::default(), None, &Limits::default()).unwrap();
+        let golden = source.with_extension("ll");
+
+        if env::var_os("KARMAC_BLESS").is_some() {
+            fs::write(&golden, &actual).unwrap();
+            return;
+        }
+
+        let expected = fs::read_to_string(&golden).unwrap();
+        let mut expected_lines = expected.lines();
+        for (number, line) in actual.lines().enumerate() {
+            let expected_line = expected_lines.next();
+            assert!(expected_line == Some(line), "{} differs from {} at line {}:\n  expected: {}\n  actual:   {}\nrun with KARMAC_BLESS=1 to update it",
+                    source.display(), golden.display(), number + 1, expected_line.unwrap_or("<end of file>"), line);
+        }
+        assert!(expected_lines.next().is_none(), "{} is longer than the IR compiled from {}, run with KARMAC_BLESS=1 to update it",
+                golden.display(), source.display());
+    }
+}
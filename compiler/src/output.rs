@@ -0,0 +1,226 @@
+
+use llvm_sys::prelude::*;
+use builder::*;
+use limits::Checks;
+
+
+/// The bytes collected before they are written
+const BUFFER_SIZE: i64 = 1 << 16;
+
+/// The most bytes a number takes, with its sign and the terminating null
+const NUMBER_SIZE: i64 = 21;
+
+const STDIN: i32 = 0;
+const STDOUT: i32 = 1;
+
+
+/// What a program prints, collected in a buffer and written to standard
+/// output with `write` once it is full, at exit, after every line when
+/// standard output is a terminal, and before reading input when standard
+/// input is a terminal
+pub struct Output {
+    pub buffer: LLVMValueRef,
+    pub length: LLVMValueRef,
+
+    /// How much of the buffer a flush has written so far, so that a flush
+    /// interrupted by a signal can be finished by the handler
+    pub written: LLVMValueRef,
+
+    /// Whether to flush after a newline
+    pub flush_lines: LLVMValueRef,
+
+    /// Whether to flush before reading
+    pub flush_before_read: LLVMValueRef,
+
+    pub flush: LLVMValueRef,
+
+    /// Print the byte of its argument
+    pub put_byte: LLVMValueRef,
+
+    /// Print its argument in decimal
+    pub put_number: LLVMValueRef,
+}
+
+
+impl Output {
+    pub fn build(builder: &mut Builder, checks: &Checks) -> Output {
+        let output = Output {
+            buffer: builder.add_global_variable("output", i64_ptr_value()),
+            length: builder.add_global_variable("output_length", i64_value(0)),
+            written: builder.add_global_variable("output_written", i64_value(0)),
+            flush_lines: builder.add_global_variable("flush_lines", i1_value(false)),
+            flush_before_read: builder.add_global_variable("flush_before_read", i1_value(false)),
+
+            flush: builder.add_function("flush", void_type(), &[]),
+            put_byte: builder.add_function("put_byte", void_type(), &[("value", i64_type())]),
+            put_number: builder.add_function("puti64", void_type(), &[("value", i64_type())]),
+        };
+
+        output.build_flush(builder);
+        output.build_put_byte(builder);
+        output.build_put_number(builder, checks);
+
+        output
+    }
+
+
+    /// Write everything in the buffer, retrying after partial writes. Only
+    /// `write` is called, so the handler of a signal may flush as well.
+    fn build_flush(&self, builder: &mut Builder) {
+        let entry = builder.add_block(self.flush, "entry");
+        let write = builder.add_block(self.flush, "write");
+        let empty = builder.add_block(self.flush, "empty");
+        let done = builder.add_block(self.flush, "done");
+
+        let length = builder.build_block(entry, |mut b| {
+            let length = b.load(self.length);
+            let written = b.load(self.written);
+            let is_written = b.compare(written, Compare::GreaterOrEqual, length);
+            b.conditional_branch(is_written, empty, write);
+            length
+        });
+
+        builder.build_block(write, |mut b| {
+            let written = b.load(self.written);
+
+            let data = b.load(self.buffer);
+            let data = b.pointer_cast(data, i8_ptr_type());
+            let start = b.get_element_offset(data, written);
+            let remaining = b.sub(length, written);
+
+            // an error leaves the rest unwritten, as stdio does
+            let count = b.call_function("write", &[i32_value(STDOUT), start, remaining]);
+            let failed = b.compare(i64_value(1), Compare::Greater, count);
+            let next_written = b.add(written, count);
+            b.store(next_written, self.written);
+
+            let is_done = b.compare(next_written, Compare::GreaterOrEqual, length);
+            let stop = b.bit_or(failed, is_done);
+            b.conditional_branch(stop, empty, write);
+        });
+
+        builder.build_block(empty, |mut b| {
+            b.store(i64_value(0), self.length);
+            b.store(i64_value(0), self.written);
+            b.branch(done);
+        });
+
+        builder.build_block(done, |mut b| {
+            b.return_void();
+        });
+    }
+
+    fn build_put_byte(&self, builder: &mut Builder) {
+        let value = builder.get_param(self.put_byte, 0);
+
+        let entry = builder.add_block(self.put_byte, "entry");
+        let make_room = builder.add_block(self.put_byte, "make_room");
+        let append = builder.add_block(self.put_byte, "append");
+        let end_line = builder.add_block(self.put_byte, "end_line");
+        let done = builder.add_block(self.put_byte, "done");
+
+        builder.build_block(entry, |mut b| {
+            let length = b.load(self.length);
+            let is_full = b.compare(length, Compare::GreaterOrEqual, i64_value(BUFFER_SIZE));
+            b.conditional_branch(is_full, make_room, append);
+        });
+
+        builder.build_block(make_room, |mut b| {
+            b.call(self.flush, &[]);
+            b.branch(append);
+        });
+
+        builder.build_block(append, |mut b| {
+            let length = b.load(self.length);
+            let data = b.load(self.buffer);
+            let data = b.pointer_cast(data, i8_ptr_type());
+            let element = b.get_element_offset(data, length);
+            let byte = b.cast_int(value, i8_type());
+            b.store(byte, element);
+
+            let length = b.add(length, i64_value(1));
+            b.store(length, self.length);
+
+            let is_newline = b.compare(value, Compare::Equal, i64_value(b'\n' as i64));
+            let flush_lines = b.load(self.flush_lines);
+            let flushes = b.bit_and(is_newline, flush_lines);
+            b.conditional_branch(flushes, end_line, done);
+        });
+
+        builder.build_block(end_line, |mut b| {
+            b.call(self.flush, &[]);
+            b.return_void();
+        });
+
+        builder.build_block(done, |mut b| {
+            b.return_void();
+        });
+    }
+
+    /// Format a number straight into the buffer, counting its digits before
+    /// they count as printed if the output is limited
+    fn build_put_number(&self, builder: &mut Builder, checks: &Checks) {
+        let value = builder.get_param(self.put_number, 0);
+        let format = builder.constant_string("%ld\0");
+        let format = builder.add_global_variable("format", format);
+
+        let entry = builder.add_block(self.put_number, "entry");
+        let make_room = builder.add_block(self.put_number, "make_room");
+        let append = builder.add_block(self.put_number, "append");
+
+        builder.build_block(entry, |mut b| {
+            let length = b.load(self.length);
+            let is_full = b.compare(length, Compare::Greater, i64_value(BUFFER_SIZE - NUMBER_SIZE));
+            b.conditional_branch(is_full, make_room, append);
+        });
+
+        builder.build_block(make_room, |mut b| {
+            b.call(self.flush, &[]);
+            b.branch(append);
+        });
+
+        builder.build_block(append, |mut b| {
+            let format = b.pointer_cast(format, i8_ptr_type());
+            let length = b.load(self.length);
+            let data = b.load(self.buffer);
+            let data = b.pointer_cast(data, i8_ptr_type());
+            let end = b.get_element_offset(data, length);
+
+            let digits = b.call_function("snprintf", &[end, i64_value(NUMBER_SIZE), format, value]);
+            let digits = b.zero_extend_int(digits, i64_type());
+            checks.count_output(&mut b, digits);
+
+            let length = b.add(length, digits);
+            b.store(length, self.length);
+            b.return_void();
+        });
+    }
+
+
+    /// Allocate the buffer and decide when to flush it
+    pub fn build_constructor(&self, b: &mut BlockBuilder) {
+        let data = b.call_function("malloc", &[i32_value(BUFFER_SIZE as i32)]);
+        let data = b.pointer_cast(data, i64_ptr_type());
+        b.store(data, self.buffer);
+
+        for &(descriptor, flag) in &[(STDOUT, self.flush_lines), (STDIN, self.flush_before_read)] {
+            let terminal = b.call_function("isatty", &[i32_value(descriptor)]);
+            let is_terminal = b.compare(terminal, Compare::Greater, i32_value(0));
+            b.store(is_terminal, flag);
+        }
+    }
+
+    /// Write the buffer before reading, if standard input is a terminal
+    pub fn build_before_read(&self, b: &mut BlockBuilder, flush: LLVMBasicBlockRef, read: LLVMBasicBlockRef) {
+        let flushes = b.load(self.flush_before_read);
+        b.conditional_branch(flushes, flush, read);
+    }
+}
+
+
+/// Declare the C functions the output is written with
+pub fn add_external_functions(builder: &mut Builder) {
+    builder.add_function_var_arg("snprintf", i32_type(), &[("", i8_ptr_type()), ("", i64_type()), ("", i8_ptr_type())]);
+    builder.add_function("write", i64_type(), &[("", i32_type()), ("", i8_ptr_type()), ("", i64_type())]);
+    builder.add_function("isatty", i32_type(), &[("", i32_type())]);
+}
@@ -3,6 +3,9 @@
 use llvm_sys::prelude::*;
 use builder::*;
 use karma_parser::*;
+use karma_parser::flow::{FlowGraph, Location};
+use coverage::Counters;
+use limits::Checks;
 
 use super::SequenceBlock;
 
@@ -11,17 +14,31 @@ pub struct InstructionBuilder<'a> {
     pub on_success: LLVMBasicBlockRef,
     pub on_failure: LLVMBasicBlockRef,
 
+    pub flow: &'a FlowGraph,
     pub sequences: &'a [SequenceBlock],
+    pub coverage: Option<&'a Counters>,
+    pub checks: Option<&'a Checks>,
     pub sequence: usize,
     pub section: usize
 }
 
 
 impl<'a> InstructionBuilder<'a> {
+    #[allow(clippy::single_match)]
     pub fn build(mut self, instructions: &[Instruction]) {
         let mut append_jump = true;
 
+        if let Some(coverage) = self.coverage {
+            coverage.count_section(&mut self.builder, self.sequence, self.section);
+        }
+
         for instruction in instructions.iter() {
+            // counted one at a time like the interpreter does, so that the
+            // instructions before the limit still run
+            if let Some(checks) = self.checks {
+                checks.count_step(&mut self.builder);
+            }
+
             match instruction {
                 &Instruction::Jump(_, _) => {
                     self.build_advance_section();
@@ -32,9 +49,13 @@ impl<'a> InstructionBuilder<'a> {
 
             self.build_instruction(instruction);
 
+            // the block ends here, a trap folded from constants may leave
+            // instructions behind it that can never run
             match instruction {
-                &Instruction::Exit | &Instruction::SkipIfNotOne | &Instruction::Jump(_, _) 
-                    => append_jump = false,
+                &Instruction::Exit | &Instruction::Trap(_) | &Instruction::SkipIfNotOne | &Instruction::Jump(_, _) => {
+                    append_jump = false;
+                    break;
+                }
 
                 _ => ()
             }
@@ -48,11 +69,13 @@ impl<'a> InstructionBuilder<'a> {
 
 
     fn build_advance_section(&mut self) {
-        let next_section = self.sequences[self.sequence].jump_table.next_section;
-        self.builder.store(i64_value(self.section as i64 + 1), next_section);
+        if let Some(ref jump_table) = self.sequences[self.sequence].jump_table {
+            self.builder.store(i64_value(self.section as i64 + 1), jump_table.next_section);
+        }
     }
 
 
+    #[allow(clippy::match_ref_pats, clippy::needless_borrowed_reference)]
     fn build_instruction(&mut self, instruction: &Instruction) {
         match instruction {
             &Instruction::Push(ref source) => self.build_push(source),
@@ -65,11 +88,15 @@ impl<'a> InstructionBuilder<'a> {
             &Instruction::LogicalNot => self.build_logical_not(),
 
             &Instruction::Destroy => self.build_destroy(),
+            &Instruction::Replace(ref source) => self.build_replace(source),
+            &Instruction::Discard(ref source) => self.build_discard(source),
 
             &Instruction::SkipIfNotOne => self.build_skip(),
             &Instruction::Jump(ref direction, ref start) => self.build_jump(direction, start),
 
             &Instruction::Exit => self.build_exit(),
+            &Instruction::Trap(trap) => self.build_trap(trap),
+            &Instruction::CountedLoop(step, ref operator) => self.build_counted_loop(step, operator),
         }
     }
 
@@ -78,6 +105,7 @@ impl<'a> InstructionBuilder<'a> {
         self.builder.call_function("push", &[value]);
     }
 
+    #[allow(clippy::match_ref_pats)]
     fn build_insert(&mut self, source: &ValueSource, end: &QueueEnd) {
         let value = self.get_value_from_source(source);
         match end {
@@ -92,11 +120,24 @@ impl<'a> InstructionBuilder<'a> {
         self.builder.call_function("pop", &[]);
     }
 
+    fn build_replace(&mut self, source: &ValueSource) {
+        self.builder.call_function("pop", &[]);
+        self.build_push(source);
+    }
+
+    fn build_discard(&mut self, source: &ValueSource) {
+        self.get_value_from_source(source);
+    }
+
 
     fn build_output_character(&mut self, source: &ValueSource) {
         let value = self.get_value_from_source(source);
-        let value = self.builder.cast_int(value, i32_type());
-        self.builder.call_function("putchar", &[value]);
+
+        if let Some(checks) = self.checks {
+            checks.count_output(&mut self.builder, i64_value(1));
+        }
+
+        self.builder.call_function("put_byte", &[value]);
     }
 
     fn build_output_number(&mut self, source: &ValueSource) {
@@ -126,6 +167,10 @@ impl<'a> InstructionBuilder<'a> {
         let one = i64_value(1);
         let boolean_value = self.builder.compare(value, Compare::Equal, one);
 
+        if let Some(coverage) = self.coverage {
+            coverage.count_skip(&mut self.builder, self.sequence, self.section, boolean_value);
+        }
+
         let zero = i1_value(false);
         let one = i1_value(true);
 
@@ -140,24 +185,40 @@ impl<'a> InstructionBuilder<'a> {
     }
 
 
+    #[allow(clippy::match_ref_pats)]
     fn build_jump(&mut self, direction: &Direction, start: &Start) {
+        let location = Location { sequence: self.sequence, section: self.section };
+
+        // Restarting always lands in the first section, continuing often
+        // has only one possible section to resume at
+        if let Some(destination) = self.flow.jump_destination(location) {
+            let section = self.sequences[destination.sequence].sections[destination.section];
+            self.builder.branch(section);
+            return;
+        }
+
         let target_sequence = match direction {
             &Direction::Previous => self.sequence - 1,
             &Direction::Current => self.sequence,
             &Direction::Next => self.sequence + 1,
         };
 
-        let sequence = &self.sequences[target_sequence];
+        let jump_table = match self.sequences[target_sequence].jump_table {
+            Some(ref jump_table) => jump_table,
+            None => {
+                self.builder.branch(self.on_failure);
+                return;
+            }
+        };
 
         match start {
             &Start::Restart => {
-                let next_section = sequence.jump_table.next_section;
-                self.builder.store(i64_value(0), next_section);
+                self.builder.store(i64_value(0), jump_table.next_section);
             }
             &Start::Continue => ()
         }
 
-        self.builder.branch(sequence.jump_table.block);
+        self.builder.branch(jump_table.block);
     }
 
 
@@ -165,7 +226,17 @@ impl<'a> InstructionBuilder<'a> {
         self.builder.branch(self.on_success);
     }
 
+    fn build_counted_loop(&mut self, step: i64, operator: &Operator) {
+        self.builder.call_function(&counted_loop_function(operator), &[i64_value(step)]);
+    }
 
+    fn build_trap(&mut self, trap: Trap) {
+        self.builder.call_function("terminate", &[i32_value(trap.exit_code())]);
+        self.builder.branch(self.on_failure);
+    }
+
+
+    #[allow(clippy::match_ref_pats, clippy::needless_borrowed_reference, clippy::unnecessary_cast)]
     fn get_value_from_source(&mut self, source: &ValueSource) -> LLVMValueRef {
         match source {
             &ValueSource::Constant(digit) => i64_value(digit as i64),
@@ -191,10 +262,7 @@ impl<'a> InstructionBuilder<'a> {
                 value
             }
 
-            &ValueSource::Input => {
-                let value = self.builder.call_function("getchar", &[]);
-                self.builder.cast_int(value, i64_type())
-            }
+            &ValueSource::Input => self.builder.call_function("read", &[]),
 
             &ValueSource::Equal => {
                 let top = self.get_value_from_source(&ValueSource::Pop);
@@ -217,16 +285,29 @@ impl<'a> InstructionBuilder<'a> {
                        lhs: LLVMValueRef,
                        op: &Operator,
                        rhs: LLVMValueRef) -> LLVMValueRef {
-        match op {
-            &Operator::Add => self.builder.add(lhs, rhs),
-            &Operator::Sub => self.builder.sub(lhs, rhs),
-            &Operator::Mul => self.builder.mul(lhs, rhs),
-            &Operator::Div => self.builder.div(lhs, rhs),
-            &Operator::Mod => self.builder.modulo(lhs, rhs),
-
-            &Operator::And => self.builder.bit_and(lhs, rhs),
-            &Operator::Or => self.builder.bit_or(lhs, rhs),
-            &Operator::Xor => self.builder.bit_xor(lhs, rhs),
-        }
+        build_operation(&mut self.builder, lhs, op, rhs)
     }
 }
+
+
+pub fn build_operation(builder: &mut BlockBuilder,
+                       lhs: LLVMValueRef,
+                       op: &Operator,
+                       rhs: LLVMValueRef) -> LLVMValueRef {
+    match *op {
+        Operator::Add => builder.add(lhs, rhs),
+        Operator::Sub => builder.sub(lhs, rhs),
+        Operator::Mul => builder.mul(lhs, rhs),
+        Operator::Div => builder.call_function("divide", &[lhs, rhs]),
+        Operator::Mod => builder.call_function("remainder", &[lhs, rhs]),
+
+        Operator::And => builder.bit_and(lhs, rhs),
+        Operator::Or => builder.bit_or(lhs, rhs),
+        Operator::Xor => builder.bit_xor(lhs, rhs),
+    }
+}
+
+/// The name of the function that runs a `CountedLoop` with an operator
+pub fn counted_loop_function(operator: &Operator) -> String {
+    format!("counted_loop_{:?}", operator).to_lowercase()
+}
@@ -2,9 +2,13 @@
 use llvm_sys::prelude::*;
 use builder::*;
 use karma_parser::*;
+use karma_parser::flow::{FlowGraph, Location, Terminator};
+use coverage::Counters;
+use limits::Checks;
 
 mod instruction_builder;
 use self::instruction_builder::*;
+pub use self::instruction_builder::{build_operation, counted_loop_function};
 
 
 /// Builds a sequence
@@ -12,15 +16,22 @@ pub struct SequenceBuilder<'a> {
     builder: &'a mut Builder,
     target_fn: LLVMValueRef,
     panic_block: LLVMBasicBlockRef,
-    success_block: LLVMBasicBlockRef
+    success_block: LLVMBasicBlockRef,
+
+    // Counts sections and skips if set
+    coverage: Option<&'a Counters>,
+
+    // Counts steps and output against the limits if set
+    checks: Option<&'a Checks>
 }
 
 pub struct SequenceBlock {
-    // the jump table into the sequence
-    pub jump_table: JumpTable,
+    // the jump table into the sequence, only needed if it is resumed at a
+    // section that is not known at compile time
+    pub jump_table: Option<JumpTable>,
 
     // All sections
-    sections: Vec<LLVMBasicBlockRef>
+    pub sections: Vec<LLVMBasicBlockRef>
 }
 
 pub struct JumpTable {
@@ -39,15 +50,31 @@ impl<'a> SequenceBuilder<'a> {
             builder,
             target_fn,
             panic_block,
-            success_block
+            success_block,
+            coverage: None,
+            checks: None
         }
     }
 
 
+    /// Count executed sections and skip outcomes
+    pub fn with_coverage(mut self, coverage: Option<&'a Counters>) -> Self {
+        self.coverage = coverage;
+        self
+    }
+
+    /// Count steps and output for the limits
+    pub fn with_checks(mut self, checks: &'a Checks) -> Self {
+        self.checks = Some(checks);
+        self
+    }
+
+
     pub fn build(mut self, sequences: &[Sequence]) -> Vec<SequenceBlock> {
-        let sequence_blocks = self.create_sequence_blocks(sequences);
+        let flow = FlowGraph::new(sequences);
+        let sequence_blocks = self.create_sequence_blocks(sequences, &flow);
 
-        self.build_sequence_blocks(&sequence_blocks, sequences);
+        self.build_sequence_blocks(&sequence_blocks, sequences, &flow);
 
         sequence_blocks
     }
@@ -57,12 +84,16 @@ impl<'a> SequenceBuilder<'a> {
 // Implementation
 impl<'a> SequenceBuilder<'a> {
     /// Creates all blocks for each sequence
-    fn create_sequence_blocks(&mut self, sequences: &[Sequence]) -> Vec<SequenceBlock> {
+    fn create_sequence_blocks(&mut self, sequences: &[Sequence], flow: &FlowGraph) -> Vec<SequenceBlock> {
         let mut sequence_blocks = Vec::new();
 
         for (i, sequence) in sequences.iter().enumerate() {
             let sections = self.create_sections(i, sequence);
-            let jump_table = self.build_jump_table(i, &sections);
+            let jump_table = if needs_jump_table(i, sequences, flow) {
+                Some(self.build_jump_table(i, &sections))
+            } else {
+                None
+            };
 
             sequence_blocks.push(SequenceBlock {
                 jump_table, sections
@@ -74,6 +105,7 @@ impl<'a> SequenceBuilder<'a> {
     
 
     /// Creates a block for each section
+    #[allow(clippy::let_and_return)]
     fn create_sections(&mut self,
                        sequence_number: usize,
                        sequence: &Sequence) -> Vec<LLVMBasicBlockRef> {
@@ -113,9 +145,14 @@ impl<'a> SequenceBuilder<'a> {
     }
 
 
-    fn build_sequence_blocks(&mut self, sequence_blocks: &[SequenceBlock], sequences: &[Sequence]) {
+    fn build_sequence_blocks(&mut self,
+                             sequence_blocks: &[SequenceBlock],
+                             sequences: &[Sequence],
+                             flow: &FlowGraph) {
         let on_success = self.success_block;
         let on_failure = self.panic_block;
+        let coverage = self.coverage;
+        let checks = self.checks;
 
         for (sequence_index, (sequence_block, sequence)) in sequence_blocks.iter().zip(sequences.iter()).enumerate() {
             for (section_index, (block, section)) in sequence_block.sections.iter().zip(sequence.iter()).enumerate() {
@@ -126,7 +163,10 @@ impl<'a> SequenceBuilder<'a> {
                         builder,
                         on_success,
                         on_failure,
+                        flow,
                         sequences: sequence_blocks,
+                        coverage,
+                        checks,
                         sequence: sequence_index,
                         section: section_index
                     }.build(instructions);
@@ -136,3 +176,22 @@ impl<'a> SequenceBuilder<'a> {
     }
 }
 
+
+/// A sequence needs a jump table if any jump continues it at a section that
+/// depends on how the program got there
+fn needs_jump_table(target: usize, sequences: &[Sequence], flow: &FlowGraph) -> bool {
+    sequences.iter().enumerate().any(|(sequence, sections)| {
+        (0..sections.len()).any(|section| {
+            let location = Location { sequence, section };
+
+            match *flow.terminator(location) {
+                Terminator::Jump(ref direction, Start::Continue) => {
+                    flow.target(sequence, direction) == Some(target) &&
+                        flow.jump_destination(location).is_none()
+                }
+
+                _ => false
+            }
+        })
+    })
+}
@@ -1,6 +1,7 @@
 
 use llvm_sys::prelude::*;
 use builder::*;
+use karma_parser::Trap;
 
 use std::mem::size_of;
 
@@ -15,11 +16,14 @@ pub struct Stack {
     pub resize: LLVMValueRef,
     
     pub push: LLVMValueRef,
-    pub pop: LLVMValueRef
+    pub pop: LLVMValueRef,
+
+    /// The most values the stack may hold, if limited
+    pub limit: Option<usize>
 }
 
 impl Stack {
-    pub fn build(builder: &mut Builder) -> Stack {
+    pub fn build(builder: &mut Builder, limit: Option<usize>) -> Stack {
         let data = builder.add_global_variable("stack", i64_ptr_value());
         let length = builder.add_global_variable("stack_length", i64_value(0));
         let capacity = builder.add_global_variable("stack_capacity", i64_value(0));
@@ -35,7 +39,8 @@ impl Stack {
 
             resize,
             push,
-            pop
+            pop,
+            limit
         };
 
         stack.build_resize(builder);
@@ -97,10 +102,32 @@ impl Stack {
     fn build_push(&self, builder: &mut Builder) {
         let value = builder.get_param(self.push, 0);
         let entry = builder.add_block(self.push, "entry");
+        let check_capacity = builder.add_block(self.push, "check_capacity");
         let grow = builder.add_block(self.push, "grow");
         let write = builder.add_block(self.push, "write");
 
-        builder.build_block(entry, |mut block| {
+        match self.limit {
+            Some(limit) => {
+                let full = builder.add_block(self.push, "full");
+
+                builder.build_block(entry, |mut block| {
+                    let length = block.load(self.length);
+                    let is_full = block.compare(length, Compare::GreaterOrEqual, i64_value(limit as i64));
+                    block.conditional_branch(is_full, full, check_capacity);
+                });
+
+                builder.build_block(full, |mut block| {
+                    block.call_function("terminate", &[i32_value(Trap::StackLimit.exit_code())]);
+                    block.return_void();
+                });
+            }
+
+            None => builder.build_block(entry, |mut block| {
+                block.branch(check_capacity);
+            })
+        }
+
+        builder.build_block(check_capacity, |mut block| {
             let length = block.load(self.length);
             let capacity = block.load(self.capacity);
             let new_length = block.add(length, i64_value(1));
@@ -147,7 +174,7 @@ impl Stack {
         });
 
         builder.build_block(fail, |mut block| {
-            block.call_function("exit", &[i32_value(14)]);
+            block.call_function("terminate", &[i32_value(Trap::EmptyStack.exit_code())]);
             block.return_value(i64_value(-1));
         });
 
@@ -163,6 +190,7 @@ impl Stack {
     }
 
 
+    #[allow(clippy::unnecessary_mut_passed)]
     pub fn build_constructor(&self, b: &mut BlockBuilder) {
         b.call(self.resize, &mut[i64_value(INITIAL_SIZE)]);
     }
@@ -0,0 +1,39 @@
+use karma_parser::{
+    lint::{self, Severity},
+    syntax::SyntaxTree,
+};
+
+use common::{Common, Source};
+
+
+/// `karma check`: report parse errors and lint diagnostics
+pub fn check(arguments: Vec<String>) -> Result<i32, String> {
+    let mut paths = Vec::new();
+    let mut common = Common::new();
+
+    for argument in arguments {
+        if common.parse_flag(&argument)? {
+            continue;
+        } else if argument == "-" || !argument.starts_with('-') {
+            paths.push(argument);
+        } else {
+            return Err(format!("Unknown option: {}", argument));
+        }
+    }
+
+    if paths.is_empty() {
+        return Err("No source files given, use - for standard input".to_owned());
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        let source = Source::read(path)?;
+
+        for diagnostic in lint::lint(&SyntaxTree::parse(&source.text, common.dialect)) {
+            println!("{}:{}", source.name, diagnostic);
+            failed |= diagnostic.rule.severity == Severity::Error;
+        }
+    }
+
+    Ok(if failed { 1 } else { 0 })
+}
@@ -0,0 +1,180 @@
+use std::{
+    env,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use karma_parser::{
+    Sequence,
+    Dialect,
+    Eof,
+    Limits,
+    parse_str_with_dialect,
+    optimize::PassManager,
+};
+
+use golden;
+
+
+/// Flags that mean the same to every command
+pub struct Common {
+    pub dialect: Dialect,
+    pub passes: PassManager,
+    pub eof: Eof,
+    pub limits: Limits,
+
+    /// Print what each optimization pass changed
+    pub statistics: bool,
+
+    /// Check that no optimization pass changes what the program does
+    pub validate: bool,
+
+    pub karmac: String,
+    pub lli: String,
+}
+
+/// A program read from a file, or from standard input for `-`
+pub struct Source {
+    pub name: String,
+    pub text: String,
+
+    /// `None` for standard input
+    pub path: Option<PathBuf>,
+}
+
+
+impl Common {
+    pub fn new() -> Common {
+        Common {
+            dialect: Dialect::default(),
+            passes: PassManager::default(),
+            eof: Eof::default(),
+            limits: Limits::default(),
+            statistics: false,
+            validate: false,
+            karmac: env::var("KARMAC").unwrap_or_else(|_| "karmac".to_owned()),
+            lli: env::var("LLI").unwrap_or_else(|_| "lli".to_owned()),
+        }
+    }
+
+    /// Take an argument if it is a common flag
+    pub fn parse_flag(&mut self, argument: &str) -> Result<bool, String> {
+        if argument == "--stats" {
+            self.statistics = true;
+        } else if argument == "--validate" {
+            self.validate = true;
+        } else if let Some(name) = argument.strip_prefix("--dialect=") {
+            self.dialect = Dialect::from_name(name).map_err(|e| format!("{:?}", e))?;
+        } else if let Some(name) = argument.strip_prefix("--eof=") {
+            self.eof = Eof::from_name(name).map_err(|e| format!("{:?}", e))?;
+        } else if self.limits.parse_flag(argument).map_err(|e| format!("{:?}", e))? {
+            return Ok(true);
+        } else if let Some(path) = argument.strip_prefix("--karmac=") {
+            self.karmac = path.to_owned();
+        } else if let Some(path) = argument.strip_prefix("--lli=") {
+            self.lli = path.to_owned();
+        } else {
+            return self.passes.parse_flag(argument).map_err(|e| format!("{:?}", e));
+        }
+
+        Ok(true)
+    }
+
+
+    /// Parse and optimize a program for the interpreter
+    pub fn prepare(&self, source: &Source) -> Result<Vec<Sequence>, String> {
+        let sequences = parse_str_with_dialect(&source.text, self.dialect)
+            .map_err(|e| format!("{}: {:?}", source.name, e))?;
+
+        if self.validate {
+            self.passes.validate(&sequences).map_err(|counterexample| counterexample.to_string())?;
+        }
+
+        let (sequences, statistics) = self.passes.run(sequences);
+
+        if self.statistics {
+            for pass in statistics {
+                eprintln!("{}", pass);
+            }
+        }
+
+        Ok(sequences)
+    }
+
+    /// Generate LLVM IR for a program with `karmac`, passing it `extra`
+    /// arguments
+    pub fn compile(&self, source: &Source, extra: &[String]) -> Result<Vec<u8>, String> {
+        let temporary = match source.path {
+            Some(_) => None,
+            None => {
+                let path = golden::temporary_path(Path::new("stdin"), "kar");
+                fs::write(&path, &source.text).map_err(|e| e.to_string())?;
+                Some(path)
+            }
+        };
+
+        let mut command = Command::new(&self.karmac);
+        command.arg(source.path.as_ref().or(temporary.as_ref()).unwrap())
+            .arg(format!("--dialect={}", dialect_name(self.dialect)))
+            .arg(format!("--passes={}", self.passes.enabled().join(",")))
+            .arg(format!("--eof={}", eof_name(self.eof)))
+            .args(self.limits.flags())
+            .args(extra);
+
+        if self.statistics {
+            command.arg("--stats");
+        }
+        if self.validate {
+            command.arg("--validate");
+        }
+
+        let compiled = command.output();
+        if let Some(path) = temporary {
+            let _ = fs::remove_file(path);
+        }
+
+        let compiled = compiled.map_err(|e| format!("could not run {}: {}", self.karmac, e))?;
+        eprint!("{}", String::from_utf8_lossy(&compiled.stderr));
+
+        if !compiled.status.success() {
+            return Err(format!("{} failed: {}", self.karmac, String::from_utf8_lossy(&compiled.stdout).trim()));
+        }
+
+        Ok(compiled.stdout)
+    }
+}
+
+
+impl Source {
+    pub fn read(path: &str) -> Result<Source, String> {
+        if path == "-" {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).map_err(|e| format!("<stdin>: {}", e))?;
+            return Ok(Source { name: "<stdin>".to_owned(), text, path: None });
+        }
+
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Source { name: path.to_owned(), text, path: Some(PathBuf::from(path)) })
+    }
+
+    pub fn is_stdin(&self) -> bool {
+        self.path.is_none()
+    }
+}
+
+
+pub fn dialect_name(dialect: Dialect) -> &'static str {
+    match dialect {
+        Dialect::Standard => "standard",
+        Dialect::Literals => "literals",
+    }
+}
+
+pub fn eof_name(eof: Eof) -> String {
+    match eof {
+        Eof::Error => "error".to_owned(),
+        Eof::Value(value) => value.to_string(),
+    }
+}
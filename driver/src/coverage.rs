@@ -0,0 +1,60 @@
+use std::{
+    fs,
+    io::{self, Write},
+};
+
+use karma_interpreter::coverage::Coverage;
+use karma_parser::syntax::SyntaxTree;
+
+use common::{Common, Source};
+
+
+/// `karma coverage`: merge coverage files and show what they cover
+pub fn coverage(arguments: Vec<String>) -> Result<i32, String> {
+    let mut common = Common::new();
+    let mut lcov = None;
+    let mut paths = Vec::new();
+
+    for argument in arguments {
+        if common.parse_flag(&argument)? {
+            continue;
+        } else if let Some(path) = argument.strip_prefix("--lcov=") {
+            lcov = Some(path.to_owned());
+        } else if argument == "-" || !argument.starts_with('-') {
+            paths.push(argument);
+        } else {
+            return Err(format!("Unknown option: {}", argument));
+        }
+    }
+
+    if paths.len() < 2 {
+        return Err("Give the program and at least one coverage file".to_owned());
+    }
+
+    let source = Source::read(&paths[0])?;
+    let tree = SyntaxTree::parse(&source.text, common.dialect);
+    let sequences = tree.to_sequences().map_err(|e| format!("{}: {:?}", source.name, e))?;
+
+    let mut coverage = Coverage::new(&sequences);
+    for path in &paths[1..] {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        coverage.read(&text).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    if lcov.as_deref() != Some("-") {
+        let stdout = io::stdout();
+        coverage.write_annotated(&tree, &sequences, &mut stdout.lock()).map_err(|e| e.to_string())?;
+    }
+
+    if let Some(path) = lcov {
+        let mut tracefile = Vec::new();
+        coverage.write_lcov(&source.name, &sequences, &mut tracefile).map_err(|e| e.to_string())?;
+
+        match path.as_str() {
+            "-" => io::stdout().write_all(&tracefile).map_err(|e| e.to_string())?,
+            _ => fs::write(&path, tracefile).map_err(|e| format!("{}: {}", path, e))?,
+        }
+    }
+
+    Ok(0)
+}
@@ -0,0 +1,859 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use karma_parser::{
+    Sequence,
+    Instruction,
+    Instruction::*,
+    ValueSource,
+    ValueSource::*,
+    Operator,
+    QueueEnd::*,
+    Direction::*,
+    Start::*,
+    Dialect,
+    Eof,
+    Limits,
+    Trap,
+    depth::Depths,
+    optimize::PassManager,
+    parse_str,
+    unparse::unparse,
+};
+
+use karma_interpreter::execution::State;
+
+use golden::{self, Backend, Outcome};
+
+
+/// Written before the final contents of the stack and deque, so that a
+/// difference there can be told apart from one in the rest of the output
+const STATE_MARKER: u8 = 0;
+
+const OPERATORS: &[Operator] = &[
+    Operator::Add, Operator::Sub, Operator::Mul, Operator::Div,
+    Operator::Mod, Operator::And, Operator::Or, Operator::Xor,
+];
+
+/// How many instructions a line has at most
+const MAX_INSTRUCTIONS: usize = 8;
+
+/// How often a loop body runs at most
+const MAX_ITERATIONS: i64 = 4;
+
+/// How many steps a program may take, so that programs jumping back and forth
+/// forever are left out
+const MAX_STEPS: u64 = 10_000;
+
+/// How often an instruction is kept although it takes more values than the
+/// stack or deque holds
+const UNDERFLOW_PERCENT: usize = 2;
+
+
+pub struct Options {
+    /// How many programs to try
+    pub programs: usize,
+    pub seed: u64,
+
+    /// How many lines a program has at most
+    pub lines: usize,
+
+    /// Whether programs may run out of input
+    pub allow_eof: bool,
+    pub eof: Eof,
+
+    /// The backend compared with the unoptimized interpreter
+    pub backend: Backend,
+    pub passes: Arc<PassManager>,
+
+    pub timeout: Duration,
+    pub karmac: String,
+    pub lli: String,
+
+    /// Where to write the reproducer of a divergence
+    pub save: Option<PathBuf>,
+}
+
+/// A small, fast pseudorandom number generator (xorshift64*), so that a seed
+/// always gives the same programs
+pub struct Random(u64);
+
+/// A generated program. Lines end with a jump to the next one, although the
+/// instructions in them may jump elsewhere, and a program that gets past its
+/// lines prints the contents of the stack and deque on a last line.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Program {
+    pub lines: Vec<Line>,
+    pub input: Vec<u8>,
+    pub eof: Eof,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Line {
+    /// Instructions followed by a jump to the next line
+    Straight(Vec<Instruction>),
+
+    /// A counting loop that runs its body `bound` times, as long as the body
+    /// leaves alone the counter at the front of the deque
+    Loop { bound: i64, body: Vec<Instruction> },
+}
+
+/// How many values are in the stack and deque when a program gets to its last
+/// line, none if it stops before
+#[derive(Debug, Copy, Clone, Default)]
+struct Simulation {
+    stack: usize,
+    deque: usize,
+}
+
+/// Input made up of random bytes, remembering what was read
+struct RandomInput<'a> {
+    random: &'a mut Random,
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Kind {
+    /// A backend failed to run the program
+    Failure,
+
+    ExitCode,
+    Output,
+
+    /// Only the final contents of the stack or deque differ
+    FinalState,
+}
+
+/// A program that the backends disagree on
+#[derive(Debug)]
+pub struct Divergence {
+    pub kind: Kind,
+    pub reference: Result<Outcome, String>,
+    pub subject: Result<Outcome, String>,
+}
+
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        // the state must never be zero
+        Random(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A number in `0..bound`
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+
+/// Generate random programs and compare how the interpreter and a backend run
+/// them. Stops at the first divergence, which is shrunk and reported. Returns
+/// whether all programs behaved the same.
+pub fn run(options: &Options) -> io::Result<bool> {
+    let reference = golden_options(options, Backend::Interpreter, Arc::new(PassManager::new(0)));
+    let subject = golden_options(options, options.backend, options.passes.clone());
+    let backends = (&reference, &subject);
+
+    for index in 0..options.programs {
+        let seed = options.seed.wrapping_add(index as u64);
+        let program = generate(&mut Random::new(seed), options.lines, options.allow_eof, options.eof);
+
+        let divergence = match compare(&program, backends)? {
+            Some(divergence) => divergence,
+            None => continue,
+        };
+
+        println!("{} programs passed, the one with seed {} diverged: {}", index, seed, divergence.kind.name());
+
+        let signature = divergence.signature();
+        let shrunk = shrink(&program, |candidate| {
+            compare(candidate, backends).ok()
+                .and_then(|divergence| divergence)
+                .is_some_and(|divergence| divergence.signature() == signature)
+        });
+
+        let source = shrunk.source().unwrap();
+        let divergence = compare(&shrunk, backends)?.unwrap_or(divergence);
+
+        println!();
+        print!("{}", source);
+        println!();
+        println!("input:       {:?}", String::from_utf8_lossy(&shrunk.input));
+        println!("interpreter: {}", describe(&divergence.reference));
+        println!("{:<12} {}", format!("{}:", divergence_name(options)), describe(&divergence.subject));
+
+        if let Some(ref directory) = options.save {
+            fs::create_dir_all(directory)?;
+
+            let path = directory.join(format!("divergence-{}.kar", seed));
+            fs::write(&path, &source)?;
+            fs::write(path.with_extension("in"), &shrunk.input)?;
+            println!();
+            println!("saved to {}", path.display());
+        }
+
+        return Ok(false);
+    }
+
+    println!("{} programs passed", options.programs);
+    Ok(true)
+}
+
+
+/// Generate a program that stops within `MAX_STEPS`. Its stack and deque
+/// mostly hold the values it takes, but now and then run out. Unless
+/// `allow_eof` is given, the input is exactly as long as what the program reads.
+pub fn generate(random: &mut Random, max_lines: usize, allow_eof: bool, eof: Eof) -> Program {
+    loop {
+        let mut depths = Depths::start();
+        let mut lines = Vec::new();
+
+        for _ in 0..random.below(max_lines.max(1)) + 1 {
+            let length = random.below(MAX_INSTRUCTIONS) + 1;
+
+            if random.chance(25) {
+                let bound = random.below(MAX_ITERATIONS as usize + 1) as i64;
+                execute(&mut depths, &loop_setup(bound));
+
+                // the body starts after the head and the counter going back
+                let entry = depths;
+                let mut inner = depths;
+                execute(&mut inner, &loop_head());
+                execute(&mut inner, &[Insert(Pop, Front)]);
+
+                // half of the loops can become counted loops
+                let counted = random.chance(50);
+                let mut body = generate_instructions(random, &mut inner, counted, length);
+                while inner.stack.min > entry.stack.min {
+                    body.push(Destroy);
+                    execute(&mut inner, &[Destroy]);
+                }
+                while inner.stack.min < entry.stack.min {
+                    body.push(Push(Constant(random.below(10) as i64)));
+                    execute(&mut inner, &[Push(Constant(0))]);
+                }
+
+                execute(&mut depths, &loop_head());
+                lines.push(Line::Loop { bound, body });
+            } else {
+                lines.push(Line::Straight(generate_instructions(random, &mut depths, false, length)));
+            }
+        }
+
+        let mut program = Program { lines, input: Vec::new(), eof };
+        let mut input = RandomInput { random: &mut *random, bytes: Vec::new() };
+        if program.simulate(&mut input).is_none() {
+            continue;
+        }
+
+        program.input = input.bytes;
+        if allow_eof && random.chance(30) {
+            let length = random.below(program.input.len() + 1);
+            program.input.truncate(length);
+        }
+
+        // running out of input may keep the program from stopping
+        if program.source().is_some() {
+            return program;
+        }
+    }
+}
+
+/// Run a program with both backends
+pub fn compare(program: &Program, backends: (&golden::Options, &golden::Options)) -> io::Result<Option<Divergence>> {
+    let path = golden::temporary_path(&PathBuf::from("differential"), "kar");
+    fs::write(&path, program.source().unwrap())?;
+
+    let (reference, subject) = backends;
+    let reference_result = golden::run(&path, &program.input, reference.backends[0], reference);
+    let subject_result = golden::run(&path, &program.input, subject.backends[0], subject);
+    fs::remove_file(&path)?;
+
+    let kind = match (&reference_result, &subject_result) {
+        (Ok(reference), Ok(subject)) if reference == subject => return Ok(None),
+        (Ok(reference), Ok(subject)) if reference.exit_code != subject.exit_code => Kind::ExitCode,
+        (Ok(reference), Ok(subject)) if before_state(&reference.output) == before_state(&subject.output) => Kind::FinalState,
+        (Ok(_), Ok(_)) => Kind::Output,
+        _ => Kind::Failure,
+    };
+
+    Ok(Some(Divergence { kind, reference: reference_result, subject: subject_result }))
+}
+
+/// Make a program smaller for as long as it keeps failing
+pub fn shrink<F: FnMut(&Program) -> bool>(program: &Program, mut fails: F) -> Program {
+    let mut current = program.clone();
+
+    'progress: loop {
+        for candidate in current.simplifications() {
+            if candidate.source().is_some() && fails(&candidate) {
+                current = candidate;
+                continue 'progress;
+            }
+        }
+
+        return current;
+    }
+}
+
+
+impl Program {
+    /// The program as standard Karma source code, `None` if it cannot be
+    /// written down or does not stop within `MAX_STEPS`
+    pub fn source(&self) -> Option<String> {
+        let simulation = self.simulate(&mut &self.input[..])?;
+        unparse(&self.sequences(&simulation))
+    }
+
+    fn sequences(&self, simulation: &Simulation) -> Vec<Sequence> {
+        let mut sequences = vec![vec![vec![Exit]]];
+        for line in &self.lines {
+            match *line {
+                Line::Straight(ref instructions) => {
+                    sequences.push(sequence(&[&instructions[..], &[Jump(Next, Restart)]].concat()));
+                }
+
+                Line::Loop { bound, ref body } => {
+                    sequences.push(sequence(&[&loop_setup(bound)[..], &[Jump(Next, Restart)]].concat()));
+                    sequences.push(sequence(&[
+                        &loop_head()[..],
+                        &[Jump(Next, Restart), Insert(Pop, Front)],
+                        &body[..],
+                        &[Jump(Current, Restart)],
+                    ].concat()));
+                }
+            }
+        }
+
+        sequences.push(sequence(&print_state(simulation)));
+        sequences.push(vec![vec![Exit]]);
+        sequences
+    }
+
+    /// Run the program with the unoptimized interpreter to see what its last
+    /// line has to print, `None` if it does not stop within `MAX_STEPS`
+    fn simulate(&self, input: &mut dyn Read) -> Option<Simulation> {
+        // the source can leave out instructions that do nothing but run out
+        // of values, so run what the backends see
+        let source = unparse(&self.sequences(&Simulation::default()))?;
+        let sequences = parse_str(&source).ok()?;
+        let last_line = sequences.len() - 2;
+
+        let mut state = State::new();
+        let limits = Limits { steps: Some(MAX_STEPS), ..Limits::default() };
+        let result = golden::catch_panic(|| {
+            state.run_limited(&sequences, input, &mut io::sink(), self.eof, &limits, &mut ())
+        });
+
+        match result {
+            Ok(Err(Trap::StepLimit)) => None,
+            Ok(Ok(())) if state.current_sequence == last_line => {
+                Some(Simulation { stack: state.stack.len(), deque: state.deque.len() })
+            }
+
+            // the reference interpreter panics too, which the comparison shows
+            _ => Some(Simulation::default()),
+        }
+    }
+
+    /// Programs that are a little simpler, starting with the biggest steps
+    fn simplifications(&self) -> Vec<Program> {
+        let mut candidates = Vec::new();
+        let with_line = |index: usize, line: Option<Line>| {
+            let mut lines = self.lines.clone();
+            match line {
+                Some(line) => lines[index] = line,
+                None => { lines.remove(index); }
+            }
+            Program { lines, input: self.input.clone(), eof: self.eof }
+        };
+
+        for index in 0..self.lines.len() {
+            candidates.push(with_line(index, None));
+        }
+
+        for (index, line) in self.lines.iter().enumerate() {
+            for simpler in line.simplifications() {
+                candidates.push(with_line(index, Some(simpler)));
+            }
+        }
+
+        if !self.input.is_empty() {
+            let mut unread = &self.input[..];
+            self.simulate(&mut unread);
+
+            let reads = self.input.len() - unread.len();
+            let length = if reads < self.input.len() { reads } else { self.input.len() - 1 };
+            candidates.push(Program { lines: self.lines.clone(), input: self.input[..length].to_vec(), eof: self.eof });
+        }
+
+        candidates
+    }
+}
+
+
+impl Line {
+    fn simplifications(&self) -> Vec<Line> {
+        match *self {
+            Line::Straight(ref instructions) => {
+                simpler_lists(instructions).into_iter().map(Line::Straight).collect()
+            }
+
+            Line::Loop { bound, ref body } => {
+                let mut simpler = vec![Line::Straight(body.clone())];
+                if bound > 0 {
+                    simpler.push(Line::Loop { bound: bound - 1, body: body.clone() });
+                }
+
+                simpler.extend(simpler_lists(body).into_iter().map(|body| Line::Loop { bound, body }));
+                simpler
+            }
+        }
+    }
+}
+
+
+impl Read for RandomInput<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        for byte in buffer.iter_mut() {
+            *byte = random_byte(self.random);
+            self.bytes.push(*byte);
+        }
+
+        Ok(buffer.len())
+    }
+}
+
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Kind::Failure => "a backend failed",
+            Kind::ExitCode => "different exit codes",
+            Kind::Output => "different output",
+            Kind::FinalState => "different final stack or deque",
+        }
+    }
+}
+
+impl Divergence {
+    /// What a shrunk program has to keep doing to count as the same divergence
+    fn signature(&self) -> (Kind, Option<i32>, Option<i32>) {
+        let exit_code = |result: &Result<Outcome, String>| result.as_ref().ok().map(|outcome| outcome.exit_code);
+        (self.kind, exit_code(&self.reference), exit_code(&self.subject))
+    }
+}
+
+
+fn golden_options(options: &Options, backend: Backend, passes: Arc<PassManager>) -> golden::Options {
+    golden::Options {
+        paths: Vec::new(),
+        backends: vec![backend],
+        dialect: Dialect::Standard,
+        passes,
+        eof: options.eof,
+        limits: Limits::default(),
+        bless: false,
+        jobs: 1,
+        timeout: options.timeout,
+        karmac: options.karmac.clone(),
+        lli: options.lli.clone(),
+    }
+}
+
+fn divergence_name(options: &Options) -> String {
+    match options.backend {
+        Backend::Interpreter => "optimized".to_owned(),
+        Backend::Compiler => "compiler".to_owned(),
+    }
+}
+
+fn describe(result: &Result<Outcome, String>) -> String {
+    match *result {
+        Ok(ref outcome) => format!("exit code {}, output {:?}", outcome.exit_code, String::from_utf8_lossy(&outcome.output)),
+        Err(ref message) => message.clone(),
+    }
+}
+
+/// The output up to the final stack and deque
+fn before_state(output: &[u8]) -> &[u8] {
+    match output.iter().rposition(|&byte| byte == STATE_MARKER) {
+        Some(end) => &output[..end],
+        None => output
+    }
+}
+
+
+/// Split instructions into sections the way the parser does
+fn sequence(instructions: &[Instruction]) -> Sequence {
+    let mut sequence = Vec::new();
+    let mut section = Vec::new();
+    let mut after_skip = false;
+
+    for instruction in instructions {
+        section.push(instruction.clone());
+
+        let breaks = matches!(*instruction, Jump(_, _) | SkipIfNotOne);
+        if breaks || after_skip {
+            sequence.push(section);
+            section = Vec::new();
+        }
+
+        after_skip = *instruction == SkipIfNotOne;
+    }
+
+    section.push(Exit);
+    sequence.push(section);
+    sequence
+}
+
+/// Put a loop's bound and a counter of 0 in front of the deque
+fn loop_setup(bound: i64) -> Vec<Instruction> {
+    vec![Insert(Constant(bound), Front), Insert(Constant(0), Front)]
+}
+
+/// Increment the counter and compare it with the bound, leaving the counter
+/// on the stack. The jump out of the loop follows.
+fn loop_head() -> Vec<Instruction> {
+    vec![
+        Push(Remove(Front)),
+        Push(Operate(Box::new(Constant(1)), Operator::Add, Box::new(Pop))),
+        Push(CloneTop),
+        Push(Greater),
+        SkipIfNotOne,
+    ]
+}
+
+/// Print the values in the stack from the top, then those in the deque from
+/// the front
+fn print_state(simulation: &Simulation) -> Vec<Instruction> {
+    let separator = OutputCharacter(Constant(' ' as i64));
+
+    let mut instructions = vec![OutputCharacter(Constant(STATE_MARKER as i64))];
+    for _ in 0..simulation.stack {
+        instructions.push(OutputNumber(Pop));
+        instructions.push(separator.clone());
+    }
+
+    instructions.push(OutputCharacter(Constant('|' as i64)));
+    for _ in 0..simulation.deque {
+        instructions.push(OutputNumber(Remove(Front)));
+        instructions.push(separator.clone());
+    }
+
+    instructions
+}
+
+
+/// Instructions that mostly find the values they take, following `depths`
+/// through them as if they ran one after the other. Those of a `counted` loop
+/// body keep to what the loops pass can turn into a counted loop.
+fn generate_instructions(random: &mut Random, depths: &mut Depths, counted: bool, length: usize) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+
+    for _ in 0..length {
+        let underflow = random.chance(UNDERFLOW_PERCENT);
+
+        // most instructions need values, so try a few times
+        for _ in 0..10 {
+            let candidate = random_instruction(random);
+            if counted && !fits_counted_loop(&candidate) || unparse(&[vec![vec![Exit]], sequence(&candidate)]).is_none() {
+                continue;
+            }
+
+            let mut after = *depths;
+            if try_execute(&mut after, &candidate).is_some() {
+                *depths = after;
+            } else if !underflow {
+                continue;
+            }
+
+            instructions.extend(candidate);
+            break;
+        }
+    }
+
+    instructions
+}
+
+fn random_instruction(random: &mut Random) -> Vec<Instruction> {
+    let instruction = match random.below(15) {
+        0 => BitwiseNot,
+        1 => LogicalNot,
+        2..=4 => Push(random_value(random, 0)),
+        5 => Insert(random_value(random, 0), if random.chance(50) { Front } else { Back }),
+        6 => Destroy,
+        7 => Replace(random_value(random, 0)),
+        8 => Discard(random_value(random, 0)),
+        9 => OutputCharacter(random_value(random, 0)),
+        10 => OutputNumber(random_value(random, 0)),
+        11 => random_jump(random),
+        _ => {
+            let mut skipped = random_instruction(random);
+            skipped.insert(0, SkipIfNotOne);
+            return skipped;
+        }
+    };
+
+    vec![instruction]
+}
+
+fn random_jump(random: &mut Random) -> Instruction {
+    match random.below(4) {
+        0 => Jump(Next, Restart),
+        1 => Jump(Next, Continue),
+        2 => Jump(Previous, Continue),
+        _ => Jump(Current, Restart),
+    }
+}
+
+fn random_value(random: &mut Random, nesting: usize) -> ValueSource {
+    match random.below(if nesting < 2 { 10 } else { 8 }) {
+        0..=2 => Constant(random_constant(random)),
+        3 => Pop,
+        4 => CloneTop,
+        5 => Remove(if random.chance(50) { Front } else { Back }),
+        6 => Input,
+        7 => if random.chance(50) { Equal } else { Greater },
+        _ => Operate(
+            Box::new(random_value(random, nesting + 1)),
+            OPERATORS[random.below(OPERATORS.len())].clone(),
+            Box::new(random_value(random, nesting + 1)),
+        ),
+    }
+}
+
+fn random_constant(random: &mut Random) -> i64 {
+    match random.below(10) {
+        0..=4 => random.below(10) as i64,
+        5 => random.below(128) as i64,
+        6 => -(random.below(20) as i64),
+        7 => [i64::MIN, i64::MAX, -1, 255, 256][random.below(5)],
+        _ => random.next() as i64 >> random.below(64),
+    }
+}
+
+fn random_byte(random: &mut Random) -> u8 {
+    match random.below(4) {
+        0 => b'0' + random.below(10) as u8,
+        1 => b'a' + random.below(26) as u8,
+        2 => b'\n',
+        _ => random.below(256) as u8,
+    }
+}
+
+
+/// Whether the instructions can be in the body of a counted loop, which may not
+/// jump, touch the deque or change the size of the stack depending on data
+fn fits_counted_loop(instructions: &[Instruction]) -> bool {
+    let allowed = instructions.iter().all(|instruction| match *instruction {
+        Jump(_, _) | Insert(_, _) => false,
+        _ => !instruction_sources(instruction).iter().any(|source| removes(source)),
+    });
+
+    // a skip must be followed by a command that keeps the size of the stack
+    let skips_are_safe = instructions.iter().enumerate().all(|(index, instruction)| {
+        *instruction != SkipIfNotOne || matches!(instructions.get(index + 1), Some(&BitwiseNot) | Some(&LogicalNot))
+    });
+
+    allowed && skips_are_safe
+}
+
+fn instruction_sources(instruction: &Instruction) -> Vec<&ValueSource> {
+    match *instruction {
+        Push(ref source) | Insert(ref source, _) | Replace(ref source) | Discard(ref source)
+            | OutputCharacter(ref source) | OutputNumber(ref source) => vec![source],
+        _ => Vec::new()
+    }
+}
+
+fn removes(source: &ValueSource) -> bool {
+    match *source {
+        Remove(_) => true,
+        Operate(ref lhs, _, ref rhs) => removes(lhs) || removes(rhs),
+        _ => false
+    }
+}
+
+/// The instructions with one of them left out or simplified
+fn simpler_lists(instructions: &[Instruction]) -> Vec<Vec<Instruction>> {
+    let mut simpler = Vec::new();
+
+    for index in 0..instructions.len() {
+        let mut removed = instructions.to_vec();
+        removed.remove(index);
+        simpler.push(removed);
+
+        for instruction in simpler_instructions(&instructions[index]) {
+            let mut replaced = instructions.to_vec();
+            replaced[index] = instruction;
+            simpler.push(replaced);
+        }
+    }
+
+    simpler
+}
+
+fn simpler_instructions(instruction: &Instruction) -> Vec<Instruction> {
+    match *instruction {
+        Push(ref source) => simpler_values(source).into_iter().map(Push).collect(),
+        Replace(ref source) => simpler_values(source).into_iter().map(Replace).collect(),
+        Discard(ref source) => simpler_values(source).into_iter().map(Discard).collect(),
+        OutputCharacter(ref source) => simpler_values(source).into_iter().map(OutputCharacter).collect(),
+        OutputNumber(ref source) => simpler_values(source).into_iter().map(OutputNumber).collect(),
+        Insert(ref source, ref end) => simpler_values(source).into_iter()
+            .map(|source| Insert(source, end.clone()))
+            .collect(),
+        _ => Vec::new()
+    }
+}
+
+fn simpler_values(source: &ValueSource) -> Vec<ValueSource> {
+    match *source {
+        Constant(0) => Vec::new(),
+        Constant(value) if value.unsigned_abs() > 1 => vec![Constant(0), Constant(value / 2)],
+
+        Operate(ref lhs, ref operator, ref rhs) => {
+            let mut simpler = vec![(**lhs).clone(), (**rhs).clone()];
+
+            for lhs in simpler_values(lhs) {
+                simpler.push(Operate(Box::new(lhs), operator.clone(), rhs.clone()));
+            }
+            for rhs in simpler_values(rhs) {
+                simpler.push(Operate(lhs.clone(), operator.clone(), Box::new(rhs)));
+            }
+
+            simpler
+        }
+
+        _ => vec![Constant(0)]
+    }
+}
+
+
+fn execute(depths: &mut Depths, instructions: &[Instruction]) {
+    try_execute(depths, instructions).unwrap();
+}
+
+/// Update the depths, `None` if a container runs out of values
+fn try_execute(depths: &mut Depths, instructions: &[Instruction]) -> Option<()> {
+    for instruction in instructions {
+        depths.execute(instruction).ok()?;
+    }
+
+    Some(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use karma_parser::{Eof, QueueEnd::Back};
+    use parse_fuzz_arguments;
+
+    fn interpret(program: &Program) -> (Vec<u8>, i32) {
+        let sequences = karma_parser::parse_str(&program.source().unwrap()).unwrap();
+        let mut output = Vec::new();
+
+        let exit_code = match karma_interpreter::execution::execute(&sequences, &mut &program.input[..], &mut output, Eof::default()) {
+            Ok(()) => 0,
+            Err(trap) => trap.exit_code(),
+        };
+
+        (output, exit_code)
+    }
+
+    #[test]
+    fn generate_programs_that_stop() {
+        let mut exit_codes = Vec::new();
+        let mut jumps = 0;
+
+        for seed in 0..500 {
+            let program = generate(&mut Random::new(seed), 6, false, Eof::default());
+            let (_, exit_code) = interpret(&program);
+
+            exit_codes.push(exit_code);
+            if program.lines.iter().any(|line| format!("{:?}", line).contains("Jump")) {
+                jumps += 1;
+            }
+        }
+
+        // some run out of values
+        for exit_code in &[0, 13, 14] {
+            assert!(exit_codes.contains(exit_code), "{:?}", exit_codes);
+        }
+        assert!(jumps > 50);
+    }
+
+    #[test]
+    fn print_the_final_state() {
+        let program = Program {
+            lines: vec![
+                Line::Straight(vec![Push(Constant(1)), Push(Constant(2)), Insert(Constant(3), Back)]),
+                Line::Loop { bound: 2, body: vec![OutputNumber(CloneTop)] },
+            ],
+            input: Vec::new(),
+            eof: Eof::default(),
+        };
+
+        assert_eq!(interpret(&program), (b"22\x003 2 1 |2 3 ".to_vec(), 0));
+    }
+
+    #[test]
+    fn shrink_failing_programs() {
+        let traps = |program: &Program| interpret(program).1 == 15;
+
+        let program = (0..)
+            .map(|seed| generate(&mut Random::new(seed), 6, false, Eof::default()))
+            .find(|program| program.lines.len() > 2 && traps(program))
+            .unwrap();
+
+        let shrunk = shrink(&program, traps);
+        assert!(traps(&shrunk));
+        assert_eq!(shrunk.lines.len(), 1);
+        assert!(shrunk.source().unwrap().len() < program.source().unwrap().len());
+        assert!(shrunk.input.is_empty());
+    }
+
+    #[test]
+    fn optimized_interpreter_agrees() {
+        let mut options = parse_fuzz_arguments(vec!["--backend=interpreter".to_owned(), "-O2".to_owned()]).unwrap();
+        options.programs = 200;
+        options.seed = 0;
+
+        assert!(run(&options).unwrap());
+    }
+
+    #[test]
+    fn compiled_programs_agree() {
+        // `karmac` comes from `$KARMAC`, the path or a build of the compiler next to this crate
+        let built = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../compiler/target/debug/karmac");
+        let mut arguments = vec!["--backend=compiler".to_owned()];
+        if env::var_os("KARMAC").is_none() && built.exists() {
+            arguments.push(format!("--karmac={}", built.display()));
+        }
+
+        let mut options = parse_fuzz_arguments(arguments).unwrap();
+        options.programs = 100;
+        options.seed = 0;
+
+        assert!(run(&options).unwrap());
+    }
+}
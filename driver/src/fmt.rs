@@ -0,0 +1,87 @@
+use std::{
+    fs,
+    io::{self, Write},
+};
+
+use karma_parser::{
+    Dialect,
+    format::format,
+    syntax::SyntaxTree,
+};
+
+use common::{Common, Source};
+
+
+/// `karma fmt`: format files in place, or standard input to standard output
+pub fn fmt(arguments: Vec<String>) -> Result<i32, String> {
+    let mut paths = Vec::new();
+    let mut common = Common::new();
+    let mut check = false;
+
+    for argument in arguments {
+        if common.parse_flag(&argument)? {
+            continue;
+        } else if argument == "--check" {
+            check = true;
+        } else if argument == "-" || !argument.starts_with('-') {
+            paths.push(argument);
+        } else {
+            return Err(format!("Unknown option: {}", argument));
+        }
+    }
+
+    if paths.is_empty() {
+        paths.push("-".to_owned());
+    }
+
+    let mut unformatted = false;
+    let mut failed = false;
+
+    for path in &paths {
+        let result = Source::read(path).and_then(|source| {
+            format_source(&source.text, common.dialect)
+                .map(|formatted| (source, formatted))
+                .map_err(|message| format!("{}: {}", path, message))
+        });
+
+        match result {
+            Ok((ref source, ref formatted)) if source.is_stdin() && !check => {
+                io::stdout().write_all(formatted.as_bytes()).map_err(|e| e.to_string())?;
+            }
+
+            Ok((ref source, ref formatted)) if *formatted == source.text => (),
+
+            Ok((ref source, _)) if check => {
+                println!("{}", source.name);
+                unformatted = true;
+            }
+
+            Ok((_, formatted)) => if let Err(e) = fs::write(path, formatted) {
+                eprintln!("{}: {}", path, e);
+                failed = true;
+            },
+
+            Err(message) => {
+                eprintln!("{}", message);
+                failed = true;
+            }
+        }
+    }
+
+    Ok(if failed { 2 } else if unformatted { 1 } else { 0 })
+}
+
+
+/// Format a program, refusing programs that do not parse or whose meaning
+/// would change
+pub fn format_source(source: &str, dialect: Dialect) -> Result<String, String> {
+    let tree = SyntaxTree::parse(source, dialect);
+    let formatted = format(&tree);
+
+    let sequences = tree.to_sequences().map_err(|e| format!("{:?}", e))?;
+    if formatted.to_sequences().ok() != Some(sequences) {
+        return Err("formatting would change the program".to_owned());
+    }
+
+    Ok(formatted.to_string())
+}
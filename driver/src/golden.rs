@@ -0,0 +1,551 @@
+use std::{
+    cell::Cell,
+    env,
+    fs,
+    io::{self, Cursor, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        Arc,
+        Mutex,
+        Once,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use karma_parser::{parse_file_with_dialect, Dialect, Eof, Limits, Trap, optimize::PassManager};
+use karma_interpreter::execution::execute_limited;
+
+use common::{dialect_name, eof_name};
+
+
+/// The exit code of a Rust program that panicked, which is what a bug in the
+/// interpreter looks like
+const PANIC_EXIT_CODE: i32 = 101;
+
+thread_local! {
+    /// Whether a panic on this thread is caught by `catch_panic`
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+
+/// A way of running a program
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Backend {
+    /// The interpreter, run within this process
+    Interpreter,
+
+    /// `karmac`, with the generated IR run by `lli`
+    Compiler,
+}
+
+pub struct Options {
+    pub paths: Vec<PathBuf>,
+    pub backends: Vec<Backend>,
+    pub dialect: Dialect,
+    pub passes: Arc<PassManager>,
+    pub eof: Eof,
+    pub limits: Limits,
+
+    /// Overwrite the expectations with the actual results
+    pub bless: bool,
+
+    /// How many tests run at the same time
+    pub jobs: usize,
+    pub timeout: Duration,
+
+    pub karmac: String,
+    pub lli: String,
+}
+
+/// What running a program resulted in
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Outcome {
+    pub output: Vec<u8>,
+    pub exit_code: i32,
+}
+
+/// A program together with its expected outcome
+struct Test {
+    source: PathBuf,
+    input: Vec<u8>,
+    expected: Outcome,
+}
+
+struct TestResult {
+    source: PathBuf,
+    backend: Backend,
+    result: Result<Outcome, String>,
+}
+
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Vec<Backend>> {
+        match name {
+            "interpreter" => Some(vec![Backend::Interpreter]),
+            "compiler" => Some(vec![Backend::Compiler]),
+            "all" => Some(vec![Backend::Interpreter, Backend::Compiler]),
+            _ => None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Backend::Interpreter => "interpreter",
+            Backend::Compiler => "compiler",
+        }
+    }
+}
+
+
+/// Run every test found and print a report. Returns whether all tests passed.
+///
+/// A test is a `.kar` file with a sibling `.out` or `.exitcode` file holding
+/// the expected output or exit code, which default to nothing and 0. A sibling
+/// `.in` file is used as input. Files named on the command line are tests even
+/// without expectations, so that `--bless` can create them.
+pub fn run_tests(options: &Options) -> io::Result<bool> {
+    let tests = find_tests(&options.paths)?;
+    let results = run_in_parallel(&tests, options);
+
+    let mut failed = 0;
+    for result in &results {
+        let test = tests.iter().find(|test| test.source == result.source).unwrap();
+
+        match result.result {
+            Ok(ref outcome) if options.bless => {
+                if result.backend == options.backends[0] {
+                    bless(&test.source, outcome)?;
+                    println!("blessed {}", test.source.display());
+                } else if *outcome != test.expected {
+                    println!("FAILED  {} ({}): differs from the blessed {}",
+                             test.source.display(), result.backend.name(), options.backends[0].name());
+                    failed += 1;
+                }
+            }
+
+            Ok(ref outcome) if *outcome == test.expected => {
+                println!("ok      {} ({})", test.source.display(), result.backend.name());
+            }
+
+            Ok(ref outcome) => {
+                println!("FAILED  {} ({})", test.source.display(), result.backend.name());
+                report_difference(&test.expected, outcome);
+                failed += 1;
+            }
+
+            Err(ref message) => {
+                println!("FAILED  {} ({}): {}", test.source.display(), result.backend.name(), message);
+                failed += 1;
+            }
+        }
+    }
+
+    println!();
+    println!("{} passed, {} failed", results.len() - failed, failed);
+
+    Ok(failed == 0)
+}
+
+
+fn find_tests(paths: &[PathBuf]) -> io::Result<Vec<Test>> {
+    let mut sources = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            find_sources(path, &mut sources)?;
+        } else {
+            sources.push(path.clone());
+        }
+    }
+
+    sources.sort();
+    sources.dedup();
+
+    let mut tests = Vec::new();
+    for source in sources {
+        let out = source.with_extension("out");
+        let exit_code = source.with_extension("exitcode");
+        let named = paths.contains(&source);
+
+        if !named && !out.exists() && !exit_code.exists() {
+            continue;
+        }
+
+        let exit_code = match fs::read_to_string(&exit_code) {
+            Ok(code) => code.trim().parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: invalid exit code", source.display()))
+            })?,
+            Err(_) => 0
+        };
+
+        tests.push(Test {
+            input: fs::read(source.with_extension("in")).unwrap_or_default(),
+            expected: Outcome { output: fs::read(&out).unwrap_or_default(), exit_code },
+            source,
+        });
+    }
+
+    Ok(tests)
+}
+
+fn find_sources(directory: &Path, sources: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            if path.file_name().is_some_and(|name| name != "target") {
+                find_sources(&path, sources)?;
+            }
+        } else if path.extension().is_some_and(|extension| extension == "kar") {
+            sources.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Run every test with every backend, sorted by test
+fn run_in_parallel(tests: &[Test], options: &Options) -> Vec<TestResult> {
+    let jobs: Vec<(usize, Backend)> = (0..tests.len())
+        .flat_map(|test| options.backends.iter().map(move |&backend| (test, backend)))
+        .collect();
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..options.jobs.max(1) {
+            scope.spawn(|| {
+                while let Some(&(index, backend)) = jobs.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let test = &tests[index];
+                    let result = run(&test.source, &test.input, backend, options);
+                    results.lock().unwrap().push((index, TestResult { source: test.source.clone(), backend, result }));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|&(index, ref result)| (index, result.backend.name()));
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+
+/// Run a program with a backend
+pub fn run(source: &Path, input: &[u8], backend: Backend, options: &Options) -> Result<Outcome, String> {
+    match backend {
+        Backend::Interpreter => interpret(source, input, options),
+        Backend::Compiler => compile_and_run(source, input, options),
+    }
+}
+
+fn interpret(source: &Path, input: &[u8], options: &Options) -> Result<Outcome, String> {
+    let sequences = parse_file_with_dialect(source, options.dialect).map_err(|e| format!("{:?}", e))?;
+    let mut output = Vec::new();
+
+    // the timeout stops the program like a time limit, but is a failure
+    let timeout = options.limits.time.is_none_or(|time| options.timeout < time);
+    let limits = if timeout {
+        Limits { time: Some(options.timeout), ..options.limits }
+    } else {
+        options.limits
+    };
+
+    let result = catch_panic(|| {
+        let (sequences, _) = options.passes.run(sequences);
+        execute_limited(&sequences, &mut Cursor::new(input), &mut output, options.eof, &limits, &mut ())
+    });
+
+    let exit_code = match result {
+        Ok(Ok(())) => 0,
+        Ok(Err(Trap::TimeLimit)) if timeout => {
+            return Err(format!("timed out after {} seconds", options.timeout.as_secs_f64()));
+        }
+        Ok(Err(trap)) => trap.exit_code(),
+        Err(_) => PANIC_EXIT_CODE,
+    };
+
+    Ok(Outcome { output, exit_code })
+}
+
+/// Run a function, returning a panic as the panic message instead of printing
+/// it. Panics on other threads are printed as usual.
+pub fn catch_panic<T, F: FnOnce() -> T>(function: F) -> Result<T, String> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !CATCHING.with(Cell::get) {
+                hook(info);
+            }
+        }));
+    });
+
+    CATCHING.with(|catching| catching.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(function));
+    CATCHING.with(|catching| catching.set(false));
+
+    result.map_err(|payload| {
+        payload.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default()
+    })
+}
+
+fn compile_and_run(source: &Path, input: &[u8], options: &Options) -> Result<Outcome, String> {
+    let mut command = Command::new(&options.karmac);
+    command.arg(source)
+        .arg(format!("--dialect={}", dialect_name(options.dialect)))
+        .arg(format!("--passes={}", options.passes.enabled().join(",")))
+        .arg(format!("--eof={}", eof_name(options.eof)))
+        .args(options.limits.flags());
+
+    let compiled = command.output().map_err(|e| format!("could not run {}: {}", options.karmac, e))?;
+    if !compiled.status.success() {
+        return Err(format!("{} failed: {}", options.karmac, String::from_utf8_lossy(&compiled.stderr).trim()));
+    }
+
+    let ir = temporary_path(source, "ll");
+    fs::write(&ir, &compiled.stdout).map_err(|e| e.to_string())?;
+
+    let result = run_with_timeout(Command::new(&options.lli).arg(&ir), input, options.timeout);
+    let _ = fs::remove_file(&ir);
+    result
+}
+
+
+/// Run a command with some input, killing it once the timeout is reached
+pub fn run_with_timeout(command: &mut Command, input: &[u8], timeout: Duration) -> Result<Outcome, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(&input);
+    });
+
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = io::copy(&mut stdout, &mut output);
+        output
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+
+        if start.elapsed() > timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("timed out after {} seconds", timeout.as_secs_f64()));
+        }
+
+        thread::sleep(Duration::from_millis(5));
+    };
+
+    let _ = writer.join();
+    let output = reader.join().unwrap_or_default();
+
+    Ok(Outcome { output, exit_code: status.code().unwrap_or(-1) })
+}
+
+/// A unique path in the temporary directory
+pub fn temporary_path(source: &Path, extension: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let stem = source.file_stem().and_then(|stem| stem.to_str()).unwrap_or("karma");
+    let name = format!("{}-{}-{}.{}", stem, std::process::id(), COUNTER.fetch_add(1, Ordering::SeqCst), extension);
+    env::temp_dir().join(name)
+}
+
+fn bless(source: &Path, outcome: &Outcome) -> io::Result<()> {
+    fs::write(source.with_extension("out"), &outcome.output)?;
+
+    let exit_code = source.with_extension("exitcode");
+    if outcome.exit_code != 0 {
+        fs::write(exit_code, format!("{}\n", outcome.exit_code))?;
+    } else if exit_code.exists() {
+        fs::remove_file(exit_code)?;
+    }
+
+    Ok(())
+}
+
+
+fn report_difference(expected: &Outcome, actual: &Outcome) {
+    if expected.exit_code != actual.exit_code {
+        println!("    exit code: expected {}, got {}", expected.exit_code, actual.exit_code);
+    }
+
+    if expected.output != actual.output {
+        let expected = String::from_utf8_lossy(&expected.output);
+        let actual = String::from_utf8_lossy(&actual.output);
+
+        for line in diff_lines(&expected, &actual) {
+            println!("    {}", line);
+        }
+    }
+}
+
+/// A line diff of two texts, based on their longest common subsequence
+pub fn diff_lines(expected: &str, actual: &str) -> Vec<String> {
+    let expected: Vec<&str> = expected.split('\n').collect();
+    let actual: Vec<&str> = actual.split('\n').collect();
+
+    // common[i][j] is the length of the longest common subsequence of the
+    // lines starting at i and j
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push(format!(" {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || common[i][j + 1] >= common[i + 1][j]) {
+            lines.push(format!("+{}", actual[j]));
+            j += 1;
+        } else {
+            lines.push(format!("-{}", expected[i]));
+            i += 1;
+        }
+    }
+
+    lines
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse_test_arguments;
+
+    fn options(paths: Vec<PathBuf>) -> Options {
+        Options { paths, ..parse_test_arguments(vec!["--jobs=4".to_owned()]).unwrap() }
+    }
+
+    #[test]
+    fn diff_changed_lines() {
+        assert_eq!(diff_lines("a\nb\nc", "a\nx\nc\nd"), vec![" a", "+x", "-b", " c", "+d"]);
+        assert_eq!(diff_lines("same", "same"), vec![" same"]);
+    }
+
+    #[test]
+    fn run_and_bless_golden_files() {
+        let directory = temporary_path(&PathBuf::from("golden"), "d");
+        fs::create_dir_all(directory.join("nested")).unwrap();
+
+        let echo = directory.join("echo.kar");
+        fs::write(&echo, "?:?:\n").unwrap();
+        fs::write(directory.join("echo.in"), "hi").unwrap();
+        fs::write(directory.join("echo.out"), "hi").unwrap();
+
+        let trap = directory.join("nested/trap.kar");
+        fs::write(&trap, "1;05/\n").unwrap();
+        fs::write(directory.join("nested/trap.out"), "2").unwrap();
+
+        // not a test, since it has no expectations
+        fs::write(directory.join("other.kar"), "1;\n").unwrap();
+
+        assert!(!run_tests(&options(vec![directory.clone()])).unwrap());
+
+        let mut bless = options(vec![directory.clone()]);
+        bless.bless = true;
+        assert!(run_tests(&bless).unwrap());
+
+        assert_eq!(fs::read_to_string(directory.join("nested/trap.out")).unwrap(), "1");
+        assert_eq!(fs::read_to_string(directory.join("nested/trap.exitcode")).unwrap(), "15\n");
+        assert!(!directory.join("other.out").exists());
+        assert!(run_tests(&options(vec![directory.clone()])).unwrap());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn time_out_endless_programs() {
+        let source = temporary_path(&PathBuf::from("endless"), "kar");
+        fs::write(&source, "<\n").unwrap();
+
+        let mut options = options(vec![source.clone()]);
+        options.timeout = Duration::from_millis(100);
+
+        let result = run(&source, b"", Backend::Interpreter, &options);
+        assert!(result.unwrap_err().starts_with("timed out"));
+
+        // a shorter time limit is part of the outcome
+        options.limits.time = Some(Duration::from_millis(10));
+        let outcome = run(&source, b"", Backend::Interpreter, &options).unwrap();
+        assert_eq!(outcome, Outcome { output: Vec::new(), exit_code: 19 });
+
+        fs::remove_file(source).unwrap();
+    }
+
+    #[test]
+    fn catch_panics_of_one_thread() {
+        assert_eq!(catch_panic(|| 1), Ok(1));
+        assert_eq!(catch_panic(|| -> () { panic!("boom") }), Err("boom".to_owned()));
+        assert_eq!(catch_panic(|| -> () { panic!("{}", 2) }), Err("2".to_owned()));
+    }
+
+    #[test]
+    fn handle_end_of_input() {
+        let source = temporary_path(&PathBuf::from("eof"), "kar");
+        fs::write(&source, "?;?;\n").unwrap();
+
+        let run = |eof: &str| {
+            let options = parse_test_arguments(vec![format!("--eof={}", eof)]).unwrap();
+            run(&source, b"A", Backend::Interpreter, &options).unwrap()
+        };
+
+        assert_eq!(run("-1"), Outcome { output: b"65-1".to_vec(), exit_code: 0 });
+        assert_eq!(run("0"), Outcome { output: b"650".to_vec(), exit_code: 0 });
+        assert_eq!(run("error"), Outcome { output: b"65".to_vec(), exit_code: 17 });
+        assert!(parse_test_arguments(vec!["--eof=never".to_owned()]).is_err());
+
+        fs::remove_file(source).unwrap();
+    }
+
+    #[test]
+    fn stop_endless_tests_at_limits() {
+        let source = temporary_path(&PathBuf::from("limited"), "kar");
+        fs::write(&source, "1;<\n").unwrap();
+
+        let options = parse_test_arguments(vec!["--max-output=3".to_owned(), "--max-steps=1000".to_owned()]).unwrap();
+        assert_eq!(options.limits.flags(), vec!["--max-steps=1000", "--max-output=3"]);
+
+        let outcome = run(&source, b"", Backend::Interpreter, &options).unwrap();
+        assert_eq!(outcome, Outcome { output: b"111".to_vec(), exit_code: 22 });
+        assert!(parse_test_arguments(vec!["--max-time=soon".to_owned()]).is_err());
+
+        fs::remove_file(source).unwrap();
+    }
+
+    #[test]
+    fn check_examples() {
+        let examples = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../karma");
+        assert!(run_tests(&options(vec![examples])).unwrap());
+    }
+}
@@ -0,0 +1,428 @@
+extern crate karma_parser;
+extern crate karma_interpreter;
+extern crate serde_json;
+
+
+use std::{
+    env,
+    path::PathBuf,
+    process::exit,
+    sync::Arc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use karma_parser::optimize::PassManager;
+
+mod check;
+mod common;
+mod coverage;
+mod differential;
+mod fmt;
+mod golden;
+mod repl;
+mod run;
+mod trace;
+
+use common::Common;
+use golden::{Backend, Options};
+
+
+/// A subcommand of `karma`
+struct Command {
+    name: &'static str,
+    summary: &'static str,
+    usage: &'static str,
+
+    /// Returns the exit code, or a message for exit code 2
+    run: fn(Vec<String>) -> Result<i32, String>,
+}
+
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "run",
+        summary: "Run a program",
+        usage: "\
+Usage: karma run [options] <FILE | ->
+
+Runs a program with the interpreter, or with karmac and lli. The program reads
+standard input unless it was read from there itself.
+
+Options:
+    --backend=<name>         interpreter (default) or compiler
+    --input=<file>           Read the program's input from a file
+    --coverage=<file>        Add the sections that ran and the outcomes of
+                             skips to a file, without optimizing
+",
+        run: run::run,
+    },
+
+    Command {
+        name: "build",
+        summary: "Compile a program to an executable",
+        usage: "\
+Usage: karma build [options] <FILE | -> [-o <executable>]
+
+Compiles a program with karmac, llc ($LLC) and cc ($CC). The executable is
+named after the source file by default.
+",
+        run: run::build,
+    },
+
+    Command {
+        name: "emit-ir",
+        summary: "Print the LLVM IR of a program",
+        usage: "\
+Usage: karma emit-ir [options] <FILE | -> [-o <file>]
+
+Writes the LLVM IR karmac generates to standard output or a file.
+",
+        run: run::emit_ir,
+    },
+
+    Command {
+        name: "fmt",
+        summary: "Format source files",
+        usage: "\
+Usage: karma fmt [--check] [--dialect=<name>] [FILE | -]...
+
+Formats files in place, or standard input to standard output.
+
+Options:
+    --check                  Only list the files that are not formatted
+",
+        run: fmt::fmt,
+    },
+
+    Command {
+        name: "check",
+        summary: "Report parse errors and likely mistakes",
+        usage: "\
+Usage: karma check [--dialect=<name>] <FILE | ->...
+
+Prints the diagnostics of karmalint for every file.
+",
+        run: check::check,
+    },
+
+    Command {
+        name: "test",
+        summary: "Run golden-file tests",
+        usage: "\
+Usage: karma test [options] [PATH]...
+
+Runs every .kar file that has a sibling .out or .exitcode file, with input
+from a sibling .in file, and compares the output and exit code.
+
+Options:
+    --bless                  Write the actual results as expectations
+    --backend=<name>         interpreter (default), compiler or all
+    --jobs=<n>               Number of tests run at the same time
+    --timeout=<seconds>      Time a test may take (default 10)
+",
+        run: test,
+    },
+
+    Command {
+        name: "bench",
+        summary: "Time a program",
+        usage: "\
+Usage: karma bench [options] <FILE | ->
+
+Runs a program several times with its output thrown away, and prints how long
+each run took.
+
+Options:
+    --backend=<name>         interpreter (default) or compiler
+    --input=<file>           Read the program's input from a file
+    --runs=<n>               How often to run the program (default 10)
+",
+        run: run::bench,
+    },
+
+    Command {
+        name: "fuzz",
+        summary: "Compare backends on randomly generated programs",
+        usage: "\
+Usage: karma fuzz [options]
+
+Generates random programs and inputs, and runs them with the unoptimized
+interpreter and with another backend. The first program they disagree on is
+shrunk to a small reproducer.
+
+Options:
+    --programs=<n>           How many programs to try (default 1000)
+    --seed=<n>               The seed of the first program (default: random)
+    --lines=<n>              How many lines a program has at most (default 6)
+    --allow-eof              Let programs run out of input
+    --backend=<name>         compiler (default), or interpreter to compare
+                             with the optimized interpreter
+    --timeout=<seconds>      Time a program may take (default 10)
+    --save=<directory>       Write the reproducer and its input there
+",
+        run: fuzz,
+    },
+
+    Command {
+        name: "repl",
+        summary: "Run lines as they are entered",
+        usage: "\
+Usage: karma repl [options] [FILE]...
+
+Runs every line as it is entered, keeping the stack and deque between lines,
+and shows them afterwards. The files are loaded first. Optimization passes are
+not run, and programs read their input from the prompt.
+
+Commands:
+    :stack, :deque           Show the values, with their characters
+    :reset                   Forget all lines and values
+    :load <file>             Append the lines of a file and run the first one
+    :save <file>             Write the lines entered so far to a file
+    :help, :quit
+",
+        run: repl::repl,
+    },
+
+    Command {
+        name: "coverage",
+        summary: "Show which sections and skips ran",
+        usage: "\
+Usage: karma coverage [options] <FILE | -> <COVERAGE>...
+
+Adds up coverage files written by `karma run --coverage=`, `karmai
+--coverage=` or programs compiled with `karmac --coverage=`, and prints the
+program with how often each line and section ran, `#####` for sections that
+never did, and how often every skip skipped the instruction after it.
+
+Options:
+    --lcov=<file>            Also write an lcov tracefile, or only print it
+                             for -
+",
+        run: coverage::coverage,
+    },
+
+    Command {
+        name: "trace-diff",
+        summary: "Find where two traces diverge",
+        usage: "\
+Usage: karma trace-diff [options] <TRACE> <TRACE>
+
+Compares two traces written by `karmai --trace=` record by record, and prints
+the first records that differ and in which fields. Exits with 1 if they do.
+
+Options:
+    --ignore=<a>,<b>         Fields to leave out of the comparison, such as
+                             span or section for differently optimized runs
+",
+        run: trace::trace_diff,
+    },
+];
+
+
+const COMMON_USAGE: &str = "\
+Common options:
+    --dialect=<name>         standard (default) or literals
+    -O<level>, --passes=<a>,<b>, --disable-pass=<a>
+                             Optimization passes to run (default -O2)
+    --stats                  Print what each optimization pass changed
+    --validate               Check that the passes keep the program's meaning
+    --eof=<error | value>    What reading past the end of input does: stop
+                             with exit code 17, or read a value (default -1)
+    --max-steps=<n>, --max-time=<seconds>, --max-stack=<n>, --max-deque=<n>,
+    --max-output=<bytes>
+                             Stop the program once it goes past a limit
+    --karmac=<path>          The compiler (default: karmac, or $KARMAC)
+    --lli=<path>             The LLVM interpreter (default: lli, or $LLI)
+";
+
+const EXIT_CODES: &str = "\
+Exit codes:
+    0                        Success
+    1                        Failed tests or checks, or unformatted files
+    2                        Invalid arguments, or a file that cannot be read
+                             or parsed
+    13, 14                   The program ran out of values in the deque or
+                             stack
+    15, 16, 17               The program divided by zero, overflowed a
+                             division or read past the end of input
+    18, 19, 20, 21, 22       The program went past the limit of steps, time,
+                             stack, deque or output
+    130                      The program was interrupted
+";
+
+
+fn main() {
+    let mut arguments: Vec<String> = env::args().skip(1).collect();
+    if arguments.is_empty() {
+        eprint!("{}", usage());
+        exit(2);
+    }
+
+    let name = arguments.remove(0);
+    if name == "help" || name == "--help" || name == "-h" {
+        match arguments.first().and_then(|name| find_command(name)) {
+            Some(command) => print!("{}\n{}", command.usage, COMMON_USAGE),
+            None => print!("{}", usage()),
+        }
+        return;
+    }
+
+    let command = match find_command(&name) {
+        Some(command) => command,
+        None => {
+            eprint!("Unknown command: {}\n\n{}", name, usage());
+            exit(2);
+        }
+    };
+
+    if arguments.iter().any(|argument| argument == "--help" || argument == "-h") {
+        print!("{}\n{}", command.usage, COMMON_USAGE);
+        return;
+    }
+
+    match (command.run)(arguments) {
+        Ok(code) => exit(code),
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            exit(2);
+        }
+    }
+}
+
+
+fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+fn usage() -> String {
+    let mut usage = "Usage: karma <command> [options]\n\nCommands:\n".to_owned();
+    for command in COMMANDS {
+        usage += &format!("    {:<12} {}\n", command.name, command.summary);
+    }
+
+    usage + "\nRun `karma help <command>` for the options of a command.\n\n" + EXIT_CODES
+}
+
+
+fn test(arguments: Vec<String>) -> Result<i32, String> {
+    let options = parse_test_arguments(arguments)?;
+
+    match golden::run_tests(&options) {
+        Ok(true) => Ok(0),
+        Ok(false) => Ok(1),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_test_arguments(arguments: Vec<String>) -> Result<Options, String> {
+    let mut common = Common::new();
+    let mut paths = Vec::new();
+    let mut backends = vec![Backend::Interpreter];
+    let mut bless = false;
+    let mut jobs = thread::available_parallelism().map_or(1, |jobs| jobs.get());
+    let mut timeout = Duration::from_secs(10);
+
+    for argument in arguments {
+        if common.parse_flag(&argument)? {
+            continue;
+        } else if argument == "--bless" {
+            bless = true;
+        } else if let Some(name) = argument.strip_prefix("--backend=") {
+            backends = Backend::from_name(name).ok_or_else(|| format!("Unknown backend: {}", name))?;
+        } else if let Some(count) = argument.strip_prefix("--jobs=") {
+            jobs = count.parse().map_err(|_| format!("Invalid number of jobs: {}", count))?;
+        } else if let Some(seconds) = argument.strip_prefix("--timeout=") {
+            timeout = parse_timeout(seconds)?;
+        } else if argument.starts_with('-') {
+            return Err(format!("Unknown option: {}", argument));
+        } else {
+            paths.push(PathBuf::from(argument));
+        }
+    }
+
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
+
+    Ok(Options {
+        paths,
+        backends,
+        dialect: common.dialect,
+        passes: Arc::new(common.passes),
+        eof: common.eof,
+        limits: common.limits,
+        bless,
+        jobs,
+        timeout,
+        karmac: common.karmac,
+        lli: common.lli,
+    })
+}
+
+
+fn fuzz(arguments: Vec<String>) -> Result<i32, String> {
+    let options = parse_fuzz_arguments(arguments)?;
+
+    match differential::run(&options) {
+        Ok(true) => Ok(0),
+        Ok(false) => Ok(1),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_fuzz_arguments(arguments: Vec<String>) -> Result<differential::Options, String> {
+    let mut common = Common::new();
+    let mut options = differential::Options {
+        programs: 1000,
+        seed: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()),
+        lines: 6,
+        allow_eof: false,
+        eof: common.eof,
+        backend: Backend::Compiler,
+        passes: Arc::new(PassManager::default()),
+        timeout: Duration::from_secs(10),
+        karmac: String::new(),
+        lli: String::new(),
+        save: None,
+    };
+
+    for argument in arguments {
+        if common.parse_flag(&argument)? {
+            continue;
+        } else if argument == "--allow-eof" {
+            options.allow_eof = true;
+        } else if let Some(count) = argument.strip_prefix("--programs=") {
+            options.programs = count.parse().map_err(|_| format!("Invalid number of programs: {}", count))?;
+        } else if let Some(seed) = argument.strip_prefix("--seed=") {
+            options.seed = seed.parse().map_err(|_| format!("Invalid seed: {}", seed))?;
+        } else if let Some(lines) = argument.strip_prefix("--lines=") {
+            options.lines = lines.parse().map_err(|_| format!("Invalid number of lines: {}", lines))?;
+        } else if let Some(name) = argument.strip_prefix("--backend=") {
+            options.backend = match name {
+                "compiler" => Backend::Compiler,
+                "interpreter" => Backend::Interpreter,
+                _ => return Err(format!("Unknown backend: {}", name)),
+            };
+        } else if let Some(seconds) = argument.strip_prefix("--timeout=") {
+            options.timeout = parse_timeout(seconds)?;
+        } else if let Some(directory) = argument.strip_prefix("--save=") {
+            options.save = Some(PathBuf::from(directory));
+        } else {
+            return Err(format!("Unknown option: {}", argument));
+        }
+    }
+
+    options.eof = common.eof;
+    options.passes = Arc::new(common.passes);
+    options.karmac = common.karmac;
+    options.lli = common.lli;
+    Ok(options)
+}
+
+fn parse_timeout(seconds: &str) -> Result<Duration, String> {
+    seconds.parse().ok()
+        .filter(|seconds: &f64| *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("Invalid timeout: {}", seconds))
+}
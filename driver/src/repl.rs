@@ -0,0 +1,374 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    sync::atomic::Ordering,
+};
+
+use karma_parser::{
+    Sequence,
+    Dialect,
+    Eof,
+    Instruction,
+    parse_str_with_dialect,
+};
+use karma_interpreter::execution::{State, DataType, INTERRUPTED};
+#[cfg(unix)]
+use karma_interpreter::interrupt;
+
+use common::Common;
+
+
+const HELP: &str = "\
+Every line is appended to the program and run from its start, with the stack
+and deque left by the lines before. Jumps reach the lines entered earlier, and
+jumping past the last line ends the run. Ctrl-C stops a line that keeps running.
+
+Commands:
+    :stack                   Show the stack, top first
+    :deque                   Show the deque, front first
+    :reset                   Forget all lines and values
+    :load <file>             Append the lines of a file and run the first one
+    :save <file>             Write the lines entered so far to a file
+    :help                    Show this help
+    :quit                    Leave, like the end of input
+";
+
+
+/// `karma repl`: run lines as they are entered
+pub fn repl(arguments: Vec<String>) -> Result<i32, String> {
+    let mut common = Common::new();
+    let mut paths = Vec::new();
+
+    for argument in arguments {
+        if common.parse_flag(&argument)? {
+            continue;
+        } else if !argument.starts_with('-') {
+            paths.push(argument);
+        } else {
+            return Err(format!("Unknown option: {}", argument));
+        }
+    }
+
+    #[cfg(unix)]
+    interrupt::catch();
+
+    let mut repl = Repl::new(common.dialect, common.eof);
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut output = stdout.lock();
+
+    for path in paths {
+        repl.handle(&format!(":load {}", path), &mut input, &mut output).map_err(|e| e.to_string())?;
+    }
+
+    repl.run(&mut input, &mut output).map_err(|e| e.to_string())?;
+    Ok(0)
+}
+
+
+/// The lines entered so far, and what they left behind
+pub struct Repl {
+    dialect: Dialect,
+    eof: Eof,
+
+    lines: Vec<String>,
+    sequences: Vec<Sequence>,
+    state: State,
+}
+
+/// Passes the output of a line through as it is printed, so that a prompt shows
+/// before the line waits for its answer
+struct Printed<'a> {
+    output: &'a mut dyn Write,
+
+    /// The last byte printed
+    last: Option<u8>,
+}
+
+
+impl Repl {
+    pub fn new(dialect: Dialect, eof: Eof) -> Repl {
+        Repl {
+            dialect,
+            eof,
+            lines: Vec::new(),
+            sequences: vec![vec![vec![Instruction::Exit]]; 2],
+            state: State::new(),
+        }
+    }
+
+    /// Prompt for lines until the input ends or `:quit` is entered. The
+    /// program reads its input from the same place.
+    pub fn run(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+        loop {
+            write!(output, "> ")?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(());
+            }
+
+            if !self.handle(line.trim_end_matches(&['\r', '\n'][..]), input, output)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Run a line or a command, `false` once the session should end. Lines
+    /// that do not start with the name of a command, such as `:` or `:,`, are
+    /// Karma.
+    pub fn handle(&mut self, line: &str, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<bool> {
+        let (command, argument) = match line.find(' ') {
+            Some(index) => (&line[..index], line[index + 1..].trim()),
+            None => (line, ""),
+        };
+
+        match command {
+            ":stack" => show_values(self.state.stack.iter().rev(), output)?,
+            ":deque" => show_values(self.state.deque.iter(), output)?,
+
+            ":reset" => *self = Repl::new(self.dialect, self.eof),
+
+            ":load" if !argument.is_empty() => {
+                let first = self.sequences.len() - 1;
+                let loaded = fs::read_to_string(argument)
+                    .map_err(|e| format!("{}: {}", argument, e))
+                    .and_then(|text| self.append(&text.lines().map(str::to_owned).collect::<Vec<_>>()));
+
+                match loaded {
+                    Ok(()) if first < self.sequences.len() - 1 => self.execute(first, input, output)?,
+                    Ok(()) => {}
+                    Err(message) => writeln!(output, "Error: {}", message)?,
+                }
+            }
+
+            ":save" if !argument.is_empty() => {
+                let mut text = self.lines.join("\n");
+                text.push('\n');
+
+                if let Err(e) = fs::write(argument, text) {
+                    writeln!(output, "Error: {}: {}", argument, e)?;
+                }
+            }
+
+            ":help" => write!(output, "{}", HELP)?,
+            ":quit" => return Ok(false),
+
+            ":load" | ":save" => writeln!(output, "Error: {} needs a file", command)?,
+            _ => self.enter(line, input, output)?,
+        }
+
+        Ok(true)
+    }
+
+    /// Append a line of Karma and run it
+    fn enter(&mut self, line: &str, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+
+        let first = self.sequences.len() - 1;
+        match self.append(&[line.to_owned()]) {
+            Ok(()) => self.execute(first, input, output),
+            Err(message) => writeln!(output, "Error: {}", message),
+        }
+    }
+
+
+    /// Parse lines and add them in front of the sentinel that ends the
+    /// program, or nothing if one of them does not parse. Lines after a blank
+    /// line are not part of the program and are left out.
+    fn append(&mut self, lines: &[String]) -> Result<(), String> {
+        let mut sequences = parse_str_with_dialect(&lines.join("\n"), self.dialect)
+            .map_err(|e| format!("{:?}", e))?;
+
+        // keep the lines between the sentinels
+        sequences.pop();
+        sequences.remove(0);
+
+        let sentinel = self.sequences.pop().unwrap();
+        self.lines.extend(lines.iter().take(sequences.len()).cloned());
+        self.sequences.extend(sequences);
+        self.sequences.push(sentinel);
+
+        Ok(())
+    }
+
+    /// Run from the start of a line and show what it left behind. Ctrl-C
+    /// before the line starts is forgotten.
+    fn execute(&mut self, sequence: usize, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+        let mut printed = Printed { output, last: None };
+
+        INTERRUPTED.store(false, Ordering::Relaxed);
+        self.state.enter(sequence);
+        let result = self.state.run(&self.sequences, &mut &mut *input, &mut printed, self.eof);
+        INTERRUPTED.store(false, Ordering::Relaxed);
+
+        let output = printed.output;
+        if printed.last.is_some_and(|byte| byte != b'\n') {
+            writeln!(output)?;
+        }
+
+        if let Err(trap) = result {
+            writeln!(output, "Error: {}", trap)?;
+        }
+
+        writeln!(output, "stack: {}", join(self.state.stack.iter().rev()))?;
+        writeln!(output, "deque: {}", join(self.state.deque.iter()))
+    }
+}
+
+
+impl Write for Printed<'_> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.output.write_all(bytes)?;
+        self.output.flush()?;
+
+        if let Some(&last) = bytes.last() {
+            self.last = Some(last);
+        }
+
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+
+fn join<'a, I: Iterator<Item = &'a DataType>>(values: I) -> String {
+    values.map(|value| value.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+/// One value per line, with its character if it has a visible one
+fn show_values<'a, I: Iterator<Item = &'a DataType>>(values: I, output: &mut dyn Write) -> io::Result<()> {
+    for value in values {
+        match *value {
+            32..=126 => writeln!(output, "{} '{}'", value, *value as u8 as char)?,
+            _ => writeln!(output, "{}", value)?,
+        }
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, fs, path::PathBuf, rc::Rc};
+    use golden;
+
+    fn repl_session(lines: &str) -> String {
+        let mut output = Vec::new();
+        Repl::new(Default::default(), Eof::default()).run(&mut lines.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn keep_values_between_repl_lines() {
+        assert_eq!(repl_session("12\n3}\n:stack\n:deque\n+;\n"),
+                   "> stack: 2 1\ndeque: \n\
+                    > stack: 2 1\ndeque: 3\n\
+                    > 2\n1\n\
+                    > 3\n\
+                    > 3\nstack: \ndeque: 3\n\
+                    > \n");
+
+        assert_eq!(repl_session("48*\n:stack\n:reset\n:stack\n:quit\n1\n"),
+                   "> stack: 32\ndeque: \n> 32 ' '\n> > > ");
+    }
+
+    #[test]
+    fn run_repl_lines_that_start_with_a_colon() {
+        assert_eq!(repl_session("48*\n:\n48*\n:,\n"),
+                   "> stack: 32\ndeque: \n\
+                    >  \nstack: \ndeque: \n\
+                    > stack: 32\ndeque: \n\
+                    >  \nstack: \ndeque: \n\
+                    > \n");
+    }
+
+    #[test]
+    fn jump_between_repl_lines() {
+        // the second line runs the first one again, and the input is read from the prompt
+        assert_eq!(repl_session("7;\n'\n?:?:\nhi\n,\n"),
+                   "> 7\nstack: \ndeque: \n\
+                    > 7\nstack: \ndeque: \n\
+                    > hi\nstack: \ndeque: \n\
+                    > > stack: \ndeque: \n\
+                    > \n");
+    }
+
+    #[test]
+    fn print_repl_output_before_reading() {
+        /// Input that remembers what had been printed when it was read
+        struct Answer {
+            printed: Rc<RefCell<Vec<u8>>>,
+            seen: Vec<u8>,
+        }
+
+        impl io::Read for Answer {
+            fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+                self.seen = self.printed.borrow().clone();
+                buffer[0] = b'x';
+                Ok(1)
+            }
+        }
+
+        struct Shared(Rc<RefCell<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+                self.0.borrow_mut().write(bytes)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let printed = Rc::new(RefCell::new(Vec::new()));
+        let mut input = io::BufReader::new(Answer { printed: printed.clone(), seen: Vec::new() });
+        let mut repl = Repl::new(Default::default(), Eof::default());
+
+        repl.handle("1;?;", &mut input, &mut Shared(printed.clone())).unwrap();
+        assert_eq!(input.get_ref().seen, b"1");
+        assert_eq!(*printed.borrow(), b"1120\nstack: \ndeque: \n");
+    }
+
+    #[test]
+    fn report_repl_errors() {
+        assert_eq!(repl_session("1\n##\n1@\n:load\n"),
+                   "> stack: 1\ndeque: \n\
+                    > Error: the stack is empty\nstack: \ndeque: \n\
+                    > Error: TrailingSkip(1)\n\
+                    > Error: :load needs a file\n\
+                    > \n");
+    }
+
+    #[test]
+    fn load_and_save_repl_lines() {
+        let path = golden::temporary_path(&PathBuf::from("repl"), "kar");
+        let path = path.to_str().unwrap();
+
+        let output = repl_session(&format!("22,\n+;\n:save {}\n:reset\n:load {}\n", path, path));
+        assert_eq!(fs::read_to_string(path).unwrap(), "22,\n+;\n");
+        assert_eq!(output, "> stack: 2 2\ndeque: \n\
+                            > 4\nstack: \ndeque: \n\
+                            > > > 4\nstack: \ndeque: \n\
+                            > \n");
+
+        // the blank line ends the program, so the line after it is not kept
+        fs::write(path, "1;\n\n2;\n").unwrap();
+        let output = repl_session(&format!(":load {}\n:save {}\n", path, path));
+        assert_eq!(fs::read_to_string(path).unwrap(), "1;\n");
+        assert_eq!(output, "> 1\nstack: \ndeque: \n> > \n");
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use karma_interpreter::execution::{execute, execute_limited};
+use karma_interpreter::coverage::Coverage;
+use karma_interpreter::buffered::{Buffered, TimedInput};
+use karma_parser::optimize::PassManager;
+
+use common::{Common, Source};
+use golden::{self, Backend};
+
+
+/// `karma run`
+pub fn run(arguments: Vec<String>) -> Result<i32, String> {
+    let arguments = parse_arguments(arguments, &["--coverage"])?;
+    let source = Source::read(&arguments.path)?;
+
+    match arguments.backend {
+        Backend::Interpreter => {
+            let sequences = arguments.common.prepare(&source)?;
+            let reads_terminal = arguments.input.is_none() && io::stdin().is_terminal();
+            let mut input = program_input(&arguments.input, &source)?;
+            if let Some(time) = arguments.common.limits.time {
+                input = Box::new(TimedInput::new(input, Instant::now() + time));
+            }
+
+            let stdio = Buffered::new(input, io::stdout().lock())
+                .with_flush_lines(io::stdout().is_terminal())
+                .with_flush_before_read(reads_terminal);
+
+            let mut coverage = arguments.coverage.as_ref().map(|_| Coverage::new(&sequences));
+            let result = execute_limited(&sequences, &mut &stdio, &mut &stdio, arguments.common.eof,
+                                         &arguments.common.limits, &mut coverage);
+            stdio.flush().map_err(|e| e.to_string())?;
+
+            if let (Some(coverage), Some(path)) = (coverage, arguments.coverage.as_ref()) {
+                let file = OpenOptions::new().create(true).append(true).open(path);
+                file.and_then(|mut file| coverage.write(&sequences, &mut file)).map_err(|e| format!("{}: {}", path, e))?;
+            }
+
+            match result {
+                Ok(()) => Ok(0),
+                Err(trap) => {
+                    eprintln!("Error: {}", trap);
+                    Ok(trap.exit_code())
+                }
+            }
+        }
+
+        Backend::Compiler => {
+            let extra: Vec<String> = arguments.coverage.iter().map(|path| format!("--coverage={}", path)).collect();
+            let ir = temporary_file(&source, "ll", &arguments.common.compile(&source, &extra)?)?;
+
+            let input = match arguments.input {
+                Some(ref path) => Stdio::from(File::open(path).map_err(|e| format!("{}: {}", path, e))?),
+                None if source.is_stdin() => Stdio::null(),
+                None => Stdio::inherit(),
+            };
+
+            let status = Command::new(&arguments.common.lli).arg(&ir).stdin(input).status();
+            let _ = fs::remove_file(&ir);
+
+            let status = status.map_err(|e| format!("could not run {}: {}", arguments.common.lli, e))?;
+            Ok(status.code().unwrap_or(1))
+        }
+    }
+}
+
+/// `karma emit-ir`
+pub fn emit_ir(arguments: Vec<String>) -> Result<i32, String> {
+    let arguments = parse_arguments(arguments, &["-o"])?;
+    let source = Source::read(&arguments.path)?;
+    let ir = arguments.common.compile(&source, &[])?;
+
+    match arguments.output {
+        Some(ref path) if path != "-" => fs::write(path, ir).map_err(|e| format!("{}: {}", path, e))?,
+        _ => io::stdout().write_all(&ir).map_err(|e| e.to_string())?,
+    }
+
+    Ok(0)
+}
+
+/// `karma build`: compile a program to an executable with `llc` and `cc`
+pub fn build(arguments: Vec<String>) -> Result<i32, String> {
+    let arguments = parse_arguments(arguments, &["-o"])?;
+    let source = Source::read(&arguments.path)?;
+
+    let output = match arguments.output {
+        Some(ref path) => PathBuf::from(path),
+        None => match source.path {
+            Some(ref path) => PathBuf::from(path.file_stem().unwrap_or_else(|| "a.out".as_ref())),
+            None => PathBuf::from("a.out"),
+        }
+    };
+
+    let ir = temporary_file(&source, "ll", &arguments.common.compile(&source, &[])?)?;
+    let object = ir.with_extension("o");
+
+    let llc = env::var("LLC").unwrap_or_else(|_| "llc".to_owned());
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+
+    let result = run_tool(Command::new(&llc).arg("-O3").arg("-filetype=obj").arg("-o").arg(&object).arg(&ir))
+        .and_then(|_| run_tool(Command::new(&cc).arg("-no-pie").arg(&object).arg("-o").arg(&output)));
+
+    let _ = fs::remove_file(&ir);
+    let _ = fs::remove_file(&object);
+
+    result.map(|_| 0)
+}
+
+/// `karma bench`: time how long a program takes over several runs
+pub fn bench(arguments: Vec<String>) -> Result<i32, String> {
+    let arguments = parse_arguments(arguments, &["--runs"])?;
+    let source = Source::read(&arguments.path)?;
+
+    let mut input = Vec::new();
+    program_input(&arguments.input, &source)?.read_to_end(&mut input).map_err(|e| e.to_string())?;
+
+    let mut times = Vec::new();
+    match arguments.backend {
+        Backend::Interpreter => {
+            let sequences = arguments.common.prepare(&source)?;
+
+            for _ in 0..arguments.runs {
+                let start = Instant::now();
+                let result = execute(&sequences, &mut &input[..], &mut io::sink(), arguments.common.eof);
+                times.push(start.elapsed());
+
+                if let Err(trap) = result {
+                    return Err(format!("the program stopped: {}", trap));
+                }
+            }
+        }
+
+        Backend::Compiler => {
+            let ir = temporary_file(&source, "ll", &arguments.common.compile(&source, &[])?)?;
+
+            for _ in 0..arguments.runs {
+                let start = Instant::now();
+                let result = golden::run_with_timeout(Command::new(&arguments.common.lli).arg(&ir), &input, Duration::MAX);
+                times.push(start.elapsed());
+
+                if let Err(message) = result {
+                    let _ = fs::remove_file(&ir);
+                    return Err(message);
+                }
+            }
+
+            let _ = fs::remove_file(&ir);
+        }
+    }
+
+    for (run, time) in times.iter().enumerate() {
+        println!("run {}: {:.6} seconds", run + 1, time.as_secs_f64());
+    }
+
+    let total: Duration = times.iter().sum();
+    let fastest = times.iter().min().cloned().unwrap_or_default();
+    println!("mean: {:.6} seconds, fastest: {:.6} seconds",
+             total.as_secs_f64() / times.len().max(1) as f64, fastest.as_secs_f64());
+
+    Ok(0)
+}
+
+
+struct Arguments {
+    common: Common,
+    path: String,
+    backend: Backend,
+
+    /// The program's input, standard input by default
+    input: Option<String>,
+
+    /// `-o`
+    output: Option<String>,
+
+    /// `--runs`
+    runs: usize,
+
+    /// `--coverage`
+    coverage: Option<String>,
+}
+
+
+/// Parse the arguments of a command that runs a single program, which takes
+/// common flags, `--input=`, `--backend=` and the `extra` flags
+fn parse_arguments(arguments: Vec<String>, extra: &[&str]) -> Result<Arguments, String> {
+    let mut common = Common::new();
+    let mut path = None;
+    let mut backend = Backend::Interpreter;
+    let mut input = None;
+    let mut output = None;
+    let mut runs = 10;
+    let mut coverage = None;
+
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        if common.parse_flag(&argument)? {
+            continue;
+        } else if let Some(file) = argument.strip_prefix("--input=") {
+            input = Some(file.to_owned());
+        } else if let Some(name) = argument.strip_prefix("--backend=") {
+            backend = match name {
+                "interpreter" => Backend::Interpreter,
+                "compiler" => Backend::Compiler,
+                _ => return Err(format!("Unknown backend: {}", name)),
+            };
+        } else if argument == "-o" && extra.contains(&"-o") {
+            output = Some(arguments.next().ok_or("-o needs a file")?);
+        } else if let (Some(count), true) = (argument.strip_prefix("--runs="), extra.contains(&"--runs")) {
+            runs = count.parse().map_err(|_| format!("Invalid number of runs: {}", count))?;
+        } else if let (Some(file), true) = (argument.strip_prefix("--coverage="), extra.contains(&"--coverage")) {
+            coverage = Some(file.to_owned());
+        } else if argument == "-" || !argument.starts_with('-') {
+            if path.is_some() {
+                return Err(format!("Unexpected argument: {}", argument));
+            }
+            path = Some(argument);
+        } else {
+            return Err(format!("Unknown option: {}", argument));
+        }
+    }
+
+    // coverage is recorded for the sections as they are written
+    if coverage.is_some() {
+        common.passes = PassManager::new(0);
+    }
+
+    let path = path.ok_or("No source file given, use - for standard input")?;
+    Ok(Arguments { common, path, backend, input, output, runs, coverage })
+}
+
+
+/// The input of a program: a file, or standard input unless the program
+/// itself was read from there
+fn program_input(input: &Option<String>, source: &Source) -> Result<Box<dyn Read + Send>, String> {
+    match *input {
+        Some(ref path) => Ok(Box::new(File::open(path).map_err(|e| format!("{}: {}", path, e))?)),
+        None if source.is_stdin() => Ok(Box::new(io::empty())),
+        None => Ok(Box::new(io::stdin())),
+    }
+}
+
+fn temporary_file(source: &Source, extension: &str, contents: &[u8]) -> Result<PathBuf, String> {
+    let path = golden::temporary_path(source.path.as_ref().map_or(Path::new("stdin"), |path| path), extension);
+    fs::write(&path, contents).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+fn run_tool(command: &mut Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command.status().map_err(|e| format!("could not run {}: {}", program, e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} failed", program))
+    }
+}
@@ -0,0 +1,150 @@
+use std::fs;
+
+use serde_json::{self, Map, Value};
+
+
+/// Where two traces stop agreeing
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    /// The line of the traces, from one
+    pub line: usize,
+
+    /// The fields that differ, or none if one trace ended
+    pub fields: Vec<String>,
+
+    pub first: Option<String>,
+    pub second: Option<String>,
+}
+
+
+/// `karma trace-diff`: find the first step where two traces differ
+pub fn trace_diff(arguments: Vec<String>) -> Result<i32, String> {
+    let mut ignored = Vec::new();
+    let mut paths = Vec::new();
+
+    for argument in arguments {
+        if let Some(fields) = argument.strip_prefix("--ignore=") {
+            ignored.extend(fields.split(',').filter(|field| !field.is_empty()).map(str::to_owned));
+        } else if !argument.starts_with('-') {
+            paths.push(argument);
+        } else {
+            return Err(format!("Unknown option: {}", argument));
+        }
+    }
+
+    if paths.len() != 2 {
+        return Err("Give two trace files".to_owned());
+    }
+
+    let first = fs::read_to_string(&paths[0]).map_err(|e| format!("{}: {}", paths[0], e))?;
+    let second = fs::read_to_string(&paths[1]).map_err(|e| format!("{}: {}", paths[1], e))?;
+
+    let divergence = match first_divergence(&first, &second, &ignored)? {
+        Some(divergence) => divergence,
+        None => {
+            println!("The traces agree on all {} records", first.lines().count());
+            return Ok(0);
+        }
+    };
+
+    match (&divergence.first, &divergence.second) {
+        (Some(record), Some(_)) => {
+            let step = fields(record).ok().and_then(|fields| fields.get("step").map(|step| format!(" (step {})", step)));
+
+            println!("The traces diverge at record {}{}, in {}", divergence.line, step.unwrap_or_default(), divergence.fields.join(", "));
+        }
+        (Some(_), None) => println!("{} ends before record {}", paths[1], divergence.line),
+        (None, _) => println!("{} ends before record {}", paths[0], divergence.line),
+    }
+
+    if let Some(ref record) = divergence.first {
+        println!("< {}", record);
+    }
+    if let Some(ref record) = divergence.second {
+        println!("> {}", record);
+    }
+
+    Ok(1)
+}
+
+
+/// The first record two traces disagree on, leaving out some fields
+pub fn first_divergence(first: &str, second: &str, ignored: &[String]) -> Result<Option<Divergence>, String> {
+    let mut first_lines = first.lines();
+    let mut second_lines = second.lines();
+    let mut line = 0;
+
+    loop {
+        line += 1;
+
+        let (a, b) = match (first_lines.next(), second_lines.next()) {
+            (None, None) => return Ok(None),
+            (Some(a), Some(b)) => (a, b),
+            (a, b) => {
+                return Ok(Some(Divergence {
+                    line,
+                    fields: Vec::new(),
+                    first: a.map(str::to_owned),
+                    second: b.map(str::to_owned),
+                }))
+            }
+        };
+
+        let a_fields = fields(a).map_err(|e| format!("line {}: invalid record: {}", line, e))?;
+        let b_fields = fields(b).map_err(|e| format!("line {}: invalid record: {}", line, e))?;
+
+        let mut names: Vec<&String> = a_fields.keys().collect();
+        for name in b_fields.keys() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        let differing: Vec<String> = names.into_iter()
+            .filter(|&name| !ignored.contains(name))
+            .filter(|&name| a_fields.get(name) != b_fields.get(name))
+            .cloned()
+            .collect();
+
+        if !differing.is_empty() {
+            return Ok(Some(Divergence {
+                line,
+                fields: differing,
+                first: Some(a.to_owned()),
+                second: Some(b.to_owned()),
+            }));
+        }
+    }
+}
+
+
+/// The fields of a record
+fn fields(record: &str) -> Result<Map<String, Value>, String> {
+    match serde_json::from_str(record) {
+        Ok(Value::Object(fields)) => Ok(fields),
+        Ok(_) => Err("not an object".to_owned()),
+        Err(error) => Err(error.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_first_divergence_of_traces() {
+        let first = "{\"step\":1,\"stack\":{\"push\":[1]},\"io\":null}\n{\"step\":2,\"stack\":{\"pop\":[1]},\"io\":{\"output\":\"1,\"}}\n";
+        let second = "{\"step\":1,\"stack\":{\"push\":[1]},\"io\":null}\n{\"step\":2,\"stack\":{\"pop\":[2]},\"io\":{\"output\":\"1,\"}}\n";
+
+        let divergence = first_divergence(first, second, &[]).unwrap().unwrap();
+        assert_eq!((divergence.line, divergence.fields), (2, vec!["stack".to_owned()]));
+        assert_eq!(first_divergence(first, second, &["stack".to_owned()]).unwrap(), None);
+
+        let divergence = first_divergence(first, &second[..second.find('\n').unwrap() + 1], &[]).unwrap().unwrap();
+        assert_eq!((divergence.line, divergence.second), (2, None));
+
+        assert_eq!(first_divergence("{\"step\":1,\"io\":{\"output\":\"\\u0041\"}}", "{\"io\": {\"output\": \"A\"}, \"step\": 1}", &[]), Ok(None));
+        assert!(first_divergence("{\"step\"}", "{}", &[]).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+extern crate karma_parser;
+
+
+use std::{
+    env,
+    fs,
+    io::{self, Read, Write},
+    process::exit,
+};
+
+use karma_parser::{
+    Dialect,
+    format::format,
+    syntax::SyntaxTree,
+};
+
+
+fn main() {
+    let arguments = match parse_arguments() {
+        Ok(arguments) => arguments,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            exit(2);
+        }
+    };
+
+    let mut unformatted = false;
+    let mut failed = false;
+
+    if arguments.paths.is_empty() {
+        let mut source = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut source) {
+            eprintln!("Error: {}", e);
+            exit(2);
+        }
+
+        match format_source(&source, arguments.dialect) {
+            Ok(ref formatted) if arguments.check => unformatted = *formatted != source,
+            Ok(formatted) => io::stdout().write_all(formatted.as_bytes()).unwrap(),
+            Err(message) => {
+                eprintln!("<stdin>: {}", message);
+                failed = true;
+            }
+        }
+    }
+
+    for path in &arguments.paths {
+        let result = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| format_source(&source, arguments.dialect).map(|formatted| (source, formatted)));
+
+        match result {
+            Ok((source, formatted)) => {
+                if formatted == source {
+                    continue;
+                }
+
+                if arguments.check {
+                    println!("{}", path);
+                    unformatted = true;
+                } else if let Err(e) = fs::write(path, formatted) {
+                    eprintln!("{}: {}", path, e);
+                    failed = true;
+                }
+            }
+
+            Err(message) => {
+                eprintln!("{}: {}", path, message);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        exit(2);
+    } else if unformatted {
+        exit(1);
+    }
+}
+
+
+/// Format a program, refusing programs that do not parse or whose meaning
+/// would change
+fn format_source(source: &str, dialect: Dialect) -> Result<String, String> {
+    let tree = SyntaxTree::parse(source, dialect);
+    let formatted = format(&tree);
+
+    let sequences = tree.to_sequences().map_err(|e| format!("{:?}", e))?;
+    if formatted.to_sequences().ok() != Some(sequences) {
+        return Err("formatting would change the program".to_owned());
+    }
+
+    Ok(formatted.to_string())
+}
+
+
+struct Arguments {
+    paths: Vec<String>,
+    dialect: Dialect,
+
+    /// Only report files that are not formatted
+    check: bool,
+}
+
+
+fn parse_arguments() -> Result<Arguments, String> {
+    let mut paths = Vec::new();
+    let mut dialect = Dialect::default();
+    let mut check = false;
+
+    for argument in env::args().skip(1) {
+        if argument == "--check" {
+            check = true;
+        } else if let Some(name) = argument.strip_prefix("--dialect=") {
+            dialect = Dialect::from_name(name).map_err(|e| format!("{:?}", e))?;
+        } else if argument.starts_with('-') {
+            return Err(format!("Unknown option: {}", argument));
+        } else {
+            paths.push(argument);
+        }
+    }
+
+    Ok(Arguments { paths, dialect, check })
+}
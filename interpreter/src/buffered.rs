@@ -0,0 +1,258 @@
+use std::{
+    cell::RefCell,
+    io::{self, BufReader, BufWriter, Cursor, ErrorKind, IsTerminal, Read, StdoutLock, Write},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant}
+};
+
+
+/// How many bytes of output are collected before they are written
+const BUFFER_SIZE: usize = 1 << 16;
+
+
+/// The input and output of a running program, both buffered. The output is
+/// written once the buffer is full, when flushed, after every line with
+/// `with_flush_lines`, and before every read with `with_flush_before_read`.
+///
+/// A shared reference reads and writes, so that it can be both the input and
+/// the output of `execute`:
+/// ```text
+/// let io = Buffered::stdio();
+/// execute(&sequences, &mut &io, &mut &io, eof)?;
+/// io.flush()?;
+/// ```
+pub struct Buffered<R: Read, W: Write> {
+    input: RefCell<BufReader<R>>,
+    output: RefCell<BufWriter<W>>,
+    flush_lines: bool,
+    flush_before_read: bool
+}
+
+/// Input read on another thread, so that waiting for it can give up at a
+/// deadline. Reading past the deadline fails with `ErrorKind::TimedOut`.
+pub struct TimedInput {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    chunk: Cursor<Vec<u8>>,
+    deadline: Instant
+}
+
+
+impl<R: Read, W: Write> Buffered<R, W> {
+    /// Buffer input and output, flushing the output only when it is full or
+    /// asked to
+    pub fn new(input: R, output: W) -> Buffered<R, W> {
+        Buffered {
+            input: RefCell::new(BufReader::new(input)),
+            output: RefCell::new(BufWriter::with_capacity(BUFFER_SIZE, output)),
+            flush_lines: false,
+            flush_before_read: false
+        }
+    }
+
+    /// Flush the output after every newline, for someone watching it
+    pub fn with_flush_lines(mut self, flush_lines: bool) -> Buffered<R, W> {
+        self.flush_lines = flush_lines;
+        self
+    }
+
+    /// Flush the output before reading, so that a prompt shows before the
+    /// program waits for its answer
+    pub fn with_flush_before_read(mut self, flush_before_read: bool) -> Buffered<R, W> {
+        self.flush_before_read = flush_before_read;
+        self
+    }
+
+    /// Write everything printed so far, which should happen before the
+    /// program exits
+    pub fn flush(&self) -> io::Result<()> {
+        self.output.borrow_mut().flush()
+    }
+
+    /// The output, after writing everything printed to it
+    pub fn into_output(self) -> io::Result<W> {
+        self.output.into_inner().into_inner().map_err(|error| error.into_error())
+    }
+}
+
+impl Buffered<Box<dyn Read>, StdoutLock<'static>> {
+    /// Standard input and output, flushing lines when the output is a
+    /// terminal, and before reading when the input is one. With a time limit,
+    /// waiting for input gives up once the time is up.
+    pub fn stdio(time: Option<Duration>) -> Buffered<Box<dyn Read>, StdoutLock<'static>> {
+        let interactive = (io::stdin().is_terminal(), io::stdout().is_terminal());
+        let input: Box<dyn Read> = match time {
+            Some(time) => Box::new(TimedInput::new(io::stdin(), Instant::now() + time)),
+            None => Box::new(io::stdin().lock())
+        };
+
+        Buffered::new(input, io::stdout().lock())
+            .with_flush_lines(interactive.1)
+            .with_flush_before_read(interactive.0)
+    }
+}
+
+
+impl TimedInput {
+    /// Read from `input` until the deadline. The thread reading keeps waiting
+    /// after it, until the input ends or the program exits.
+    pub fn new<R: Read + Send + 'static>(mut input: R, deadline: Instant) -> TimedInput {
+        let (sender, chunks) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut buffer = vec![0; 1 << 13];
+            loop {
+                let chunk = match input.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(length) => Ok(buffer[..length].to_vec()),
+                    Err(ref error) if error.kind() == ErrorKind::Interrupted => continue,
+                    Err(error) => Err(error)
+                };
+
+                let failed = chunk.is_err();
+                if sender.send(chunk).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        TimedInput { chunks, chunk: Cursor::new(Vec::new()), deadline }
+    }
+}
+
+
+impl<R: Read, W: Write> Read for &Buffered<R, W> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.flush_before_read {
+            self.flush()?;
+        }
+
+        self.input.borrow_mut().read(buffer)
+    }
+
+    // a program reads one byte at a time, which `BufReader` does faster
+    // than the default
+    fn read_exact(&mut self, buffer: &mut [u8]) -> io::Result<()> {
+        if self.flush_before_read {
+            self.flush()?;
+        }
+
+        self.input.borrow_mut().read_exact(buffer)
+    }
+}
+
+impl<R: Read, W: Write> Write for &Buffered<R, W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let mut output = self.output.borrow_mut();
+        output.write_all(bytes)?;
+
+        if self.flush_lines && bytes.contains(&b'\n') {
+            output.flush()?;
+        }
+
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Buffered::flush(*self)
+    }
+}
+
+impl Read for TimedInput {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.chunk.position() == self.chunk.get_ref().len() as u64 {
+            let timeout = self.deadline.saturating_duration_since(Instant::now());
+
+            self.chunk = match self.chunks.recv_timeout(timeout) {
+                Ok(chunk) => Cursor::new(chunk?),
+                Err(RecvTimeoutError::Timeout) => return Err(io::Error::new(ErrorKind::TimedOut, "the time limit was reached")),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0)
+            };
+        }
+
+        self.chunk.read(buffer)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use karma_parser::{Eof, Trap};
+    use karma_parser::syntax::SyntaxTree;
+    use execution::execute;
+    use std::rc::Rc;
+
+    /// Output that can still be looked at after it was moved into `Buffered`
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn keep_output_until_flushed() {
+        let sequences = SyntaxTree::parse("?:?:\n", Default::default()).to_sequences().unwrap();
+        let written = Shared::default();
+        let stdio = Buffered::new(&b"a\nb"[..], written.clone());
+
+        execute(&sequences, &mut &stdio, &mut &stdio, Eof::default()).unwrap();
+        assert_eq!(*written.0.borrow(), b"");
+        assert_eq!(stdio.into_output().unwrap().0.borrow().as_slice(), b"a\n");
+    }
+
+    #[test]
+    fn flush_complete_lines() {
+        let written = Shared::default();
+        let stdio = Buffered::new(&b""[..], written.clone()).with_flush_lines(true);
+
+        (&stdio).write_all(b"a\nb").unwrap();
+        assert_eq!(*written.0.borrow(), b"a\nb");
+        (&stdio).write_all(b"c").unwrap();
+        assert_eq!(*written.0.borrow(), b"a\nb");
+    }
+
+    #[test]
+    fn flush_before_reading() {
+        let written = Shared::default();
+        let stdio = Buffered::new(&b"b"[..], written.clone()).with_flush_before_read(true);
+
+        (&stdio).write_all(b"?").unwrap();
+        assert_eq!(*written.0.borrow(), b"");
+        let mut byte = [0];
+        (&stdio).read_exact(&mut byte).unwrap();
+        assert_eq!((written.0.borrow().clone(), byte), (b"?".to_vec(), *b"b"));
+    }
+
+    #[test]
+    fn stop_waiting_for_input_at_the_time_limit() {
+        struct Stalled;
+
+        impl Read for Stalled {
+            fn read(&mut self, _buffer: &mut [u8]) -> io::Result<usize> {
+                thread::sleep(Duration::from_secs(3600));
+                Ok(0)
+            }
+        }
+
+        let sequences = SyntaxTree::parse("?;?;\n", Default::default()).to_sequences().unwrap();
+        let run = |input: &mut TimedInput| {
+            let mut output = Vec::new();
+            let result = execute(&sequences, input, &mut output, Eof::default());
+            (result, output)
+        };
+
+        let soon = Instant::now() + Duration::from_millis(20);
+        assert_eq!(run(&mut TimedInput::new(Stalled, soon)), (Err(Trap::TimeLimit), Vec::new()));
+
+        let later = Instant::now() + Duration::from_secs(60);
+        assert_eq!(run(&mut TimedInput::new(&b"a"[..], later)), (Ok(()), b"97-1".to_vec()));
+    }
+}
@@ -0,0 +1,291 @@
+use std::io::{self, Write};
+
+use karma_parser::{Sequence, Instruction};
+use karma_parser::syntax::SyntaxTree;
+
+use execution::{Observer, Position, State};
+
+
+/// Which sections of a program ran and which way its skips went, over any
+/// number of runs. A run of the interpreter or of a program compiled with
+/// `karmac --coverage=` appends records like these to a file:
+///
+/// ```text
+/// section <sequence> <section> <executions>
+/// skip <sequence> <section> <not skipped> <skipped>
+/// ```
+///
+/// A skip is keyed by the section it ends. Reading a file adds up all of its
+/// records.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Coverage {
+    /// Executions of every section, by sequence
+    pub sections: Vec<Vec<u64>>,
+
+    /// How often the skip ending each section kept and skipped the following
+    /// instruction, zero for sections that do not end with one
+    pub skips: Vec<Vec<[u64; 2]>>
+}
+
+
+impl Coverage {
+    /// Nothing covered yet
+    pub fn new(sequences: &[Sequence]) -> Coverage {
+        Coverage {
+            sections: sequences.iter().map(|sequence| vec![0; sequence.len()]).collect(),
+            skips: sequences.iter().map(|sequence| vec![[0; 2]; sequence.len()]).collect()
+        }
+    }
+
+    /// Add the records of a coverage file
+    pub fn read(&mut self, text: &str) -> Result<(), String> {
+        for (index, line) in text.lines().enumerate().filter(|&(_, line)| !line.trim().is_empty()) {
+            let invalid = || format!("line {}: invalid coverage record: {}", index + 1, line);
+
+            let mut words = line.split_whitespace();
+            let kind = words.next().unwrap_or("");
+            let numbers = words.map(|word| word.parse::<u64>().map_err(|_| invalid()))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let (sequence, section) = match numbers.get(0..2) {
+                Some(location) => (location[0] as usize, location[1] as usize),
+                None => return Err(invalid())
+            };
+
+            let is_line = sequence > 0 && sequence < self.sections.len() - 1;
+            if !is_line || section >= self.sections[sequence].len() {
+                return Err(format!("line {}: the program has no section {} on line {}", index + 1, section, sequence));
+            }
+
+            match (kind, &numbers[2..]) {
+                ("section", &[count]) => self.sections[sequence][section] += count,
+                ("skip", &[kept, skipped]) => {
+                    self.skips[sequence][section][0] += kept;
+                    self.skips[sequence][section][1] += skipped;
+                }
+
+                _ => return Err(invalid())
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the records of the lines of a program
+    pub fn write(&self, sequences: &[Sequence], output: &mut dyn Write) -> io::Result<()> {
+        for (sequence, sections) in sequences.iter().enumerate().take(sequences.len() - 1).skip(1) {
+            for (section, instructions) in sections.iter().enumerate() {
+                writeln!(output, "section {} {} {}", sequence, section, self.sections[sequence][section])?;
+
+                if instructions.last() == Some(&Instruction::SkipIfNotOne) {
+                    let [kept, skipped] = self.skips[sequence][section];
+                    writeln!(output, "skip {} {} {} {}", sequence, section, kept, skipped)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+
+    /// Print the source with the executions of every line in the margin.
+    /// Below each line, every section is underlined with its executions, or
+    /// `#####` if it never ran, followed by how the skips went.
+    pub fn write_annotated(&self, tree: &SyntaxTree, sequences: &[Sequence], output: &mut dyn Write) -> io::Result<()> {
+        for (index, line) in tree.lines.iter().enumerate() {
+            let sequence = index + 1;
+            if sequence >= sequences.len() - 1 {
+                break;
+            }
+
+            writeln!(output, "{:>10} | {}{}", count(self.line(sequence)), line.code(), line.comment)?;
+
+            let spans = section_spans(&sequences[sequence], &line.instruction_columns());
+            for (section, &(start, end)) in spans.iter().enumerate() {
+                writeln!(output, "{:>10} | {}{}", count(self.sections[sequence][section]),
+                         " ".repeat(start), "^".repeat(end - start))?;
+            }
+
+            for (section, &(_, end)) in spans.iter().enumerate() {
+                if sequences[sequence][section].last() == Some(&Instruction::SkipIfNotOne) {
+                    let [kept, skipped] = self.skips[sequence][section];
+                    writeln!(output, "{:>10} | {}^ not skipped {}, skipped {}", "skip", " ".repeat(end - 1), kept, skipped)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write an lcov tracefile for a source file. Every line records its
+    /// executions, every section is a function named after its line and
+    /// index, and every skip is a branch with the outcomes not skipped and
+    /// skipped.
+    pub fn write_lcov(&self, path: &str, sequences: &[Sequence], output: &mut dyn Write) -> io::Result<()> {
+        let lines = 1..sequences.len() - 1;
+        writeln!(output, "TN:")?;
+        writeln!(output, "SF:{}", path)?;
+
+        for sequence in lines.clone() {
+            for section in 0..sequences[sequence].len() {
+                writeln!(output, "FN:{},line_{}_section_{}", sequence, sequence, section)?;
+            }
+        }
+
+        let mut functions_hit = 0;
+        for sequence in lines.clone() {
+            for (section, &executions) in self.sections[sequence].iter().enumerate() {
+                writeln!(output, "FNDA:{},line_{}_section_{}", executions, sequence, section)?;
+                functions_hit += (executions > 0) as usize;
+            }
+        }
+
+        writeln!(output, "FNF:{}", lines.clone().map(|sequence| sequences[sequence].len()).sum::<usize>())?;
+        writeln!(output, "FNH:{}", functions_hit)?;
+
+        let (mut branches, mut branches_hit) = (0, 0);
+        for sequence in lines.clone() {
+            let skips = sequences[sequence].iter().enumerate()
+                .filter(|&(_, instructions)| instructions.last() == Some(&Instruction::SkipIfNotOne));
+
+            for (block, (section, _)) in skips.enumerate() {
+                let outcomes = self.skips[sequence][section];
+
+                for (branch, &taken) in outcomes.iter().enumerate() {
+                    if outcomes == [0, 0] {
+                        writeln!(output, "BRDA:{},{},{},-", sequence, block, branch)?;
+                    } else {
+                        writeln!(output, "BRDA:{},{},{},{}", sequence, block, branch, taken)?;
+                    }
+
+                    branches += 1;
+                    branches_hit += (taken > 0) as usize;
+                }
+            }
+        }
+
+        writeln!(output, "BRF:{}", branches)?;
+        writeln!(output, "BRH:{}", branches_hit)?;
+
+        for sequence in lines.clone() {
+            writeln!(output, "DA:{},{}", sequence, self.line(sequence))?;
+        }
+
+        writeln!(output, "LF:{}", lines.len())?;
+        writeln!(output, "LH:{}", lines.filter(|&sequence| self.line(sequence) > 0).count())?;
+        writeln!(output, "end_of_record")
+    }
+
+
+    /// Executions of a line, which is entered at its first section unless a
+    /// jump continues it further on
+    fn line(&self, sequence: usize) -> u64 {
+        self.sections[sequence].iter().cloned().max().unwrap_or(0)
+    }
+}
+
+
+impl Observer for Coverage {
+    fn instruction(&mut self, position: Position, _instruction: &Instruction, _state: &State) {
+        if position.instruction == 0 {
+            self.sections[position.sequence][position.section] += 1;
+        }
+    }
+
+    fn skip(&mut self, position: Position, skipped: bool) {
+        self.skips[position.sequence][position.section][skipped as usize] += 1;
+    }
+}
+
+
+/// The columns each section of a line spans, from its first instruction to
+/// after its last. The exit at the end of the line is placed after the code.
+fn section_spans(sequence: &Sequence, columns: &[usize]) -> Vec<(usize, usize)> {
+    let exit = columns.last().map_or(0, |column| column + 1);
+    let mut instruction = 0;
+
+    sequence.iter().map(|section| {
+        let column = |index: usize| columns.get(index).cloned().unwrap_or(exit);
+        let span = (column(instruction), column(instruction + section.len() - 1) + 1);
+
+        instruction += section.len();
+        span
+    }).collect()
+}
+
+fn count(count: u64) -> String {
+    if count == 0 {
+        "#####".to_owned()
+    } else {
+        count.to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use karma_parser::Eof;
+    use execution::execute_observed;
+
+    /// The coverage of `?@,2;` run once with input that is skipped and once
+    /// with input that is not
+    fn merged_coverage(tree: &SyntaxTree) -> (Vec<Sequence>, Coverage) {
+        let sequences = tree.to_sequences().unwrap();
+
+        let mut records = Vec::new();
+        for input in &[&b"\x01"[..], &b"a"[..]] {
+            let mut coverage = Coverage::new(&sequences);
+            execute_observed(&sequences, &mut &input[..], &mut Vec::new(), Eof::default(), &mut coverage).unwrap();
+            coverage.write(&sequences, &mut records).unwrap();
+        }
+
+        let mut coverage = Coverage::new(&sequences);
+        coverage.read(&String::from_utf8(records).unwrap()).unwrap();
+        (sequences, coverage)
+    }
+
+    #[test]
+    fn merge_coverage_of_runs() {
+        let (_, coverage) = merged_coverage(&SyntaxTree::parse("?@,2;\n", Default::default()));
+
+        assert_eq!(coverage.sections[1], vec![2, 1, 1]);
+        assert_eq!(coverage.skips[1][0], [1, 1]);
+    }
+
+    #[test]
+    fn annotate_source_with_coverage() {
+        let tree = SyntaxTree::parse("?@,2;\n", Default::default());
+        let (sequences, coverage) = merged_coverage(&tree);
+
+        let mut annotated = Vec::new();
+        coverage.write_annotated(&tree, &sequences, &mut annotated).unwrap();
+        assert_eq!(String::from_utf8(annotated).unwrap(),
+                   "         2 | ?@,2;\n\
+                    \x20        2 | ^^\n\
+                    \x20        1 |   ^\n\
+                    \x20        1 |    ^^^\n\
+                    \x20     skip |  ^ not skipped 1, skipped 1\n");
+    }
+
+    #[test]
+    fn write_lcov() {
+        let (sequences, coverage) = merged_coverage(&SyntaxTree::parse("?@,2;\n", Default::default()));
+
+        let mut lcov = Vec::new();
+        coverage.write_lcov("test.kar", &sequences, &mut lcov).unwrap();
+        let lcov = String::from_utf8(lcov).unwrap();
+        assert!(lcov.contains("SF:test.kar\n"));
+        assert!(lcov.contains("FNDA:1,line_1_section_1\nFNDA:1,line_1_section_2\n"));
+        assert!(lcov.contains("FNF:3\nFNH:3\nBRDA:1,0,0,1\nBRDA:1,0,1,1\nBRF:2\nBRH:2\nDA:1,2\nLF:1\nLH:1\nend_of_record\n"));
+    }
+
+    #[test]
+    fn reject_records_of_other_programs() {
+        let sequences = SyntaxTree::parse("?@,2;\n", Default::default()).to_sequences().unwrap();
+        let mut coverage = Coverage::new(&sequences);
+
+        assert!(coverage.read("section 2 0 1").is_err());
+        assert!(coverage.read("skip 1 0 1").is_err());
+    }
+}
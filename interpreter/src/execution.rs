@@ -4,16 +4,19 @@ use std::{
     collections::VecDeque,
 
     io::{
-        self,
-        Read
+        ErrorKind,
+        Read,
+        Write
     },
-    slice
+    slice,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Instant
 };
 
-use karma_parser::*;
+use karma_parser::{Sequence, Instruction, ValueSource, QueueEnd, Direction, Start, Trap, Eof, Limits};
+use karma_parser::optimize::run_counted_loop;
 use karma_parser::Instruction::*;
 use karma_parser::ValueSource::*;
-use karma_parser::Operator::*;
 use karma_parser::QueueEnd::*;
 use karma_parser::Direction::*;
 use karma_parser::Start::*;
@@ -22,233 +25,664 @@ pub type DataType = i64;
 type Stack = Vec<DataType>;
 type Deque = VecDeque<DataType>;
 
+/// How many steps may pass between looking at the clock for `Limits::time`
+/// and at `INTERRUPTED`
+const CHECK_INTERVAL: u64 = 4096;
 
-pub fn execute(sequences: &[Sequence]) {
+/// Set it to stop every running program soon after with `Trap::Interrupted`,
+/// from a signal handler for example. The state of a stopped program can be
+/// run again from where it stopped.
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+
+/// Run a program until it exits or stops with a runtime error, reading
+/// characters from `input` and printing to `output`
+pub fn execute(sequences: &[Sequence], input: &mut dyn Read, output: &mut dyn Write, eof: Eof) -> Result<(), Trap> {
+    execute_observed(sequences, input, output, eof, &mut ())
+}
+
+/// Run a program like `execute`, telling `observer` about every step
+pub fn execute_observed<O: Observer>(sequences: &[Sequence], input: &mut dyn Read, output: &mut dyn Write, eof: Eof, observer: &mut O) -> Result<(), Trap> {
+    execute_limited(sequences, input, output, eof, &Limits::default(), observer)
+}
+
+/// Run a program like `execute_observed`, stopping it with a trap once it
+/// goes past one of the limits
+pub fn execute_limited<O: Observer>(sequences: &[Sequence], input: &mut dyn Read, output: &mut dyn Write, eof: Eof, limits: &Limits, observer: &mut O) -> Result<(), Trap> {
     #[cfg(feature = "debug")]
     eprintln!("Sequences: {:#?}", sequences);
 
-    let mut state = State::new(sequences);
-    while let Some(instruction) = state.next_instruction() {
-        #[cfg(feature = "debug")]
-        {
-            let pad = |len, mut string: String| {
-                for _ in string.len()..len {
-                    string += " ";
-                }
+    let mut state = State::new();
+    let result = state.run_limited(sequences, input, output, eof, limits, observer);
 
-                string
-            };
+    #[cfg(feature = "debug")]
+    {
+        eprintln!("");
+        eprintln!("");
+        eprintln!("Final stack: {:?}", state.stack);
+        eprintln!("Final deque: {:?}", state.deque);
+    }
 
-            let instr = pad(30, format!("{:?}", instruction));
-            let stack = pad(30, format!("{:?}", state.stack));
+    result
+}
 
-            eprintln!("{} {} {:?}", instr, stack, state.deque);
-        }
 
-        match instruction {
-            &BitwiseNot => {
-                let value = state.pop();
-                state.push(!value)
-            }
+/// Everything a program keeps between lines: its values, and where each line
+/// continues when it is entered again
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct State {
+    pub stack: Stack,
+    pub deque: Deque,
 
-            &LogicalNot => {
-                let value = state.pop();
-                state.push(if value == 0 {1} else {0});
-            }
+    /// The sequence being executed
+    pub current_sequence: usize,
 
+    /// The section each sequence continues at
+    pub next_sections: Vec<usize>,
 
-            &Push(ref source) => {
-                let value = state.value_from_source(source);
-                state.push(value);
-            }
+    /// The instruction a run that stopped within a section continues at, in
+    /// the section before the next one of the current sequence
+    pub current_instruction: Option<usize>
+}
 
-            &Destroy => {
-                state.pop();
-            }
+/// Where an instruction is in a program
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Position {
+    pub sequence: usize,
+    pub section: usize,
 
-            &Insert(ref source, ref end) => {
-                let value = state.value_from_source(source);
-                state.insert(value, end);
-            }
+    /// The index of the instruction in its section
+    pub instruction: usize
+}
 
+/// A value put into or taken out of the stack or deque
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Change {
+    Push(DataType),
+    Pop(DataType),
+    InsertFront(DataType),
+    InsertBack(DataType),
+    RemoveFront(DataType),
+    RemoveBack(DataType)
+}
 
-            &SkipIfNotOne => {
-                let top = state.pop();
-                if top != 1 {
-                    state.next_instruction();
-                }
-            }
+/// Notified of what a program does while it runs. Every method does nothing
+/// by default.
+pub trait Observer {
+    /// Whether to call `instruction`, which costs time even when it does
+    /// nothing
+    const INSTRUCTIONS: bool = true;
 
-            &Jump(ref direction, ref start) => {
-                state.jump(direction, start);
-            }
+    /// An instruction is about to be executed
+    fn instruction(&mut self, _position: Position, _instruction: &Instruction, _state: &State) {}
 
+    /// A skip at a position ran, and skipped the following instruction or not
+    fn skip(&mut self, _position: Position, _skipped: bool) {}
 
-            &OutputNumber(ref source) => {
-                let value = state.value_from_source(source);
-                print!("{}", value);
-            }
+    /// The instruction changed the stack or deque, only called if
+    /// `INSTRUCTIONS` is set
+    fn change(&mut self, _change: Change) {}
 
-            &OutputCharacter(ref source) => {
-                let value = state.value_from_source(source);
-                print!("{}", value as u8 as char);
-            }
+    /// `?` read a byte, or `None` past the end of input
+    fn input(&mut self, _byte: Option<u8>) {}
 
-            &Exit => break
-        }
+    /// Bytes were printed
+    fn output(&mut self, _bytes: &[u8]) {}
+
+    /// The program exited or stopped with a runtime error
+    fn stop(&mut self, _state: &State) {}
+}
+
+/// Observes nothing
+impl Observer for () {
+    const INSTRUCTIONS: bool = false;
+}
+
+/// Lets both observe
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    const INSTRUCTIONS: bool = A::INSTRUCTIONS || B::INSTRUCTIONS;
+
+    fn instruction(&mut self, position: Position, instruction: &Instruction, state: &State) {
+        self.0.instruction(position, instruction, state);
+        self.1.instruction(position, instruction, state);
     }
 
-    #[cfg(feature = "debug")]
-    {
-        eprintln!("");
-        eprintln!("");
-        eprintln!("Final stack: {:?}", state.stack);
-        eprintln!("Final deque: {:?}", state.deque);
+    fn skip(&mut self, position: Position, skipped: bool) {
+        self.0.skip(position, skipped);
+        self.1.skip(position, skipped);
+    }
+
+    fn change(&mut self, change: Change) {
+        self.0.change(change);
+        self.1.change(change);
+    }
+
+    fn input(&mut self, byte: Option<u8>) {
+        self.0.input(byte);
+        self.1.input(byte);
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        self.0.output(bytes);
+        self.1.output(bytes);
+    }
+
+    fn stop(&mut self, state: &State) {
+        self.0.stop(state);
+        self.1.stop(state);
     }
 }
 
+/// Observes if there is an observer
+impl<O: Observer> Observer for Option<O> {
+    const INSTRUCTIONS: bool = O::INSTRUCTIONS;
+
+    fn instruction(&mut self, position: Position, instruction: &Instruction, state: &State) {
+        if let Some(ref mut observer) = *self {
+            observer.instruction(position, instruction, state);
+        }
+    }
+
+    fn skip(&mut self, position: Position, skipped: bool) {
+        if let Some(ref mut observer) = *self {
+            observer.skip(position, skipped);
+        }
+    }
+
+    fn change(&mut self, change: Change) {
+        if let Some(ref mut observer) = *self {
+            observer.change(change);
+        }
+    }
+
+    fn input(&mut self, byte: Option<u8>) {
+        if let Some(ref mut observer) = *self {
+            observer.input(byte);
+        }
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        if let Some(ref mut observer) = *self {
+            observer.output(bytes);
+        }
+    }
+
+    fn stop(&mut self, state: &State) {
+        if let Some(ref mut observer) = *self {
+            observer.stop(state);
+        }
+    }
+}
 
-#[derive(Debug)]
-struct State<'a> {
-    stack: Stack,
-    deque: Deque,
 
-    current_sequence: usize,
-    next_sections: Vec<usize>,
+/// A program being executed from a state
+struct Execution<'a, O: 'a + Observer> {
+    state: &'a mut State,
     current_section: slice::Iter<'a, Instruction>,
 
-    sequences: &'a [Sequence]
+    sequences: &'a [Sequence],
+    input: &'a mut dyn Read,
+    eof: Eof,
+    observer: &'a mut O,
+
+    limits: Limits,
+    max_stack: usize,
+    max_deque: usize,
+    max_output: u64,
+    started: Instant,
+    steps: u64,
+    output_bytes: u64,
+
+    /// The step at which to look at the step and time limits again
+    next_check: u64
 }
 
-impl<'a> State<'a> {
-    pub fn new(sequences: &'a[Sequence]) -> Self {
-        let current_section = sequences[1][0].iter();
-        let mut next_sections = vec![0; sequences.len()];
-        next_sections[1] = 1;
 
+impl State {
+    /// The empty stack and deque a program starts with, at its first line
+    pub fn new() -> State {
         State {
             stack: Stack::new(),
             deque: Deque::new(),
 
             current_sequence: 1,
-            next_sections,
+            next_sections: Vec::new(),
+            current_instruction: None
+        }
+    }
+
+    /// Start the next run at the beginning of a sequence, with every other
+    /// sequence also continuing from its beginning
+    pub fn enter(&mut self, sequence: usize) {
+        self.current_sequence = sequence;
+        self.next_sections.clear();
+        self.current_instruction = None;
+    }
+
+    /// Execute from the current sequence where it left off until the program
+    /// exits or stops with a runtime error
+    pub fn run(&mut self, sequences: &[Sequence], input: &mut dyn Read, output: &mut dyn Write, eof: Eof) -> Result<(), Trap> {
+        self.run_observed(sequences, input, output, eof, &mut ())
+    }
+
+    /// Execute like `run`, telling `observer` about every step
+    pub fn run_observed<O: Observer>(&mut self, sequences: &[Sequence], input: &mut dyn Read, output: &mut dyn Write, eof: Eof, observer: &mut O) -> Result<(), Trap> {
+        self.run_limited(sequences, input, output, eof, &Limits::default(), observer)
+    }
+
+    /// Execute like `run_observed` until the program goes past one of the
+    /// limits, counting steps, time and output from the start of this run
+    pub fn run_limited<O: Observer>(&mut self, sequences: &[Sequence], input: &mut dyn Read, output: &mut dyn Write, eof: Eof, limits: &Limits, observer: &mut O) -> Result<(), Trap> {
+        if self.next_sections.len() < sequences.len() {
+            self.next_sections.resize(sequences.len(), 0);
+        }
+
+        let next_section = &mut self.next_sections[self.current_sequence];
+        let current_section = match self.current_instruction.take() {
+            Some(instruction) => sequences[self.current_sequence][*next_section - 1][instruction..].iter(),
+            None => {
+                *next_section += 1;
+                sequences[self.current_sequence][*next_section - 1].iter()
+            }
+        };
+
+        let execution = Execution {
+            state: self,
             current_section,
+            sequences,
+            input,
+            eof,
+            observer: &mut *observer,
+
+            limits: *limits,
+            max_stack: limits.stack.unwrap_or(usize::MAX),
+            max_deque: limits.deque.unwrap_or(usize::MAX),
+            max_output: limits.output.unwrap_or(u64::MAX),
+            started: Instant::now(),
+            steps: 0,
+            output_bytes: 0,
+            next_check: 0
+        };
+
+        let result = execution.run(output);
+        observer.stop(self);
+
+        result
+    }
+}
 
-            sequences
+impl Default for State {
+    fn default() -> State {
+        State::new()
+    }
+}
+
+
+impl<'a, O: Observer> Execution<'a, O> {
+    #[allow(clippy::match_ref_pats, clippy::needless_borrowed_reference)]
+    fn run(mut self, output: &mut dyn Write) -> Result<(), Trap> {
+        self.next_check = self.next_check();
+
+        while let Some(instruction) = self.next_instruction() {
+            self.steps += 1;
+            if self.steps >= self.next_check {
+                self.check_limits()?;
+            }
+
+            if O::INSTRUCTIONS {
+                let position = self.position();
+                self.observer.instruction(position, instruction, self.state);
+            }
+
+            #[cfg(feature = "debug")]
+            {
+                let pad = |len, mut string: String| {
+                    for _ in string.len()..len {
+                        string += " ";
+                    }
+
+                    string
+                };
+
+                let instr = pad(30, format!("{:?}", instruction));
+                let stack = pad(30, format!("{:?}", self.state.stack));
+
+                eprintln!("{} {} {:?}", instr, stack, self.state.deque);
+            }
+
+            match instruction {
+                &BitwiseNot => {
+                    let value = self.pop()?;
+                    self.push(!value)?;
+                }
+
+                &LogicalNot => {
+                    let value = self.pop()?;
+                    self.push(if value == 0 {1} else {0})?;
+                }
+
+
+                &Push(ref source) => {
+                    let value = self.value_from_source(source)?;
+                    self.push(value)?;
+                }
+
+                &Destroy => {
+                    self.pop()?;
+                }
+
+                &Replace(ref source) => {
+                    self.pop()?;
+                    let value = self.value_from_source(source)?;
+                    self.push(value)?;
+                }
+
+                &Discard(ref source) => {
+                    self.value_from_source(source)?;
+                }
+
+                &Insert(ref source, ref end) => {
+                    let value = self.value_from_source(source)?;
+                    self.insert(value, end)?;
+                }
+
+
+                &SkipIfNotOne => {
+                    let top = self.pop()?;
+
+                    if O::INSTRUCTIONS {
+                        let position = self.position();
+                        self.observer.skip(position, top != 1);
+                    }
+
+                    if top != 1 {
+                        self.next_instruction();
+                    }
+                }
+
+                &Jump(ref direction, ref start) => {
+                    self.jump(direction, start);
+                }
+
+
+                &OutputNumber(ref source) => {
+                    let value = self.value_from_source(source)?;
+                    self.print(output, value.to_string().as_bytes())?;
+                }
+
+                &OutputCharacter(ref source) => {
+                    let value = self.value_from_source(source)?;
+                    self.print(output, &[value as u8])?;
+                }
+
+                &Exit => break,
+
+                &Instruction::Trap(trap) => return Err(trap),
+
+                &CountedLoop(step, ref operator) => {
+                    let counter = self.remove(&Front)?;
+                    let bound = self.front()?;
+
+                    // the head exits without touching the stack
+                    if counter.wrapping_add(step) > bound {
+                        self.insert(counter, &Front)?;
+                    } else {
+                        let top = self.pop()?;
+                        let (top, counter) = run_counted_loop(step, operator, top, counter, bound)?;
+                        self.insert(counter, &Front)?;
+                        self.push(top)?;
+                    }
+                }
+            }
         }
+
+        Ok(())
     }
 
 
-    pub fn next_instruction(&mut self) -> Option<&'a Instruction> {
+    /// The position of the instruction last returned by `next_instruction`
+    fn position(&self) -> Position {
+        let sequence = self.state.current_sequence;
+        let section = self.state.next_sections[sequence] - 1;
+        let instruction = self.sequences[sequence][section].len() - self.current_section.len() - 1;
+
+        Position { sequence, section, instruction }
+    }
+
+    fn next_instruction(&mut self) -> Option<&'a Instruction> {
         match self.current_section.next() {
             instruction @ Some(_) => instruction,
 
             // go to following section
             None => {
-                let next_section = self.next_sections[self.current_sequence];
-                if next_section >= self.sequences[self.current_sequence].len() {
+                let current_sequence = self.state.current_sequence;
+                let next_section = self.state.next_sections[current_sequence];
+                if next_section >= self.sequences[current_sequence].len() {
                     None
                 } else {
-                    self.current_section =
-                        self.sequences[self.current_sequence][next_section].iter();
+                    self.current_section = self.sequences[current_sequence][next_section].iter();
 
-                    self.next_sections[self.current_sequence] += 1;
+                    self.state.next_sections[current_sequence] += 1;
                     self.next_instruction()
                 }
             }
         }
     }
 
-    pub fn push(&mut self, value: DataType) {
-        self.stack.push(value);
+    /// The next step at which to look at the step limit, the clock or for
+    /// an interrupt
+    fn next_check(&self) -> u64 {
+        let steps = self.limits.steps.map_or(u64::MAX, |steps| steps.saturating_add(1));
+        steps.min(self.steps + CHECK_INTERVAL)
     }
 
-    pub fn pop(&mut self) -> DataType {
-        self.stack.pop().unwrap()
+    /// Stop before the instruction last returned by `next_instruction` if the
+    /// program went past a limit or was interrupted, so that the state can
+    /// be run again from there
+    fn check_limits(&mut self) -> Result<(), Trap> {
+        let trap = if self.limits.steps.is_some_and(|steps| self.steps > steps) {
+            Trap::StepLimit
+        } else if self.limits.time.is_some_and(|time| self.started.elapsed() > time) {
+            Trap::TimeLimit
+        } else if INTERRUPTED.load(Ordering::Relaxed) {
+            Trap::Interrupted
+        } else {
+            self.next_check = self.next_check();
+            return Ok(());
+        };
+
+        self.state.current_instruction = Some(self.position().instruction);
+        Err(trap)
     }
-    
-    pub fn insert(&mut self, value: DataType, end: &QueueEnd) {
-        match end {
-            &Back => self.deque.push_back(value),
-            &Front => self.deque.push_front(value)
+
+    fn print(&mut self, output: &mut dyn Write, bytes: &[u8]) -> Result<(), Trap> {
+        self.output_bytes += bytes.len() as u64;
+        if self.output_bytes > self.max_output {
+            return Err(Trap::OutputLimit);
         }
+
+        output.write_all(bytes).unwrap();
+        self.observer.output(bytes);
+        Ok(())
+    }
+
+    fn push(&mut self, value: DataType) -> Result<(), Trap> {
+        if self.state.stack.len() >= self.max_stack {
+            return Err(Trap::StackLimit);
+        }
+
+        self.state.stack.push(value);
+        self.change(Change::Push(value));
+        Ok(())
     }
 
-    pub fn remove(&mut self, end: &QueueEnd) -> DataType {
+    fn pop(&mut self) -> Result<DataType, Trap> {
+        let value = self.state.stack.pop().ok_or(Trap::EmptyStack)?;
+        self.change(Change::Pop(value));
+        Ok(value)
+    }
+
+    #[allow(clippy::match_ref_pats)]
+    fn insert(&mut self, value: DataType, end: &QueueEnd) -> Result<(), Trap> {
+        if self.state.deque.len() >= self.max_deque {
+            return Err(Trap::DequeLimit);
+        }
+
         match end {
-            &Back => self.deque.pop_back().unwrap(),
-            &Front => self.deque.pop_front().unwrap(),
+            &Back => {
+                self.state.deque.push_back(value);
+                self.change(Change::InsertBack(value));
+            }
+
+            &Front => {
+                self.state.deque.push_front(value);
+                self.change(Change::InsertFront(value));
+            }
         }
+
+        Ok(())
+    }
+
+    #[allow(clippy::match_ref_pats)]
+    fn remove(&mut self, end: &QueueEnd) -> Result<DataType, Trap> {
+        let (value, change): (_, fn(DataType) -> Change) = match end {
+            &Back => (self.state.deque.pop_back(), Change::RemoveBack),
+            &Front => (self.state.deque.pop_front(), Change::RemoveFront),
+        };
+
+        let value = value.ok_or(Trap::EmptyDeque)?;
+        self.change(change(value));
+        Ok(value)
     }
 
-    pub fn jump(&mut self, direction: &Direction, start: &Start) {
+    fn change(&mut self, change: Change) {
+        if O::INSTRUCTIONS {
+            self.observer.change(change);
+        }
+    }
+
+    fn front(&self) -> Result<DataType, Trap> {
+        self.state.deque.front().cloned().ok_or(Trap::EmptyDeque)
+    }
+
+    #[allow(clippy::match_ref_pats)]
+    fn jump(&mut self, direction: &Direction, start: &Start) {
         match direction {
-            &Previous => self.current_sequence -= 1,
+            &Previous => self.state.current_sequence -= 1,
             &Current => {},
-            &Next => self.current_sequence += 1
+            &Next => self.state.current_sequence += 1
         }
 
         match start {
-            &Restart => self.next_sections[self.current_sequence] = 0,
+            &Restart => self.state.next_sections[self.state.current_sequence] = 0,
             &Continue => {}
         }
 
-        let next_section = &mut self.next_sections[self.current_sequence];
-        self.current_section = self.sequences[self.current_sequence][*next_section].iter();
+        let next_section = &mut self.state.next_sections[self.state.current_sequence];
+        self.current_section = self.sequences[self.state.current_sequence][*next_section].iter();
         *next_section += 1;
     }
 
-    pub fn value_from_source(&mut self, source: &ValueSource) -> DataType {
-        match source {
-            &Pop => self.stack.pop().unwrap(),
+    #[allow(clippy::match_ref_pats, clippy::needless_borrowed_reference)]
+    fn value_from_source(&mut self, source: &ValueSource) -> Result<DataType, Trap> {
+        let value = match source {
+            &Pop => self.pop()?,
 
             &Remove(ref end) => {
-                self.remove(end)
+                self.remove(end)?
             }
 
-            
+
             &Constant(ref value) => *value as DataType,
 
             &Operate(ref lhs, ref operator, ref rhs) => {
-                let lhs_value = self.value_from_source(lhs);
-                let rhs_value = self.value_from_source(rhs);
-                perform_operation(lhs_value, rhs_value, operator)
+                let lhs_value = self.value_from_source(lhs)?;
+                let rhs_value = self.value_from_source(rhs)?;
+                operator.apply(lhs_value, rhs_value)?
             }
 
             &CloneTop => {
-                self.stack.last().unwrap().clone()
+                *self.state.stack.last().ok_or(Trap::EmptyStack)?
             }
 
             &Input => {
-                let stdin = io::stdin();
-                stdin.bytes().next().unwrap().unwrap().clone() as DataType
+                let mut byte = [0];
+                match self.input.read_exact(&mut byte) {
+                    Ok(()) => {
+                        self.observer.input(Some(byte[0]));
+                        byte[0] as DataType
+                    }
+
+                    Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
+                        self.observer.input(None);
+
+                        match self.eof {
+                            Eof::Error => return Err(Trap::EndOfInput),
+                            Eof::Value(value) => value
+                        }
+                    }
+
+                    // only `TimedInput` gives up waiting
+                    Err(ref e) if e.kind() == ErrorKind::TimedOut => return Err(Trap::TimeLimit),
+
+                    Err(e) => panic!("could not read input: {}", e)
+                }
             }
 
 
             &Equal => {
-                let top = self.pop();
-                let front = self.deque.front().unwrap();
-                (top == *front) as DataType
+                let top = self.pop()?;
+                (top == self.front()?) as DataType
             }
 
             &Greater => {
-                let top = self.pop();
-                let front = self.deque.front().unwrap();
-                (top > *front) as DataType
+                let top = self.pop()?;
+                (top > self.front()?) as DataType
             }
-        }
+        };
+
+        Ok(value)
     }
 }
 
-fn perform_operation(lhs: DataType, rhs: DataType, operator: &Operator) -> DataType {
-    match operator {
-        &Add => lhs + rhs,
-        &Sub => lhs - rhs,
-        &Mul => lhs * rhs,
-        &Div => lhs / rhs,
-        &Mod => lhs % rhs,
-        &And => lhs & rhs,
-        &Or  => lhs | rhs,
-        &Xor => lhs ^ rhs,
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use karma_parser::syntax::SyntaxTree;
+
+    fn run_limited(source: &str, flag: &str) -> (Result<(), Trap>, Vec<u8>) {
+        let mut limits = Limits::default();
+        assert!(limits.parse_flag(flag).unwrap());
+
+        let sequences = SyntaxTree::parse(source, Default::default()).to_sequences().unwrap();
+        let mut output = Vec::new();
+        let result = execute_limited(&sequences, &mut &b""[..], &mut output, Eof::default(), &limits, &mut ());
+        (result, output)
+    }
+
+    #[test]
+    fn stop_at_step_limit() {
+        assert_eq!(run_limited("1#<", "--max-steps=10"), (Err(Trap::StepLimit), vec![]));
+        assert_eq!(run_limited("12+;", "--max-steps=5"), (Ok(()), b"3".to_vec()));
+        assert_eq!(run_limited("12+;", "--max-steps=4").0, Err(Trap::StepLimit));
+    }
+
+    #[test]
+    fn stop_at_time_limit() {
+        assert_eq!(run_limited("1#<", "--max-time=0.01").0, Err(Trap::TimeLimit));
+    }
+
+    #[test]
+    fn stop_at_size_limits() {
+        assert_eq!(run_limited("1<", "--max-stack=5").0, Err(Trap::StackLimit));
+        assert_eq!(run_limited("1[<", "--max-deque=5").0, Err(Trap::DequeLimit));
+        assert_eq!(run_limited("12+;<", "--max-output=4"), (Err(Trap::OutputLimit), b"3333".to_vec()));
+    }
+
+    #[test]
+    fn parse_limit_flags() {
+        assert!(Limits::default().parse_flag("--max-stack=-1").is_err());
+        assert!(!Limits::default().parse_flag("--eof=error").unwrap());
     }
 }
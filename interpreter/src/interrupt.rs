@@ -0,0 +1,32 @@
+use std::sync::atomic::Ordering;
+
+use karma_parser::Trap;
+
+use execution::INTERRUPTED;
+
+
+const SIGINT: i32 = 2;
+
+extern "C" {
+    fn signal(signal: i32, handler: extern "C" fn(i32)) -> usize;
+    fn _exit(code: i32) -> !;
+}
+
+
+/// Make Ctrl-C stop a running program at its next check instead of right
+/// away, by setting `INTERRUPTED`
+pub fn catch() {
+    unsafe {
+        signal(SIGINT, on_interrupt);
+    }
+}
+
+/// A second Ctrl-C stops a program that doesn't reach a check, such as
+/// one waiting for input
+extern "C" fn on_interrupt(_signal: i32) {
+    if INTERRUPTED.swap(true, Ordering::Relaxed) {
+        unsafe {
+            _exit(Trap::Interrupted.exit_code());
+        }
+    }
+}
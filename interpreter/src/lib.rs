@@ -0,0 +1,33 @@
+extern crate karma_parser;
+#[macro_use]
+extern crate serde_json;
+
+pub mod buffered;
+pub mod coverage;
+pub mod execution;
+#[cfg(unix)]
+pub mod interrupt;
+pub mod profile;
+pub mod snapshot;
+pub mod trace;
+
+
+#[cfg(test)]
+mod tests {
+    use karma_parser::Eof;
+    use karma_parser::syntax::SyntaxTree;
+    use karma_parser::optimize::PassManager;
+    use execution::execute;
+
+    #[test]
+    fn keep_stack_of_loops_that_never_iterate() {
+        let sequences = SyntaxTree::parse("3}5},\n{1+\\>@, \\}+<\n;\n", Default::default()).to_sequences().unwrap();
+
+        for level in 0..3 {
+            let (optimized, _) = PassManager::new(level).run(sequences.clone());
+            let mut output = Vec::new();
+            assert_eq!(execute(&optimized, &mut &b""[..], &mut output, Eof::default()), Ok(()), "-O{}", level);
+            assert_eq!(output, b"6", "-O{}", level);
+        }
+    }
+}
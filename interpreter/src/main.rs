@@ -1,11 +1,15 @@
 
 extern crate karma_parser;
+extern crate karma_interpreter;
 
 
 use std::{
     env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Write},
     process::exit,
-    fmt::Debug
+    fmt::Debug,
+    ops::RangeInclusive
 };
 
 #[cfg(feature = "benchmark")]
@@ -13,12 +17,22 @@ use std::time;
 
 
 use karma_parser::{
-    parse_file,
-    optimize
+    Dialect,
+    Eof,
+    Limits,
+    Trap,
+    optimize::PassManager,
+    syntax::SyntaxTree
 };
 
-mod execution;
-use execution::execute;
+use karma_interpreter::buffered::Buffered;
+use karma_interpreter::execution::State;
+use karma_interpreter::snapshot::Snapshot;
+use karma_interpreter::profile::Profile;
+use karma_interpreter::coverage::Coverage;
+use karma_interpreter::trace::Trace;
+#[cfg(unix)]
+use karma_interpreter::interrupt;
 
 fn main() {
     #[cfg(feature = "benchmark")]
@@ -26,10 +40,93 @@ fn main() {
         time::Instant::now()
     };
 
-    let path = try_or_exit(get_path_argument());
-    let sequences = try_or_exit(parse_file(path));
-    let sequences = optimize::all(sequences);
-    execute(&sequences);
+    let arguments = try_or_exit(parse_arguments());
+    let source = try_or_exit(fs::read_to_string(&arguments.path));
+    let tree = SyntaxTree::parse(&source, arguments.dialect);
+    let sequences = try_or_exit(tree.to_sequences());
+
+    if arguments.validate {
+        if let Err(counterexample) = arguments.passes.validate(&sequences) {
+            eprintln!("{}", counterexample);
+            exit(1);
+        }
+    }
+
+    let (sequences, statistics) = arguments.passes.run(sequences);
+
+    if arguments.statistics {
+        for pass in statistics {
+            eprintln!("{}", pass);
+        }
+    }
+
+    let mut state = match arguments.resume {
+        Some(ref path) => {
+            let file = try_or_exit(File::open(path));
+            let snapshot = try_or_exit(Snapshot::read(&mut BufReader::new(file)));
+            try_or_exit(snapshot.check(&sequences));
+            snapshot.state
+        }
+        None => State::new()
+    };
+
+    #[cfg(unix)]
+    interrupt::catch();
+
+    let stdio = Buffered::stdio(arguments.limits.time);
+
+    let profile = if arguments.profile || arguments.folded.is_some() {
+        Some(Profile::new(&sequences))
+    } else {
+        None
+    };
+    let coverage = arguments.coverage.as_ref().map(|_| Coverage::new(&sequences));
+    let trace = arguments.trace.as_ref().map(|path| {
+        let file = BufWriter::new(try_or_exit(File::create(path)));
+        Trace::new(file, &tree, &sequences)
+            .with_sequences(arguments.trace_sequences.clone())
+            .with_every(arguments.trace_every)
+    });
+    let mut observers = (profile, (coverage, trace));
+
+    let result = if observers.0.is_some() || (observers.1).0.is_some() || (observers.1).1.is_some() {
+        state.run_limited(&sequences, &mut &stdio, &mut &stdio, arguments.eof, &arguments.limits, &mut observers)
+    } else {
+        state.run_limited(&sequences, &mut &stdio, &mut &stdio, arguments.eof, &arguments.limits, &mut ())
+    };
+    try_or_exit(stdio.flush());
+
+    let (profile, (coverage, trace)) = observers;
+    if let Some(trace) = trace {
+        try_or_exit(trace.finish());
+    }
+
+    if let (Some(profile), true) = (profile.as_ref(), arguments.profile) {
+        eprintln!();
+        try_or_exit(profile.write_listing(&tree, &sequences, &mut io::stderr()));
+    }
+
+    if let (Some(profile), Some(ref path)) = (profile.as_ref(), arguments.folded.as_ref()) {
+        try_or_exit(File::create(path).and_then(|mut file| profile.write_folded(&arguments.path, &mut file)));
+    }
+
+    if let (Some(coverage), Some(ref path)) = (coverage.as_ref(), arguments.coverage.as_ref()) {
+        let file = OpenOptions::new().create(true).append(true).open(path);
+        try_or_exit(file.and_then(|mut file| coverage.write(&sequences, &mut file)));
+    }
+
+    if let Err(Trap::Interrupted) = result {
+        let path = arguments.snapshot.clone().unwrap_or_else(|| format!("{}.snapshot", arguments.path));
+        let snapshot = Snapshot::new(state, &sequences);
+        let mut file = BufWriter::new(try_or_exit(File::create(&path)));
+        try_or_exit(snapshot.write(&mut file).and_then(|()| file.flush()));
+        eprintln!("Interrupted, resume with --resume={}", path);
+    }
+
+    if let Err(trap) = result {
+        eprintln!("Error: {}", trap);
+        exit(trap.exit_code());
+    }
 
     #[cfg(feature = "benchmark")]
     {
@@ -41,14 +138,121 @@ fn main() {
 }
 
 
-fn get_path_argument() -> Result<String, String> {
-    let mut arguments: Vec<String> = env::args().collect();
+struct Arguments {
+    path: String,
+    passes: PassManager,
+    dialect: Dialect,
+    eof: Eof,
+    limits: Limits,
 
-    if arguments.len() < 2 {
-        Err("No source file in arguments".to_owned())
-    } else {
-        Ok(arguments.remove(1))
+    /// Print what each optimization pass changed
+    statistics: bool,
+
+    /// Check that no optimization pass changes what the program does
+    validate: bool,
+
+    /// Print how often every instruction ran
+    profile: bool,
+
+    /// Where to write the profile as folded stacks
+    folded: Option<String>,
+
+    /// The file to add the sections and skip outcomes covered to
+    coverage: Option<String>,
+
+    /// Where to write a record of every instruction executed
+    trace: Option<String>,
+
+    /// The sequences whose instructions are traced
+    trace_sequences: RangeInclusive<usize>,
+
+    /// Trace only every nth instruction
+    trace_every: u64,
+
+    /// The snapshot to continue running from
+    resume: Option<String>,
+
+    /// Where to write a snapshot when interrupted, instead of next to the
+    /// source file
+    snapshot: Option<String>
+}
+
+
+fn parse_arguments() -> Result<Arguments, String> {
+    let mut path = None;
+    let mut passes = PassManager::default();
+    let mut dialect = Dialect::default();
+    let mut eof = Eof::default();
+    let mut limits = Limits::default();
+    let mut statistics = false;
+    let mut validate = false;
+    let mut profile = false;
+    let mut folded = None;
+    let mut coverage = None;
+    let mut trace = None;
+    let mut trace_sequences = 0..=usize::MAX;
+    let mut trace_every = 1;
+    let mut resume = None;
+    let mut snapshot = None;
+
+    for argument in env::args().skip(1) {
+        if argument == "--stats" {
+            statistics = true;
+        } else if argument == "--validate" {
+            validate = true;
+        } else if argument == "--profile" {
+            profile = true;
+        } else if let Some(path) = argument.strip_prefix("--profile-folded=") {
+            folded = Some(path.to_owned());
+        } else if let Some(path) = argument.strip_prefix("--coverage=") {
+            coverage = Some(path.to_owned());
+        } else if let Some(path) = argument.strip_prefix("--trace=") {
+            trace = Some(path.to_owned());
+        } else if let Some(range) = argument.strip_prefix("--trace-sequences=") {
+            trace_sequences = parse_range(range).ok_or_else(|| format!("Invalid sequence range: {}", range))?;
+        } else if let Some(every) = argument.strip_prefix("--trace-every=") {
+            trace_every = every.parse().ok().filter(|&every| every > 0)
+                .ok_or_else(|| format!("Invalid sampling interval: {}", every))?;
+        } else if let Some(path) = argument.strip_prefix("--resume=") {
+            resume = Some(path.to_owned());
+        } else if let Some(path) = argument.strip_prefix("--snapshot=") {
+            snapshot = Some(path.to_owned());
+        } else if let Some(name) = argument.strip_prefix("--dialect=") {
+            dialect = Dialect::from_name(name).map_err(|e| format!("{:?}", e))?;
+        } else if let Some(name) = argument.strip_prefix("--eof=") {
+            eof = Eof::from_name(name).map_err(|e| format!("{:?}", e))?;
+        } else if limits.parse_flag(&argument).map_err(|e| format!("{:?}", e))?
+            || passes.parse_flag(&argument).map_err(|e| format!("{:?}", e))? {
+            continue;
+        } else if argument.starts_with('-') {
+            return Err(format!("Unknown option: {}", argument));
+        } else {
+            path = Some(argument);
+        }
+    }
+
+    // coverage is recorded for the sections as they are written
+    if coverage.is_some() {
+        passes = PassManager::new(0);
     }
+
+    match path {
+        Some(path) => Ok(Arguments {
+            path, passes, dialect, eof, limits, statistics, validate, profile, folded, coverage,
+            trace, trace_sequences, trace_every, resume, snapshot
+        }),
+        None => Err("No source file in arguments".to_owned())
+    }
+}
+
+/// A single sequence, or the sequences from one to another, such as `2-5`
+fn parse_range(range: &str) -> Option<RangeInclusive<usize>> {
+    let (first, last) = match range.find('-') {
+        Some(index) => (&range[..index], &range[index + 1..]),
+        None => (range, range)
+    };
+
+    Some(first.parse().ok()?..=last.parse().ok()?)
 }
 
 
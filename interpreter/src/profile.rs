@@ -0,0 +1,245 @@
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant}
+};
+
+use karma_parser::{Sequence, Instruction};
+use karma_parser::syntax::SyntaxTree;
+
+use execution::{Observer, Position, State};
+
+
+/// How often each part of a program ran, and what it used
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Executions of every instruction, by sequence, section and index
+    pub instructions: Vec<Vec<Vec<u64>>>,
+
+    /// How often each sequence was started or jumped to
+    pub entries: Vec<u64>,
+
+    /// Time spent in each sequence
+    pub times: Vec<Duration>,
+
+    pub peak_stack: usize,
+    pub peak_deque: usize,
+
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+
+    /// The sequence being timed and when it was entered
+    current: Option<(usize, Instant)>,
+
+    /// Whether the next instruction enters its sequence
+    entering: bool
+}
+
+
+impl Profile {
+    /// An empty profile of a program
+    pub fn new(sequences: &[Sequence]) -> Profile {
+        Profile {
+            instructions: sequences.iter()
+                .map(|sequence| sequence.iter().map(|section| vec![0; section.len()]).collect())
+                .collect(),
+            entries: vec![0; sequences.len()],
+            times: vec![Duration::default(); sequences.len()],
+
+            peak_stack: 0,
+            peak_deque: 0,
+
+            input_bytes: 0,
+            output_bytes: 0,
+
+            current: None,
+            entering: true
+        }
+    }
+
+    /// Executions of a section, which always runs from its first instruction
+    pub fn section(&self, sequence: usize, section: usize) -> u64 {
+        self.instructions[sequence][section].first().cloned().unwrap_or(0)
+    }
+
+    /// Executions of all instructions in a sequence
+    pub fn executed(&self, sequence: usize) -> u64 {
+        self.instructions[sequence].iter().flat_map(|section| section.iter()).sum()
+    }
+
+
+    /// Print totals and the source with counts in the margin. Below each line,
+    /// the instructions that ran equally often are underlined together.
+    pub fn write_listing(&self, tree: &SyntaxTree, sequences: &[Sequence], output: &mut dyn Write) -> io::Result<()> {
+        let sections: u64 = (0..sequences.len())
+            .flat_map(|sequence| (0..sequences[sequence].len()).map(move |section| (sequence, section)))
+            .map(|(sequence, section)| self.section(sequence, section))
+            .sum();
+
+        writeln!(output, "instructions: {}, sections: {}, sequences entered: {}",
+                 (0..sequences.len()).map(|sequence| self.executed(sequence)).sum::<u64>(),
+                 sections,
+                 self.entries.iter().sum::<u64>())?;
+        writeln!(output, "input: {} bytes, output: {} bytes", self.input_bytes, self.output_bytes)?;
+        writeln!(output, "peak stack: {}, peak deque: {}", self.peak_stack, self.peak_deque)?;
+        writeln!(output, "time: {:.6} seconds", self.times.iter().sum::<Duration>().as_secs_f64())?;
+        writeln!(output)?;
+        writeln!(output, "{:>10} {:>10} |", "entries", "seconds")?;
+
+        for (index, line) in tree.lines.iter().enumerate() {
+            let sequence = index + 1;
+            if sequence >= sequences.len() - 1 {
+                break;
+            }
+
+            writeln!(output, "{:>10} {:>10.6} | {}{}", self.entries[sequence], self.times[sequence].as_secs_f64(),
+                     line.code(), line.comment)?;
+
+            let counts: Vec<(usize, &Instruction, u64)> = sequences[sequence].iter()
+                .zip(&self.instructions[sequence])
+                .enumerate()
+                .flat_map(|(section, (instructions, counts))| instructions.iter().zip(counts).map(move |(i, &c)| (section, i, c)))
+                .collect();
+
+            let mut columns = line.instruction_columns();
+            if counts.len() != columns.len() + 1 || counts.last().map(|&(_, i, _)| i) != Some(&Instruction::Exit) {
+                // optimized code no longer matches the source
+                for &(section, instruction, count) in counts.iter().filter(|&&(_, _, count)| count > 0) {
+                    writeln!(output, "{:>10} {:>10} | section {}: {:?}", count, "", section, instruction)?;
+                }
+
+                continue;
+            }
+
+            let exit = columns.last().map_or(0, |column| column + 1);
+            columns.push(exit);
+
+            let mut start = 0;
+            while start < counts.len() {
+                let count = counts[start].2;
+                let end = start + counts[start..].iter().take_while(|&&(_, _, c)| c == count).count();
+
+                if count > 0 {
+                    let marks = columns[end - 1] + 1 - columns[start];
+                    writeln!(output, "{:>10} {:>10} | {}{}", count, "", " ".repeat(columns[start]), "^".repeat(marks))?;
+                }
+
+                start = end;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Print the sections that ran as folded stacks for flamegraphs, with a
+    /// frame for the program, its line and the section, weighted by the
+    /// instructions executed
+    pub fn write_folded(&self, name: &str, output: &mut dyn Write) -> io::Result<()> {
+        for (sequence, sections) in self.instructions.iter().enumerate().take(self.instructions.len() - 1).skip(1) {
+            for (section, counts) in sections.iter().enumerate() {
+                let executed: u64 = counts.iter().sum();
+                if executed > 0 {
+                    writeln!(output, "{};line {};section {} {}", name, sequence, section, executed)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+
+    fn time(&mut self, sequence: Option<usize>) {
+        let now = Instant::now();
+
+        if let Some((previous, start)) = self.current {
+            self.times[previous] += now - start;
+        }
+
+        self.current = sequence.map(|sequence| (sequence, now));
+    }
+}
+
+
+impl Observer for Profile {
+    fn instruction(&mut self, position: Position, instruction: &Instruction, state: &State) {
+        self.instructions[position.sequence][position.section][position.instruction] += 1;
+
+        if self.entering {
+            self.entries[position.sequence] += 1;
+        }
+        self.entering = matches!(*instruction, Instruction::Jump(_, _));
+
+        if self.current.map(|(sequence, _)| sequence) != Some(position.sequence) {
+            self.time(Some(position.sequence));
+        }
+
+        self.peak_stack = self.peak_stack.max(state.stack.len());
+        self.peak_deque = self.peak_deque.max(state.deque.len());
+    }
+
+    fn input(&mut self, byte: Option<u8>) {
+        if byte.is_some() {
+            self.input_bytes += 1;
+        }
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        self.output_bytes += bytes.len() as u64;
+    }
+
+    fn stop(&mut self, state: &State) {
+        self.time(None);
+        self.entering = true;
+
+        self.peak_stack = self.peak_stack.max(state.stack.len());
+        self.peak_deque = self.peak_deque.max(state.deque.len());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use karma_parser::Eof;
+    use execution::execute_observed;
+
+    fn profile_echo(tree: &SyntaxTree) -> (Vec<Sequence>, Profile) {
+        let sequences = tree.to_sequences().unwrap();
+        let mut profile = Profile::new(&sequences);
+        let mut output = Vec::new();
+        execute_observed(&sequences, &mut &b"ab"[..], &mut output, Eof::default(), &mut profile).unwrap();
+        assert_eq!(output, b"ab");
+        (sequences, profile)
+    }
+
+    #[test]
+    fn count_sections_and_resources() {
+        let (_, profile) = profile_echo(&SyntaxTree::parse("0~},\n?\\=@,:<\n", Default::default()));
+
+        assert_eq!(&profile.entries[1..3], &[1, 3]);
+        assert_eq!((profile.section(2, 0), profile.section(2, 1), profile.section(2, 2), profile.section(2, 3)), (3, 1, 2, 0));
+        assert_eq!((profile.input_bytes, profile.output_bytes), (2, 2));
+        assert_eq!((profile.peak_stack, profile.peak_deque), (2, 1));
+    }
+
+    #[test]
+    fn list_sections_below_their_line() {
+        let tree = SyntaxTree::parse("0~},\n?\\=@,:<\n", Default::default());
+        let (sequences, profile) = profile_echo(&tree);
+
+        let mut listing = Vec::new();
+        profile.write_listing(&tree, &sequences, &mut listing).unwrap();
+        let listing = String::from_utf8(listing).unwrap();
+        assert!(listing.starts_with("instructions: 22, sections: 8, sequences entered: 5\n"), "{}", listing);
+        assert!(listing.ends_with(" | ?\\=@,:<\n         3            | ^^^^\n         1            |     ^\n         2            |      ^^\n"), "{}", listing);
+    }
+
+    #[test]
+    fn fold_stacks_of_sections() {
+        let (_, profile) = profile_echo(&SyntaxTree::parse("0~},\n?\\=@,:<\n", Default::default()));
+
+        let mut folded = Vec::new();
+        profile.write_folded("echo", &mut folded).unwrap();
+        assert_eq!(String::from_utf8(folded).unwrap(),
+                   "echo;line 1;section 0 4\necho;line 2;section 0 12\necho;line 2;section 1 1\necho;line 2;section 2 4\n");
+    }
+}
@@ -0,0 +1,353 @@
+use std::io::{self, Read, Write};
+
+use karma_parser::{
+    Sequence,
+    Instruction,
+    Instruction::*,
+    ValueSource,
+    ValueSource::*,
+    Operator,
+    QueueEnd::*,
+    Direction::*,
+    Start::*,
+};
+
+use execution::State;
+
+
+/// Marks the start of a snapshot file
+const MAGIC: &[u8; 8] = b"KARMASNP";
+
+/// The version of the format `write` produces
+const VERSION: u32 = 2;
+
+
+/// The state of a program that stopped before it exited, which can be run
+/// again from where it stopped. A snapshot is written as little endian
+/// integers:
+///
+/// ```text
+/// "KARMASNP"  version: u32  program: u64
+/// current sequence: u64  current instruction: u64, or u64::MAX for none
+/// next sections: u64 count, u64 each
+/// stack: u64 count, i64 each from the bottom
+/// deque: u64 count, i64 each from the front
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Snapshot {
+    pub state: State,
+
+    /// The `program_hash` of the program the state belongs to
+    pub program: u64
+}
+
+
+impl Snapshot {
+    /// A snapshot of a program's state
+    pub fn new(state: State, sequences: &[Sequence]) -> Snapshot {
+        Snapshot { state, program: program_hash(sequences) }
+    }
+
+    pub fn write(&self, output: &mut dyn Write) -> io::Result<()> {
+        let state = &self.state;
+
+        output.write_all(MAGIC)?;
+        output.write_all(&VERSION.to_le_bytes())?;
+        output.write_all(&self.program.to_le_bytes())?;
+
+        write_u64(output, state.current_sequence as u64)?;
+        write_u64(output, state.current_instruction.map_or(u64::MAX, |instruction| instruction as u64))?;
+
+        write_u64(output, state.next_sections.len() as u64)?;
+        for &section in &state.next_sections {
+            write_u64(output, section as u64)?;
+        }
+
+        for values in &[state.stack.iter().collect::<Vec<_>>(), state.deque.iter().collect()] {
+            write_u64(output, values.len() as u64)?;
+            for value in values {
+                output.write_all(&value.to_le_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read(input: &mut dyn Read) -> Result<Snapshot, String> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic).map_err(truncated)?;
+        if &magic != MAGIC {
+            return Err("not a snapshot".to_owned());
+        }
+
+        let mut version = [0; 4];
+        input.read_exact(&mut version).map_err(truncated)?;
+        let version = u32::from_le_bytes(version);
+        if version != VERSION {
+            return Err(format!("snapshot version {} is not supported, only {}", version, VERSION));
+        }
+
+        let program = read_u64(input)?;
+        let mut state = State::new();
+
+        state.current_sequence = read_u64(input)? as usize;
+        state.current_instruction = match read_u64(input)? {
+            u64::MAX => None,
+            instruction => Some(instruction as usize)
+        };
+
+        let sections = read_u64(input)?;
+        for _ in 0..sections {
+            state.next_sections.push(read_u64(input)? as usize);
+        }
+
+        let values = read_u64(input)?;
+        for _ in 0..values {
+            state.stack.push(read_u64(input)? as i64);
+        }
+
+        let values = read_u64(input)?;
+        for _ in 0..values {
+            state.deque.push_back(read_u64(input)? as i64);
+        }
+
+        Ok(Snapshot { state, program })
+    }
+
+
+    /// Make sure the state belongs to a program and points into it
+    pub fn check(&self, sequences: &[Sequence]) -> Result<(), String> {
+        if self.program != program_hash(sequences) {
+            return Err("the snapshot is of another program, or of the program optimized differently".to_owned());
+        }
+
+        let state = &self.state;
+        let invalid = || Err("the snapshot points outside of the program".to_owned());
+
+        if state.current_sequence >= sequences.len() || state.next_sections.len() > sequences.len() {
+            return invalid();
+        }
+
+        // only the section that is running can be the last one, since a
+        // program exits at the end of it
+        let sections_fit = state.next_sections.iter().zip(sequences).enumerate().all(|(index, (&section, sequence))| {
+            match state.current_instruction {
+                Some(instruction) if index == state.current_sequence =>
+                    section > 0 && section <= sequence.len() && instruction < sequence[section - 1].len(),
+                _ => section < sequence.len()
+            }
+        });
+        let running_fits = state.current_instruction.is_none() || state.current_sequence < state.next_sections.len();
+
+        if !sections_fit || !running_fits {
+            return invalid();
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Identifies a program by its instructions, after optimization, the same way
+/// on every machine and with every version of Rust
+pub fn program_hash(sequences: &[Sequence]) -> u64 {
+    let mut bytes = Vec::new();
+    for sequence in sequences {
+        bytes.extend_from_slice(&(sequence.len() as u64).to_le_bytes());
+
+        for section in sequence {
+            bytes.extend_from_slice(&(section.len() as u64).to_le_bytes());
+            for instruction in section {
+                encode_instruction(instruction, &mut bytes);
+            }
+        }
+    }
+
+    // FNV-1a
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Write an instruction as a tag byte followed by its operands
+fn encode_instruction(instruction: &Instruction, bytes: &mut Vec<u8>) {
+    match *instruction {
+        BitwiseNot => bytes.push(0),
+        LogicalNot => bytes.push(1),
+
+        Push(ref source) => {
+            bytes.push(2);
+            encode_source(source, bytes);
+        }
+
+        Insert(ref source, ref end) => {
+            bytes.push(3);
+            encode_source(source, bytes);
+            bytes.push(match *end { Front => 0, Back => 1 });
+        }
+
+        Destroy => bytes.push(4),
+
+        Replace(ref source) => {
+            bytes.push(5);
+            encode_source(source, bytes);
+        }
+
+        Discard(ref source) => {
+            bytes.push(6);
+            encode_source(source, bytes);
+        }
+
+        OutputCharacter(ref source) => {
+            bytes.push(7);
+            encode_source(source, bytes);
+        }
+
+        OutputNumber(ref source) => {
+            bytes.push(8);
+            encode_source(source, bytes);
+        }
+
+        SkipIfNotOne => bytes.push(9),
+
+        Jump(ref direction, ref start) => {
+            bytes.push(10);
+            bytes.push(match *direction { Previous => 0, Current => 1, Next => 2 });
+            bytes.push(match *start { Restart => 0, Continue => 1 });
+        }
+
+        Exit => bytes.push(11),
+
+        Trap(trap) => {
+            bytes.push(12);
+            bytes.push(trap.exit_code() as u8);
+        }
+
+        CountedLoop(step, ref operator) => {
+            bytes.push(13);
+            bytes.extend_from_slice(&step.to_le_bytes());
+            bytes.push(encode_operator(operator));
+        }
+    }
+}
+
+fn encode_source(source: &ValueSource, bytes: &mut Vec<u8>) {
+    match *source {
+        Constant(value) => {
+            bytes.push(0);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        Pop => bytes.push(1),
+
+        Operate(ref lhs, ref operator, ref rhs) => {
+            bytes.push(2);
+            encode_source(lhs, bytes);
+            bytes.push(encode_operator(operator));
+            encode_source(rhs, bytes);
+        }
+
+        CloneTop => bytes.push(3),
+        Remove(Front) => bytes.push(4),
+        Remove(Back) => bytes.push(5),
+        Input => bytes.push(6),
+        Equal => bytes.push(7),
+        Greater => bytes.push(8),
+    }
+}
+
+fn encode_operator(operator: &Operator) -> u8 {
+    match *operator {
+        Operator::Add => 0,
+        Operator::Sub => 1,
+        Operator::Mul => 2,
+        Operator::Div => 3,
+        Operator::Mod => 4,
+        Operator::And => 5,
+        Operator::Or => 6,
+        Operator::Xor => 7,
+    }
+}
+
+
+fn write_u64(output: &mut dyn Write, value: u64) -> io::Result<()> {
+    output.write_all(&value.to_le_bytes())
+}
+
+fn read_u64(input: &mut dyn Read) -> Result<u64, String> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes).map_err(truncated)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn truncated(error: io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => "the snapshot ends early".to_owned(),
+        _ => error.to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use karma_parser::{Eof, Limits, Trap};
+    use karma_parser::syntax::SyntaxTree;
+
+    fn parse(source: &str) -> Vec<Sequence> {
+        SyntaxTree::parse(source, Default::default()).to_sequences().unwrap()
+    }
+
+    #[test]
+    fn resume_from_snapshots() {
+        let sequences = parse("0~},\n?\\=@,:<\n");
+        let mut limits = Limits::default();
+        limits.parse_flag("--max-steps=7").unwrap();
+
+        let mut input = &b"abcdef"[..];
+        let mut output = Vec::new();
+        let mut state = State::new();
+        let mut stops = 0;
+
+        while let Err(trap) = state.run_limited(&sequences, &mut input, &mut output, Eof::default(), &limits, &mut ()) {
+            assert_eq!(trap, Trap::StepLimit);
+            stops += 1;
+
+            let mut bytes = Vec::new();
+            Snapshot::new(state, &sequences).write(&mut bytes).unwrap();
+            let snapshot = Snapshot::read(&mut &bytes[..]).unwrap();
+            snapshot.check(&sequences).unwrap();
+            state = snapshot.state;
+        }
+
+        assert_eq!(output, b"abcdef");
+        assert!(stops > 5, "{}", stops);
+    }
+
+    #[test]
+    fn reject_snapshots_of_other_programs() {
+        let sequences = parse("0~},\n?\\=@,:<\n");
+
+        assert!(Snapshot::new(State::new(), &sequences).check(&parse("1;\n")).is_err());
+
+        let mut outside = Snapshot::new(State::new(), &sequences);
+        outside.state.current_sequence = sequences.len();
+        assert!(outside.check(&sequences).is_err());
+    }
+
+    #[test]
+    fn reject_malformed_snapshots() {
+        assert!(Snapshot::read(&mut &b"KARMASNAP"[..]).is_err());
+        assert!(Snapshot::read(&mut &b"KARMASNP\x01\0\0\0"[..]).is_err());
+    }
+
+    #[test]
+    fn hash_programs_stably() {
+        let hash = |source: &str| program_hash(&parse(source));
+
+        assert_eq!(hash("1;\n"), 14653262099606175110);
+        assert_ne!(hash("1;\n"), hash("2;\n"));
+        assert_ne!(hash("1;\n"), hash("1:\n"));
+        assert_ne!(hash("1,\n"), hash("1.\n"));
+    }
+}
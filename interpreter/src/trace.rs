@@ -0,0 +1,293 @@
+use std::{
+    io::{self, Write},
+    ops::{Range, RangeInclusive}
+};
+
+use serde_json::{Map, Value};
+
+use karma_parser::{Sequence, Instruction};
+use karma_parser::syntax::SyntaxTree;
+
+use execution::{Observer, Position, State, Change, DataType};
+
+
+/// Writes a JSON Lines record for every instruction executed:
+///
+/// ```text
+/// {"step":3,"sequence":1,"section":0,"span":{"line":1,"start":3,"end":4},"instruction":"Push(Input)","stack":{"push":[97]},"deque":{},"io":{"input":97}}
+/// ```
+///
+/// `step` counts every instruction executed, including the ones left out of
+/// the trace. `span` is the columns of the source the instruction comes
+/// from, counted from 1 and up to but not including `end`. It is empty for
+/// the exit that ends a line and `null` for instructions that do not come
+/// from the source, such as optimized ones. The stack and deque record the values
+/// taken out and put in, in order, leaving out the empty lists. `io` is
+/// `{"input":<byte>}`, `{"input":"eof"}`, `{"output":"<text>"}` or `null`,
+/// where the text has a character for every byte printed.
+pub struct Trace<W: Write> {
+    output: W,
+
+    /// The columns of every instruction, by sequence, section and index
+    spans: Vec<Vec<Vec<Option<Range<usize>>>>>,
+
+    sequences: RangeInclusive<usize>,
+    every: u64,
+
+    step: u64,
+    record: Option<Record>,
+
+    /// The first error writing the trace, after which nothing is written
+    error: Option<io::Error>
+}
+
+/// What an instruction did so far
+struct Record {
+    fields: Map<String, Value>,
+
+    pop: Vec<DataType>,
+    push: Vec<DataType>,
+    remove_front: Vec<DataType>,
+    insert_front: Vec<DataType>,
+    remove_back: Vec<DataType>,
+    insert_back: Vec<DataType>,
+
+    io: Value
+}
+
+
+impl<W: Write> Trace<W> {
+    /// Trace every instruction of a program parsed from `tree`
+    pub fn new(output: W, tree: &SyntaxTree, sequences: &[Sequence]) -> Trace<W> {
+        Trace {
+            output,
+            spans: instruction_spans(tree, sequences),
+            sequences: 0..=usize::MAX,
+            every: 1,
+            step: 0,
+            record: None,
+            error: None
+        }
+    }
+
+    /// Only trace the instructions of some sequences
+    pub fn with_sequences(mut self, sequences: RangeInclusive<usize>) -> Trace<W> {
+        self.sequences = sequences;
+        self
+    }
+
+    /// Only trace every nth step, starting with the first
+    pub fn with_every(mut self, every: u64) -> Trace<W> {
+        self.every = every.max(1);
+        self
+    }
+
+    /// The output, or the first error writing to it
+    pub fn finish(mut self) -> io::Result<W> {
+        self.emit();
+
+        match self.error {
+            Some(error) => Err(error),
+            None => self.output.flush().map(|_| self.output)
+        }
+    }
+
+
+    fn emit(&mut self) {
+        let record = match self.record.take() {
+            Some(record) => record,
+            None => return
+        };
+
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.output, "{}", record.finish()) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+
+impl<W: Write> Observer for Trace<W> {
+    fn instruction(&mut self, position: Position, instruction: &Instruction, _state: &State) {
+        self.emit();
+        self.step += 1;
+
+        if !self.sequences.contains(&position.sequence) || !(self.step - 1).is_multiple_of(self.every) {
+            return;
+        }
+
+        let span = match self.spans[position.sequence][position.section][position.instruction] {
+            Some(ref span) => json!({ "line": position.sequence, "start": span.start + 1, "end": span.end + 1 }),
+            None => Value::Null
+        };
+
+        let fields = json!({
+            "step": self.step,
+            "sequence": position.sequence,
+            "section": position.section,
+            "span": span,
+            "instruction": format!("{:?}", instruction)
+        });
+
+        if let Value::Object(fields) = fields {
+            self.record = Some(Record::new(fields));
+        }
+    }
+
+    fn change(&mut self, change: Change) {
+        if let Some(ref mut record) = self.record {
+            match change {
+                Change::Push(value) => record.push.push(value),
+                Change::Pop(value) => record.pop.push(value),
+                Change::InsertFront(value) => record.insert_front.push(value),
+                Change::InsertBack(value) => record.insert_back.push(value),
+                Change::RemoveFront(value) => record.remove_front.push(value),
+                Change::RemoveBack(value) => record.remove_back.push(value)
+            }
+        }
+    }
+
+    fn input(&mut self, byte: Option<u8>) {
+        if let Some(ref mut record) = self.record {
+            record.io = match byte {
+                Some(byte) => json!({ "input": byte }),
+                None => json!({ "input": "eof" })
+            };
+        }
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        if let Some(ref mut record) = self.record {
+            let text: String = bytes.iter().map(|&byte| byte as char).collect();
+            record.io = json!({ "output": text });
+        }
+    }
+
+    fn stop(&mut self, _state: &State) {
+        self.emit();
+    }
+}
+
+
+impl Record {
+    fn new(fields: Map<String, Value>) -> Record {
+        Record {
+            fields,
+            pop: Vec::new(),
+            push: Vec::new(),
+            remove_front: Vec::new(),
+            insert_front: Vec::new(),
+            remove_back: Vec::new(),
+            insert_back: Vec::new(),
+            io: Value::Null
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let stack = json_lists(&[("pop", &self.pop), ("push", &self.push)]);
+        let deque = json_lists(&[
+            ("remove_front", &self.remove_front),
+            ("insert_front", &self.insert_front),
+            ("remove_back", &self.remove_back),
+            ("insert_back", &self.insert_back)
+        ]);
+
+        self.fields.insert("stack".to_owned(), stack);
+        self.fields.insert("deque".to_owned(), deque);
+        self.fields.insert("io".to_owned(), self.io);
+
+        Value::Object(self.fields).to_string()
+    }
+}
+
+
+/// The columns of every instruction in the source, or `None` for all of a
+/// sequence if optimizations changed it. The exit that ends a line is an
+/// empty span after its code.
+fn instruction_spans(tree: &SyntaxTree, sequences: &[Sequence]) -> Vec<Vec<Vec<Option<Range<usize>>>>> {
+    sequences.iter().enumerate().map(|(sequence, sections)| {
+        let instructions: Vec<&Instruction> = sections.iter().flat_map(|section| section.iter()).collect();
+
+        let mut spans = match tree.lines.get(sequence.wrapping_sub(1)) {
+            Some(line) => line.instruction_spans(),
+            None => Vec::new()
+        };
+
+        let matches = sequence > 0 && sequence < sequences.len() - 1
+            && instructions.len() == spans.len() + 1
+            && instructions.last() == Some(&&Instruction::Exit);
+
+        if matches {
+            let exit = spans.last().map_or(0, |span| span.end);
+            spans.push(exit..exit);
+        } else {
+            spans.clear();
+        }
+
+        let mut spans = spans.into_iter().map(Some);
+
+        sections.iter().map(|section| {
+            section.iter().map(|_| spans.next().unwrap_or(None)).collect()
+        }).collect()
+    }).collect()
+}
+
+fn json_lists(lists: &[(&str, &Vec<DataType>)]) -> Value {
+    let fields = lists.iter()
+        .filter(|&&(_, values)| !values.is_empty())
+        .map(|&(name, values)| (name.to_owned(), json!(values)))
+        .collect();
+
+    Value::Object(fields)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use karma_parser::Eof;
+    use execution::execute_observed;
+
+    fn trace(source: &str, input: &[u8], eof: Eof, configure: fn(Trace<Vec<u8>>) -> Trace<Vec<u8>>) -> Vec<String> {
+        let tree = SyntaxTree::parse(source, Default::default());
+        let sequences = tree.to_sequences().unwrap();
+
+        let mut trace = configure(Trace::new(Vec::new(), &tree, &sequences));
+        execute_observed(&sequences, &mut &input[..], &mut Vec::new(), eof, &mut trace).unwrap();
+        String::from_utf8(trace.finish().unwrap()).unwrap().lines().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn trace_every_instruction() {
+        let records = trace("1 }?:,\n2{+;\n", b"", Eof::Value(-1), |trace| trace);
+
+        assert_eq!(records.len(), 10);
+        assert_eq!(records[1], "{\"step\":2,\"sequence\":1,\"section\":0,\"span\":{\"line\":1,\"start\":3,\"end\":4},\
+                                \"instruction\":\"Insert(Pop, Front)\",\"stack\":{\"pop\":[1]},\"deque\":{\"insert_front\":[1]},\"io\":null}");
+        assert!(records[7].ends_with("\"stack\":{\"pop\":[1,2],\"push\":[3]},\"deque\":{},\"io\":null}"), "{}", records[7]);
+    }
+
+    #[test]
+    fn trace_input_and_output() {
+        let records = trace("1 }?:,\n2{+;\n", b"", Eof::Value(-1), |trace| trace);
+
+        assert!(records[2].ends_with("\"stack\":{\"push\":[-1]},\"deque\":{},\"io\":{\"input\":\"eof\"}}"), "{}", records[2]);
+        assert!(records[3].ends_with("\"stack\":{\"pop\":[-1]},\"deque\":{},\"io\":{\"output\":\"\u{ff}\"}}"), "{}", records[3]);
+    }
+
+    #[test]
+    fn span_exit_after_the_code() {
+        let records = trace("1 }?:,\n2{+;\n", b"", Eof::Value(-1), |trace| trace);
+
+        assert!(records[9].contains("\"span\":{\"line\":2,\"start\":5,\"end\":5},\"instruction\":\"Exit\""), "{}", records[9]);
+    }
+
+    #[test]
+    fn trace_some_sequences_and_steps() {
+        let records = trace("1 }?:,\n2{+;\n", b"a", Eof::default(), |trace| trace.with_sequences(1..=1).with_every(2));
+
+        let steps: Vec<&str> = records.iter().map(|record| &record[..9]).collect();
+        assert_eq!(steps, vec!["{\"step\":1", "{\"step\":3", "{\"step\":5"]);
+    }
+}
@@ -1,6 +1,11 @@
 ; ModuleID = 'karma'
 source_filename = "karma"
 
+@output = global i64* null
+@output_length = global i64 0
+@output_written = global i64 0
+@flush_lines = global i1 false
+@flush_before_read = global i1 false
 @format = global [4 x i8] c"%ld\00"
 @stack = global i64* null
 @stack_length = global i64 0
@@ -9,15 +14,6 @@ source_filename = "karma"
 @deque_back = global i64 0
 @deque_length = global i64 0
 @deque_capacity = global i64 0
-@next_section_0 = global i64 0
-@next_section_1 = global i64 0
-@next_section_2 = global i64 0
-@next_section_3 = global i64 0
-@next_section_4 = global i64 0
-@next_section_5 = global i64 0
-@next_section_6 = global i64 0
-@next_section_7 = global i64 0
-@next_section_8 = global i64 0
 
 declare i8* @malloc(i32)
 
@@ -27,103 +23,278 @@ declare void @memcpy(i8*, i8*, i64)
 
 declare void @exit(i32)
 
+define void @terminate(i32 %code) {
+entry:
+  call void @flush()
+  call void @exit(i32 %code)
+  ret void
+}
+
 declare i32 @getchar()
 
-declare i32 @putchar(i32)
+declare i8* @fopen(i8*, i8*)
+
+declare i32 @fclose(i8*)
+
+declare i32 @fprintf(i8*, i8*, ...)
+
+declare i8* @signal(i32, i8*)
+
+declare i32 @alarm(i32)
+
+declare void @_exit(i32)
 
-declare i32 @printf(i8*, ...)
+declare i32 @snprintf(i8*, i64, i8*, ...)
+
+declare i64 @write(i32, i8*, i64)
+
+declare i32 @isatty(i32)
+
+define void @flush() {
+entry:
+  %0 = load i64, i64* @output_length, align 4
+  %1 = load i64, i64* @output_written, align 4
+  %2 = icmp sge i64 %1, %0
+  br i1 %2, label %empty, label %write
+
+write:                                            ; preds = %write, %entry
+  %3 = load i64, i64* @output_written, align 4
+  %4 = load i64*, i64** @output, align 8
+  %5 = bitcast i64* %4 to i8*
+  %6 = getelementptr i8, i8* %5, i64 %3
+  %7 = sub i64 %0, %3
+  %8 = call i64 @write(i32 1, i8* %6, i64 %7)
+  %9 = icmp sgt i64 1, %8
+  %10 = add i64 %3, %8
+  store i64 %10, i64* @output_written, align 4
+  %11 = icmp sge i64 %10, %0
+  %12 = or i1 %9, %11
+  br i1 %12, label %empty, label %write
+
+empty:                                            ; preds = %write, %entry
+  store i64 0, i64* @output_length, align 4
+  store i64 0, i64* @output_written, align 4
+  br label %done
+
+done:                                             ; preds = %empty
+  ret void
+}
+
+define void @put_byte(i64 %value) {
+entry:
+  %0 = load i64, i64* @output_length, align 4
+  %1 = icmp sge i64 %0, 65536
+  br i1 %1, label %make_room, label %append
+
+make_room:                                        ; preds = %entry
+  call void @flush()
+  br label %append
+
+append:                                           ; preds = %make_room, %entry
+  %2 = load i64, i64* @output_length, align 4
+  %3 = load i64*, i64** @output, align 8
+  %4 = bitcast i64* %3 to i8*
+  %5 = getelementptr i8, i8* %4, i64 %2
+  %6 = trunc i64 %value to i8
+  store i8 %6, i8* %5, align 1
+  %7 = add i64 %2, 1
+  store i64 %7, i64* @output_length, align 4
+  %8 = icmp eq i64 %value, 10
+  %9 = load i1, i1* @flush_lines, align 1
+  %10 = and i1 %8, %9
+  br i1 %10, label %end_line, label %done
+
+end_line:                                         ; preds = %append
+  call void @flush()
+  ret void
+
+done:                                             ; preds = %append
+  ret void
+}
 
 define void @puti64(i64 %value) {
 entry:
-  %0 = call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([4 x i8], [4 x i8]* @format, i32 0, i32 0), i64 %value)
+  %0 = load i64, i64* @output_length, align 4
+  %1 = icmp sgt i64 %0, 65515
+  br i1 %1, label %make_room, label %append
+
+make_room:                                        ; preds = %entry
+  call void @flush()
+  br label %append
+
+append:                                           ; preds = %make_room, %entry
+  %2 = load i64, i64* @output_length, align 4
+  %3 = load i64*, i64** @output, align 8
+  %4 = bitcast i64* %3 to i8*
+  %5 = getelementptr i8, i8* %4, i64 %2
+  %6 = call i32 (i8*, i64, i8*, ...) @snprintf(i8* %5, i64 21, i8* getelementptr inbounds ([4 x i8], [4 x i8]* @format, i32 0, i32 0), i64 %value)
+  %7 = zext i32 %6 to i64
+  %8 = add i64 %2, %7
+  store i64 %8, i64* @output_length, align 4
   ret void
 }
 
+define i64 @read() {
+entry:
+  %0 = load i1, i1* @flush_before_read, align 1
+  br i1 %0, label %flush, label %get
+
+flush:                                            ; preds = %entry
+  call void @flush()
+  br label %get
+
+get:                                              ; preds = %flush, %entry
+  %1 = call i32 @getchar()
+  %2 = sext i32 %1 to i64
+  %3 = icmp eq i64 %2, -1
+  br i1 %3, label %end, label %character
+
+character:                                        ; preds = %get
+  ret i64 %2
+
+end:                                              ; preds = %get
+  ret i64 -1
+}
+
+define i64 @divide(i64 %lhs, i64 %rhs) {
+entry:
+  %0 = icmp eq i64 %rhs, 0
+  br i1 %0, label %by_zero, label %check_overflow
+
+check_overflow:                                   ; preds = %entry
+  %1 = icmp eq i64 %lhs, -9223372036854775808
+  %2 = icmp eq i64 %rhs, -1
+  %3 = and i1 %1, %2
+  br i1 %3, label %overflow, label %divide
+
+divide:                                           ; preds = %check_overflow
+  %4 = sdiv i64 %lhs, %rhs
+  ret i64 %4
+
+by_zero:                                          ; preds = %entry
+  call void @terminate(i32 15)
+  ret i64 0
+
+overflow:                                         ; preds = %check_overflow
+  call void @terminate(i32 16)
+  ret i64 0
+}
+
+define i64 @remainder(i64 %lhs, i64 %rhs) {
+entry:
+  %0 = icmp eq i64 %rhs, 0
+  br i1 %0, label %by_zero, label %check_overflow
+
+check_overflow:                                   ; preds = %entry
+  %1 = icmp eq i64 %lhs, -9223372036854775808
+  %2 = icmp eq i64 %rhs, -1
+  %3 = and i1 %1, %2
+  br i1 %3, label %overflow, label %divide
+
+divide:                                           ; preds = %check_overflow
+  %4 = srem i64 %lhs, %rhs
+  ret i64 %4
+
+by_zero:                                          ; preds = %entry
+  call void @terminate(i32 15)
+  ret i64 0
+
+overflow:                                         ; preds = %check_overflow
+  call void @terminate(i32 16)
+  ret i64 0
+}
+
 define void @stack_resize(i64 %new_size) {
 entry:
-  %0 = load i64, i64* @stack_capacity
+  %0 = load i64, i64* @stack_capacity, align 4
   %1 = trunc i64 %new_size to i32
   %2 = mul i32 %1, 8
   %3 = mul i64 %0, 8
   %4 = call i8* @malloc(i32 %2)
-  %5 = load i64*, i64** @stack
+  %5 = load i64*, i64** @stack, align 8
   %6 = bitcast i64* %5 to i8*
   call void @memcpy(i8* %4, i8* %6, i64 %3)
   call void @free(i8* %6)
   %7 = bitcast i8* %4 to i64*
-  store i64* %7, i64** @stack
+  store i64* %7, i64** @stack, align 8
   %8 = sext i32 %1 to i64
-  store i64 %8, i64* @stack_capacity
+  store i64 %8, i64* @stack_capacity, align 4
   ret void
 }
 
 define void @push(i64 %value) {
 entry:
-  %0 = load i64, i64* @stack_length
-  %1 = load i64, i64* @stack_capacity
+  br label %check_capacity
+
+check_capacity:                                   ; preds = %entry
+  %0 = load i64, i64* @stack_length, align 4
+  %1 = load i64, i64* @stack_capacity, align 4
   %2 = add i64 %0, 1
-  store i64 %2, i64* @stack_length
+  store i64 %2, i64* @stack_length, align 4
   %3 = icmp sgt i64 %2, %1
   br i1 %3, label %grow, label %write
 
-grow:                                             ; preds = %entry
-  %4 = load i64, i64* @stack_capacity
+grow:                                             ; preds = %check_capacity
+  %4 = load i64, i64* @stack_capacity, align 4
   %5 = mul i64 %4, 2
   call void @stack_resize(i64 %5)
   br label %write
 
-write:                                            ; preds = %grow, %entry
-  %6 = load i64, i64* @stack_length
+write:                                            ; preds = %grow, %check_capacity
+  %6 = load i64, i64* @stack_length, align 4
   %7 = sub i64 %6, 1
-  %8 = load i64*, i64** @stack
+  %8 = load i64*, i64** @stack, align 8
   %9 = getelementptr i64, i64* %8, i64 %7
-  store i64 %value, i64* %9
+  store i64 %value, i64* %9, align 4
   ret void
 }
 
 define i64 @pop() {
 entry:
-  %0 = load i64, i64* @stack_length
+  %0 = load i64, i64* @stack_length, align 4
   %1 = sub i64 %0, 1
-  store i64 %1, i64* @stack_length
+  store i64 %1, i64* @stack_length, align 4
   %2 = icmp sgt i64 0, %1
   br i1 %2, label %fail, label %read
 
 fail:                                             ; preds = %entry
-  call void @exit(i32 14)
+  call void @terminate(i32 14)
   ret i64 -1
 
 read:                                             ; preds = %entry
-  %3 = load i64, i64* @stack_length
-  %4 = load i64*, i64** @stack
+  %3 = load i64, i64* @stack_length, align 4
+  %4 = load i64*, i64** @stack, align 8
   %5 = getelementptr i64, i64* %4, i64 %3
-  %6 = load i64, i64* %5
+  %6 = load i64, i64* %5, align 4
   ret i64 %6
 }
 
 define void @insert_front(i64 %value) {
 entry:
-  %0 = load i64, i64* @deque_length
-  %1 = load i64, i64* @deque_capacity
+  br label %check_capacity
+
+check_capacity:                                   ; preds = %entry
+  %0 = load i64, i64* @deque_length, align 4
+  %1 = load i64, i64* @deque_capacity, align 4
   %2 = icmp sge i64 %0, %1
   br i1 %2, label %grow, label %exit
 
-exit:                                             ; preds = %entry, %grow
-  %3 = load i64, i64* @deque_back
-  %4 = load i64, i64* @deque_length
-  %5 = load i64, i64* @deque_capacity
+exit:                                             ; preds = %check_capacity, %grow
+  %3 = load i64, i64* @deque_back, align 4
+  %4 = load i64, i64* @deque_length, align 4
+  %5 = load i64, i64* @deque_capacity, align 4
   %6 = add i64 %3, %4
   %7 = srem i64 %6, %5
-  %8 = load i64*, i64** @deque
+  %8 = load i64*, i64** @deque, align 8
   %9 = getelementptr i64, i64* %8, i64 %7
-  store i64 %value, i64* %9
-  %10 = load i64, i64* @deque_length
+  store i64 %value, i64* %9, align 4
+  %10 = load i64, i64* @deque_length, align 4
   %11 = add i64 %10, 1
-  store i64 %11, i64* @deque_length
+  store i64 %11, i64* @deque_length, align 4
   ret void
 
-grow:                                             ; preds = %entry
-  %12 = load i64, i64* @deque_capacity
+grow:                                             ; preds = %check_capacity
+  %12 = load i64, i64* @deque_capacity, align 4
   %13 = mul i64 %12, 2
   call void @deque_resize(i64 %13)
   br label %exit
@@ -131,28 +302,31 @@ grow:                                             ; preds = %entry
 
 define void @insert_back(i64 %value) {
 entry:
-  %0 = load i64, i64* @deque_length
-  %1 = load i64, i64* @deque_capacity
+  br label %check_capacity
+
+check_capacity:                                   ; preds = %entry
+  %0 = load i64, i64* @deque_length, align 4
+  %1 = load i64, i64* @deque_capacity, align 4
   %2 = icmp sge i64 %0, %1
   br i1 %2, label %grow, label %exit
 
-exit:                                             ; preds = %entry, %grow
-  %3 = load i64, i64* @deque_length
+exit:                                             ; preds = %check_capacity, %grow
+  %3 = load i64, i64* @deque_length, align 4
   %4 = add i64 %3, 1
-  store i64 %4, i64* @deque_length
-  %5 = load i64, i64* @deque_back
-  %6 = load i64, i64* @deque_capacity
+  store i64 %4, i64* @deque_length, align 4
+  %5 = load i64, i64* @deque_back, align 4
+  %6 = load i64, i64* @deque_capacity, align 4
   %7 = add i64 %5, -1
   %8 = add i64 %7, %6
   %9 = srem i64 %8, %6
-  store i64 %9, i64* @deque_back
-  %10 = load i64*, i64** @deque
+  store i64 %9, i64* @deque_back, align 4
+  %10 = load i64*, i64** @deque, align 8
   %11 = getelementptr i64, i64* %10, i64 %9
-  store i64 %value, i64* %11
+  store i64 %value, i64* %11, align 4
   ret void
 
-grow:                                             ; preds = %entry
-  %12 = load i64, i64* @deque_capacity
+grow:                                             ; preds = %check_capacity
+  %12 = load i64, i64* @deque_capacity, align 4
   %13 = mul i64 %12, 2
   call void @deque_resize(i64 %13)
   br label %exit
@@ -160,53 +334,53 @@ grow:                                             ; preds = %entry
 
 define i64 @remove_front() {
 entry:
-  %0 = load i64, i64* @deque_length
-  %1 = icmp sgt i64 0, %0
+  %0 = load i64, i64* @deque_length, align 4
+  %1 = icmp sge i64 0, %0
   br i1 %1, label %fail, label %exit
 
 exit:                                             ; preds = %entry
-  %2 = load i64, i64* @deque_length
+  %2 = load i64, i64* @deque_length, align 4
   %3 = add i64 %2, -1
-  store i64 %3, i64* @deque_length
-  %4 = load i64, i64* @deque_back
-  %5 = load i64, i64* @deque_length
-  %6 = load i64, i64* @deque_capacity
+  store i64 %3, i64* @deque_length, align 4
+  %4 = load i64, i64* @deque_back, align 4
+  %5 = load i64, i64* @deque_length, align 4
+  %6 = load i64, i64* @deque_capacity, align 4
   %7 = add i64 %4, %5
   %8 = srem i64 %7, %6
-  %9 = load i64*, i64** @deque
+  %9 = load i64*, i64** @deque, align 8
   %10 = getelementptr i64, i64* %9, i64 %8
-  %11 = load i64, i64* %10
+  %11 = load i64, i64* %10, align 4
   ret i64 %11
 
 fail:                                             ; preds = %entry
-  call void @exit(i32 13)
+  call void @terminate(i32 13)
   ret i64 -1
 }
 
 define i64 @remove_back() {
 entry:
-  %0 = load i64, i64* @deque_length
-  %1 = icmp sgt i64 0, %0
+  %0 = load i64, i64* @deque_length, align 4
+  %1 = icmp sge i64 0, %0
   br i1 %1, label %fail, label %exit
 
 exit:                                             ; preds = %entry
-  %2 = load i64, i64* @deque_back
-  %3 = load i64*, i64** @deque
+  %2 = load i64, i64* @deque_back, align 4
+  %3 = load i64*, i64** @deque, align 8
   %4 = getelementptr i64, i64* %3, i64 %2
-  %5 = load i64, i64* %4
-  %6 = load i64, i64* @deque_length
+  %5 = load i64, i64* %4, align 4
+  %6 = load i64, i64* @deque_length, align 4
   %7 = add i64 %6, -1
-  store i64 %7, i64* @deque_length
-  %8 = load i64, i64* @deque_back
-  %9 = load i64, i64* @deque_capacity
+  store i64 %7, i64* @deque_length, align 4
+  %8 = load i64, i64* @deque_back, align 4
+  %9 = load i64, i64* @deque_capacity, align 4
   %10 = add i64 %8, 1
   %11 = add i64 %10, %9
   %12 = srem i64 %11, %9
-  store i64 %12, i64* @deque_back
+  store i64 %12, i64* @deque_back, align 4
   ret i64 %5
 
 fail:                                             ; preds = %entry
-  call void @exit(i32 13)
+  call void @terminate(i32 13)
   ret i64 -1
 }
 
@@ -215,29 +389,29 @@ entry:
   %0 = mul i64 %new_size, 8
   %1 = trunc i64 %0 to i32
   %2 = call i8* @malloc(i32 %1)
-  %3 = load i64, i64* @deque_capacity
+  %3 = load i64, i64* @deque_capacity, align 4
   %4 = icmp eq i64 %3, 0
   br i1 %4, label %exit, label %find_layout
 
 find_layout:                                      ; preds = %entry
-  %5 = load i64, i64* @deque_back
-  %6 = load i64, i64* @deque_back
-  %7 = load i64, i64* @deque_length
-  %8 = load i64, i64* @deque_capacity
+  %5 = load i64, i64* @deque_back, align 4
+  %6 = load i64, i64* @deque_back, align 4
+  %7 = load i64, i64* @deque_length, align 4
+  %8 = load i64, i64* @deque_capacity, align 4
   %9 = add i64 %6, %7
   %10 = srem i64 %9, %8
   %11 = icmp sge i64 %5, %10
   br i1 %11, label %wrapping, label %linear
 
 wrapping:                                         ; preds = %find_layout
-  %12 = load i64, i64* @deque_capacity
-  %13 = load i64, i64* @deque_back
-  %14 = load i64, i64* @deque_back
-  %15 = load i64, i64* @deque_length
-  %16 = load i64, i64* @deque_capacity
+  %12 = load i64, i64* @deque_capacity, align 4
+  %13 = load i64, i64* @deque_back, align 4
+  %14 = load i64, i64* @deque_back, align 4
+  %15 = load i64, i64* @deque_length, align 4
+  %16 = load i64, i64* @deque_capacity, align 4
   %17 = add i64 %14, %15
   %18 = srem i64 %17, %16
-  %19 = load i64*, i64** @deque
+  %19 = load i64*, i64** @deque, align 8
   %20 = bitcast i64* %19 to i8*
   %21 = mul i64 %18, 8
   call void @memcpy(i8* %2, i8* %20, i64 %21)
@@ -249,29 +423,38 @@ wrapping:                                         ; preds = %find_layout
   %27 = bitcast i64* %26 to i8*
   call void @memcpy(i8* %25, i8* %27, i64 %23)
   %28 = sub i64 %new_size, %22
-  store i64 %28, i64* @deque_back
+  store i64 %28, i64* @deque_back, align 4
   br label %exit
 
 linear:                                           ; preds = %find_layout
-  %29 = load i64, i64* @deque_capacity
-  %30 = load i64*, i64** @deque
+  %29 = load i64, i64* @deque_capacity, align 4
+  %30 = load i64*, i64** @deque, align 8
   %31 = bitcast i64* %30 to i8*
   %32 = mul i64 %29, 8
   call void @memcpy(i8* %2, i8* %31, i64 %32)
   br label %exit
 
 exit:                                             ; preds = %wrapping, %linear, %entry
-  %33 = load i64*, i64** @deque
+  %33 = load i64*, i64** @deque, align 8
   %34 = bitcast i64* %33 to i8*
   call void @free(i8* %34)
   %35 = bitcast i8* %2 to i64*
-  store i64* %35, i64** @deque
-  store i64 %new_size, i64* @deque_capacity
+  store i64* %35, i64** @deque, align 8
+  store i64 %new_size, i64* @deque_capacity, align 4
   ret void
 }
 
 define i32 @main() {
 init_stack:
+  %0 = call i8* @malloc(i32 65536)
+  %1 = bitcast i8* %0 to i64*
+  store i64* %1, i64** @output, align 8
+  %2 = call i32 @isatty(i32 1)
+  %3 = icmp sgt i32 %2, 0
+  store i1 %3, i1* @flush_lines, align 1
+  %4 = call i32 @isatty(i32 0)
+  %5 = icmp sgt i32 %4, 0
+  store i1 %5, i1* @flush_before_read, align 1
   call void @stack_resize(i64 16)
   br label %init_deque
 
@@ -280,183 +463,67 @@ init_deque:                                       ; preds = %init_stack
   br label %entry
 
 entry:                                            ; preds = %init_deque
-  store i64 0, i64* @next_section_1
-  br label %jump_table_1
+  br label %section_1_0
 
-exit:                                             ; preds = %section_8_0, %section_7_0, %section_6_1, %section_5_1, %section_4_1, %section_3_1, %section_2_1, %section_1_1, %section_0_0
+exit:                                             ; preds = %section_7_0, %section_0_0
+  call void @terminate(i32 0)
   ret i32 0
 
-panic:                                            ; preds = %jump_table_8, %jump_table_7, %jump_table_6, %jump_table_5, %jump_table_4, %jump_table_3, %jump_table_2, %jump_table_1, %jump_table_0
+panic:                                            ; No predecessors!
   ret i32 1
 
-section_0_0:                                      ; preds = %jump_table_0
+section_0_0:                                      ; No predecessors!
   br label %exit
 
-jump_table_0:                                     ; No predecessors!
-  %0 = load i64, i64* @next_section_0
-  switch i64 %0, label %panic [
-    i64 0, label %section_0_0
-  ]
+section_1_0:                                      ; preds = %entry
+  br label %section_2_0
 
-section_1_0:                                      ; preds = %jump_table_1
-  store i64 1, i64* @next_section_1
-  store i64 0, i64* @next_section_2
-  br label %jump_table_2
+section_2_0:                                      ; preds = %section_1_0
+  br label %section_3_0
 
-section_1_1:                                      ; preds = %jump_table_1
-  br label %exit
-
-jump_table_1:                                     ; preds = %entry
-  %1 = load i64, i64* @next_section_1
-  switch i64 %1, label %panic [
-    i64 0, label %section_1_0
-    i64 1, label %section_1_1
-  ]
+section_3_0:                                      ; preds = %section_2_0
+  br label %section_4_0
 
-section_2_0:                                      ; preds = %jump_table_2
-  store i64 1, i64* @next_section_2
-  store i64 0, i64* @next_section_3
-  br label %jump_table_3
-
-section_2_1:                                      ; preds = %jump_table_2
-  br label %exit
-
-jump_table_2:                                     ; preds = %section_1_0
-  %2 = load i64, i64* @next_section_2
-  switch i64 %2, label %panic [
-    i64 0, label %section_2_0
-    i64 1, label %section_2_1
-  ]
-
-section_3_0:                                      ; preds = %jump_table_3
-  store i64 1, i64* @next_section_3
-  store i64 0, i64* @next_section_4
-  br label %jump_table_4
+section_4_0:                                      ; preds = %section_3_0
+  call void @put_byte(i64 72)
+  call void @put_byte(i64 101)
+  call void @push(i64 108)
+  call void @put_byte(i64 108)
+  %6 = call i64 @pop()
+  call void @push(i64 %6)
+  call void @put_byte(i64 %6)
+  %7 = call i64 @pop()
+  %8 = add i64 3, %7
+  call void @put_byte(i64 %8)
+  call void @put_byte(i64 44)
+  call void @put_byte(i64 32)
+  br label %section_5_0
 
-section_3_1:                                      ; preds = %jump_table_3
-  br label %exit
+section_5_0:                                      ; preds = %section_4_0
+  br label %section_6_0
 
-jump_table_3:                                     ; preds = %section_2_0
-  %3 = load i64, i64* @next_section_3
-  switch i64 %3, label %panic [
-    i64 0, label %section_3_0
-    i64 1, label %section_3_1
-  ]
+section_6_0:                                      ; preds = %section_5_0
+  br label %section_7_0
 
-section_4_0:                                      ; preds = %jump_table_4
-  %4 = call i32 @putchar(i32 72)
-  %5 = call i32 @putchar(i32 101)
-  call void @push(i64 108)
-  %6 = call i32 @putchar(i32 108)
-  %7 = call i64 @pop()
-  call void @push(i64 %7)
-  call void @push(i64 %7)
-  %8 = call i64 @pop()
-  %9 = trunc i64 %8 to i32
-  %10 = call i32 @putchar(i32 %9)
-  call void @push(i64 3)
+section_7_0:                                      ; preds = %section_6_0
+  call void @push(i64 119)
+  call void @put_byte(i64 119)
+  %9 = call i64 @pop()
+  %10 = add i64 -8, %9
+  call void @push(i64 %10)
   %11 = call i64 @pop()
+  call void @push(i64 %11)
+  call void @put_byte(i64 %11)
   %12 = call i64 @pop()
-  %13 = add i64 %11, %12
+  %13 = add i64 3, %12
   call void @push(i64 %13)
   %14 = call i64 @pop()
-  %15 = trunc i64 %14 to i32
-  %16 = call i32 @putchar(i32 %15)
-  %17 = call i32 @putchar(i32 44)
-  %18 = call i32 @putchar(i32 32)
-  store i64 1, i64* @next_section_4
-  store i64 0, i64* @next_section_5
-  br label %jump_table_5
-
-section_4_1:                                      ; preds = %jump_table_4
+  call void @push(i64 %14)
+  call void @put_byte(i64 %14)
+  %15 = call i64 @pop()
+  %16 = add i64 -6, %15
+  call void @put_byte(i64 %16)
+  call void @put_byte(i64 100)
+  call void @put_byte(i64 33)
   br label %exit
-
-jump_table_4:                                     ; preds = %section_3_0
-  %19 = load i64, i64* @next_section_4
-  switch i64 %19, label %panic [
-    i64 0, label %section_4_0
-    i64 1, label %section_4_1
-  ]
-
-section_5_0:                                      ; preds = %jump_table_5
-  store i64 1, i64* @next_section_5
-  store i64 0, i64* @next_section_6
-  br label %jump_table_6
-
-section_5_1:                                      ; preds = %jump_table_5
-  br label %exit
-
-jump_table_5:                                     ; preds = %section_4_0
-  %20 = load i64, i64* @next_section_5
-  switch i64 %20, label %panic [
-    i64 0, label %section_5_0
-    i64 1, label %section_5_1
-  ]
-
-section_6_0:                                      ; preds = %jump_table_6
-  store i64 1, i64* @next_section_6
-  store i64 0, i64* @next_section_7
-  br label %jump_table_7
-
-section_6_1:                                      ; preds = %jump_table_6
-  br label %exit
-
-jump_table_6:                                     ; preds = %section_5_0
-  %21 = load i64, i64* @next_section_6
-  switch i64 %21, label %panic [
-    i64 0, label %section_6_0
-    i64 1, label %section_6_1
-  ]
-
-section_7_0:                                      ; preds = %jump_table_7
-  call void @push(i64 119)
-  %22 = call i32 @putchar(i32 119)
-  call void @push(i64 -8)
-  %23 = call i64 @pop()
-  %24 = call i64 @pop()
-  %25 = add i64 %23, %24
-  call void @push(i64 %25)
-  %26 = call i64 @pop()
-  call void @push(i64 %26)
-  call void @push(i64 %26)
-  %27 = call i64 @pop()
-  %28 = trunc i64 %27 to i32
-  %29 = call i32 @putchar(i32 %28)
-  call void @push(i64 3)
-  %30 = call i64 @pop()
-  %31 = call i64 @pop()
-  %32 = add i64 %30, %31
-  call void @push(i64 %32)
-  %33 = call i64 @pop()
-  call void @push(i64 %33)
-  call void @push(i64 %33)
-  %34 = call i64 @pop()
-  %35 = trunc i64 %34 to i32
-  %36 = call i32 @putchar(i32 %35)
-  call void @push(i64 -6)
-  %37 = call i64 @pop()
-  %38 = call i64 @pop()
-  %39 = add i64 %37, %38
-  call void @push(i64 %39)
-  %40 = call i64 @pop()
-  %41 = trunc i64 %40 to i32
-  %42 = call i32 @putchar(i32 %41)
-  %43 = call i32 @putchar(i32 100)
-  %44 = call i32 @putchar(i32 33)
-  br label %exit
-
-jump_table_7:                                     ; preds = %section_6_0
-  %45 = load i64, i64* @next_section_7
-  switch i64 %45, label %panic [
-    i64 0, label %section_7_0
-  ]
-
-section_8_0:                                      ; preds = %jump_table_8
-  br label %exit
-
-jump_table_8:                                     ; No predecessors!
-  %46 = load i64, i64* @next_section_8
-  switch i64 %46, label %panic [
-    i64 0, label %section_8_0
-  ]
 }
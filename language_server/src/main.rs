@@ -0,0 +1,220 @@
+extern crate karma_parser;
+#[macro_use]
+extern crate serde_json;
+
+
+use std::{
+    io::{self, BufRead, Write},
+    process::exit,
+};
+
+mod server;
+use server::Server;
+
+mod transport;
+use transport::{read_message, write_message};
+
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    match serve(&mut stdin.lock(), &mut stdout.lock()) {
+        Ok(true) => (),
+        Ok(false) => exit(1),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(1);
+        }
+    }
+}
+
+
+/// Answer messages until the client asks the server to exit. Returns whether
+/// the server was shut down properly first.
+fn serve<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<bool> {
+    let mut server = Server::new();
+
+    while let Some(message) = read_message(reader)? {
+        for reply in server.handle(&message) {
+            write_message(writer, &reply)?;
+        }
+
+        if server.has_exited() {
+            break;
+        }
+    }
+
+    Ok(server.was_shut_down())
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::io::Cursor;
+
+    const URI: &str = "file:///test.kar";
+
+
+    /// A client that sends a fixed script of messages to an in-process server
+    struct Client {
+        input: Vec<u8>,
+        next_id: u64,
+    }
+
+    impl Client {
+        fn new() -> Client {
+            let mut client = Client { input: Vec::new(), next_id: 1 };
+            client.request("initialize", json!({ "capabilities": {} }));
+            client.notify("initialized", json!({}));
+            client
+        }
+
+        /// Send a request and return its id
+        fn request(&mut self, method: &str, params: Value) -> u64 {
+            let id = self.next_id;
+            self.next_id += 1;
+
+            let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+            write_message(&mut self.input, &message).unwrap();
+            id
+        }
+
+        fn notify(&mut self, method: &str, params: Value) {
+            let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+            write_message(&mut self.input, &message).unwrap();
+        }
+
+        fn open(&mut self, text: &str) {
+            self.notify("textDocument/didOpen", json!({
+                "textDocument": { "uri": URI, "languageId": "karma", "version": 1, "text": text }
+            }));
+        }
+
+        fn at(&mut self, method: &str, line: u64, character: u64) -> u64 {
+            self.request(method, json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character }
+            }))
+        }
+
+        /// Shut the server down and collect everything it sent
+        fn run(mut self) -> Vec<Value> {
+            self.request("shutdown", Value::Null);
+            self.notify("exit", Value::Null);
+
+            let mut output = Vec::new();
+            assert!(serve(&mut Cursor::new(self.input), &mut output).unwrap());
+
+            let mut reader = Cursor::new(output);
+            let mut messages = Vec::new();
+            while let Some(message) = read_message(&mut reader).unwrap() {
+                messages.push(message);
+            }
+
+            messages
+        }
+    }
+
+    fn response(messages: &[Value], id: u64) -> &Value {
+        &messages.iter().find(|message| message["id"] == id).unwrap()["result"]
+    }
+
+    fn diagnostics(messages: &[Value]) -> Vec<&Value> {
+        messages.iter().filter(|message| message["method"] == "textDocument/publishDiagnostics").collect()
+    }
+
+
+    #[test]
+    fn publish_diagnostics() {
+        let mut client = Client::new();
+        client.open("1##\n");
+        client.notify("textDocument/didChange", json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "1#\n" }]
+        }));
+        client.open("");
+        let messages = client.run();
+
+        let published = diagnostics(&messages);
+        assert_eq!(published.len(), 3);
+
+        let first = &published[0]["params"]["diagnostics"][0];
+        assert_eq!(first["code"], "K006");
+        assert_eq!(first["severity"], 1);
+        assert_eq!(first["range"]["start"], json!({ "line": 0, "character": 2 }));
+
+        assert_eq!(published[1]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn hover_commands() {
+        let mut client = Client::new();
+        client.open("12}\\,\n;\n");
+        let digit = client.at("textDocument/hover", 0, 1);
+        let clone = client.at("textDocument/hover", 0, 3);
+        let whitespace = client.at("textDocument/hover", 5, 0);
+        let messages = client.run();
+
+        let digit = response(&messages, digit)["contents"]["value"].as_str().unwrap().to_owned();
+        assert!(digit.starts_with("`2` Push the digit"), "{}", digit);
+        assert!(digit.contains("Stack: 1 value"), "{}", digit);
+        assert!(digit.contains("Deque: 0 values"), "{}", digit);
+
+        let clone = response(&messages, clone)["contents"]["value"].as_str().unwrap().to_owned();
+        assert!(clone.contains("Stack: 1 value  \nDeque: 1 value"), "{}", clone);
+
+        assert_eq!(*response(&messages, whitespace), Value::Null);
+    }
+
+    #[test]
+    fn go_to_jump_targets() {
+        let mut client = Client::new();
+        client.open("1,\n2\\@.'<\n3.'\n");
+        let next = client.at("textDocument/definition", 0, 1);
+        let restart = client.at("textDocument/definition", 1, 5);
+        let previous = client.at("textDocument/definition", 2, 2);
+        let digit = client.at("textDocument/definition", 0, 0);
+        let messages = client.run();
+
+        let targets = |id| -> Vec<Value> {
+            response(&messages, id).as_array().unwrap().iter().map(|target| target["range"]["start"].clone()).collect()
+        };
+
+        assert_eq!(targets(next), vec![json!({ "line": 1, "character": 0 })]);
+        assert_eq!(targets(restart), vec![json!({ "line": 1, "character": 0 })]);
+
+        // `'` continues the previous line after the `.` or `'` that left it
+        assert_eq!(targets(previous), vec![json!({ "line": 1, "character": 4 }), json!({ "line": 1, "character": 5 })]);
+
+        assert_eq!(*response(&messages, digit), Value::Null);
+    }
+
+    #[test]
+    fn list_and_format_lines() {
+        let mut client = Client::new();
+        client.open("1  2+,   Add\n;\n");
+        let symbols = client.request("textDocument/documentSymbol", json!({ "textDocument": { "uri": URI } }));
+        let formatting = client.request("textDocument/formatting", json!({
+            "textDocument": { "uri": URI },
+            "options": { "tabSize": 4, "insertSpaces": true }
+        }));
+        let unknown = client.request("textDocument/rename", json!({}));
+        let messages = client.run();
+
+        let symbols = response(&messages, symbols);
+        assert_eq!(symbols[0]["name"], "Add");
+        assert_eq!(symbols[0]["detail"], "1  2+,");
+        assert_eq!(symbols[1]["name"], "Line 2");
+
+        let edits = response(&messages, formatting);
+        assert_eq!(edits[0]["newText"], "1 2+,  Add\n;\n");
+        assert_eq!(edits[0]["range"]["end"], json!({ "line": 2, "character": 0 }));
+
+        let error = &messages.iter().find(|message| message["id"] == unknown).unwrap()["error"];
+        assert_eq!(error["code"], -32601);
+    }
+}
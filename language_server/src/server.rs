@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use karma_parser::{
+    Dialect,
+    Sequence,
+    depth::{Depth, Depths, DepthAnalysis},
+    flow::{FlowGraph, Location},
+    format::format,
+    lint::{self, Severity},
+    syntax::{SyntaxTree, Token},
+};
+
+
+/// JSON-RPC error codes
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INVALID_REQUEST: i64 = -32600;
+
+/// LSP symbol kind of a line
+const FUNCTION: u64 = 12;
+
+
+/// The state of a language server, independent of how messages are exchanged
+pub struct Server {
+    /// The text of every open document, by URI
+    documents: HashMap<String, String>,
+    dialect: Dialect,
+
+    shut_down: bool,
+    exited: bool,
+}
+
+type RequestResult = Result<Value, (i64, String)>;
+
+
+/// A parsed document
+struct Document {
+    tree: SyntaxTree,
+    sequences: Option<Vec<Sequence>>,
+
+    /// The text of each line without its line ending
+    lines: Vec<String>,
+}
+
+
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            documents: HashMap::new(),
+            dialect: Dialect::Standard,
+            shut_down: false,
+            exited: false,
+        }
+    }
+
+    /// Has the client asked the server to exit?
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Did the client shut the server down before asking it to exit?
+    pub fn was_shut_down(&self) -> bool {
+        self.shut_down
+    }
+
+
+    /// Handle a message from the client and return the messages to send back
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+
+            // responses to requests the server never sends
+            None => return Vec::new()
+        };
+
+        let params = &message["params"];
+
+        match message.get("id") {
+            Some(id) => {
+                let response = match self.request(method, params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, message)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": message }
+                    }),
+                };
+
+                vec![response]
+            }
+
+            None => self.notification(method, params)
+        }
+    }
+
+
+    fn request(&mut self, method: &str, params: &Value) -> RequestResult {
+        if self.shut_down && method != "shutdown" {
+            return Err((INVALID_REQUEST, "The server has been shut down".to_owned()));
+        }
+
+        match method {
+            "initialize" => self.initialize(params),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/documentSymbol" => self.symbols(params),
+            "textDocument/formatting" => self.formatting(params),
+
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method)))
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_owned();
+
+        match method {
+            "exit" => {
+                self.exited = true;
+                Vec::new()
+            }
+
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_owned());
+                vec![self.diagnostics(&uri)]
+            }
+
+            // the server asks for full documents on every change
+            "textDocument/didChange" => {
+                let text = params["contentChanges"].as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+
+                match text {
+                    Some(text) => {
+                        self.documents.insert(uri.clone(), text.to_owned());
+                        vec![self.diagnostics(&uri)]
+                    }
+                    None => Vec::new()
+                }
+            }
+
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![publish_diagnostics(&uri, Vec::new())]
+            }
+
+            _ => Vec::new()
+        }
+    }
+
+
+    fn initialize(&mut self, params: &Value) -> RequestResult {
+        if let Some(name) = params["initializationOptions"]["dialect"].as_str() {
+            self.dialect = Dialect::from_name(name).map_err(|e| (INVALID_PARAMS, format!("{:?}", e)))?;
+        }
+
+        Ok(json!({
+            "capabilities": {
+                "textDocumentSync": 1,
+                "hoverProvider": true,
+                "definitionProvider": true,
+                "documentSymbolProvider": true,
+                "documentFormattingProvider": true,
+            },
+            "serverInfo": { "name": "karma-lsp", "version": env!("CARGO_PKG_VERSION") }
+        }))
+    }
+
+
+    /// Parse errors and lints of a document
+    fn diagnostics(&self, uri: &str) -> Value {
+        let document = match self.document(uri) {
+            Some(document) => document,
+            None => return publish_diagnostics(uri, Vec::new())
+        };
+
+        let diagnostics = lint::lint(&document.tree).iter().map(|diagnostic| {
+            let line = diagnostic.line - 1;
+            let column = diagnostic.column - 1;
+
+            json!({
+                "range": document.range(line, column, column + 1),
+                "severity": match diagnostic.rule.severity { Severity::Error => 1, Severity::Warning => 2 },
+                "code": diagnostic.rule.id,
+                "source": "karmalint",
+                "message": format!("{} ({})", diagnostic.message, diagnostic.rule.name),
+            })
+        }).collect();
+
+        publish_diagnostics(uri, diagnostics)
+    }
+
+
+    /// What the command under the cursor does, and how full the stack and
+    /// deque are when it runs
+    fn hover(&self, params: &Value) -> RequestResult {
+        let document = self.find_document(params)?;
+        let (line, column) = document.position(&params["position"])?;
+
+        let (start, token) = match document.token_at(line, column) {
+            Some(found) => found,
+            None => return Ok(Value::Null)
+        };
+
+        let meaning = match token {
+            Token::Command(command) => format!("`{}` {}", command, meaning(command)),
+            Token::Literal(ref literal) if literal.starts_with('#') => format!("`{}` Push the number {}", literal, &literal[1..]),
+            Token::Literal(ref literal) => format!("`{}` Push the characters, the first one on top", literal),
+            Token::Whitespace(_) => return Ok(Value::Null),
+        };
+
+        let depths = match document.sequences {
+            Some(ref sequences) => match document.instruction_at(line, start) {
+                Some((location, index)) => {
+                    match DepthAnalysis::new(sequences).before(sequences, location, index) {
+                        Some(depths) => describe_depths(&depths),
+                        None => "Never executed".to_owned()
+                    }
+                }
+                None => "Never executed".to_owned()
+            },
+            None => "The program cannot be parsed".to_owned()
+        };
+
+        let width = token.text().chars().count();
+        Ok(json!({
+            "contents": { "kind": "markdown", "value": format!("{}\n\n{}", meaning, depths) },
+            "range": document.range(line, start, start + width),
+        }))
+    }
+
+
+    /// Where the jump under the cursor may continue
+    fn definition(&self, params: &Value) -> RequestResult {
+        let document = self.find_document(params)?;
+        let (line, column) = document.position(&params["position"])?;
+        let uri = params["textDocument"]["uri"].clone();
+
+        let sequences = match document.sequences {
+            Some(ref sequences) => sequences,
+            None => return Ok(Value::Null)
+        };
+
+        let (location, index) = match document.token_at(line, column) {
+            Some((start, Token::Command(',')))
+            | Some((start, Token::Command('.')))
+            | Some((start, Token::Command('\'')))
+            | Some((start, Token::Command('<'))) => match document.instruction_at(line, start) {
+                Some(instruction) => instruction,
+                None => return Ok(Value::Null)
+            },
+            _ => return Ok(Value::Null)
+        };
+
+        // a jump always ends its section
+        if index + 1 != sequences[location.sequence][location.section].len() {
+            return Ok(Value::Null);
+        }
+
+        let graph = FlowGraph::new(sequences);
+        let targets: Vec<Value> = graph.successors(location).into_iter()
+            .filter(|target| target.sequence >= 1 && target.sequence <= document.tree.lines.len())
+            .map(|target| {
+                let column = document.section_column(sequences, target);
+                json!({ "uri": uri, "range": document.range(target.sequence - 1, column, column) })
+            })
+            .collect();
+
+        Ok(Value::Array(targets))
+    }
+
+
+    /// Every line of the program, named after its comment
+    fn symbols(&self, params: &Value) -> RequestResult {
+        let document = self.find_document(params)?;
+
+        let symbols: Vec<Value> = document.tree.lines.iter().enumerate().map(|(index, line)| {
+            let comment = line.comment.trim();
+            let name = if comment.is_empty() { format!("Line {}", index + 1) } else { comment.to_owned() };
+            let width = document.lines[index].chars().count();
+            let range = document.range(index, 0, width);
+
+            json!({
+                "name": name,
+                "detail": line.code().trim(),
+                "kind": FUNCTION,
+                "range": range,
+                "selectionRange": range,
+            })
+        }).collect();
+
+        Ok(Value::Array(symbols))
+    }
+
+
+    /// Replace the whole document with its formatted version
+    fn formatting(&self, params: &Value) -> RequestResult {
+        let document = self.find_document(params)?;
+        let formatted = format(&document.tree);
+
+        // never change what a program does
+        if document.sequences.is_none() || formatted.to_sequences().ok() != document.sequences {
+            return Ok(Value::Array(Vec::new()));
+        }
+
+        let source = document.tree.to_string();
+        let formatted = formatted.to_string();
+        if formatted == source {
+            return Ok(Value::Array(Vec::new()));
+        }
+
+        let last = document.lines.len() - 1;
+        let end = document.lines[last].chars().count();
+        let range = json!({ "start": document.lsp_position(0, 0), "end": document.lsp_position(last, end) });
+
+        Ok(json!([{ "range": range, "newText": formatted }]))
+    }
+
+
+    fn find_document(&self, params: &Value) -> Result<Document, (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str()
+            .ok_or_else(|| (INVALID_PARAMS, "Missing document".to_owned()))?;
+
+        self.document(uri).ok_or_else(|| (INVALID_PARAMS, format!("Unknown document: {}", uri)))
+    }
+
+    fn document(&self, uri: &str) -> Option<Document> {
+        let text = self.documents.get(uri)?;
+        let tree = SyntaxTree::parse(text, self.dialect);
+        let sequences = tree.to_sequences().ok();
+        let lines = text.split('\n').map(|line| line.trim_end_matches('\r').to_owned()).collect();
+
+        Some(Document { tree, sequences, lines })
+    }
+}
+
+
+impl Default for Server {
+    fn default() -> Server {
+        Server::new()
+    }
+}
+
+
+impl Document {
+    /// The line and character column of an LSP position
+    fn position(&self, position: &Value) -> Result<(usize, usize), (i64, String)> {
+        let line = position["line"].as_u64().ok_or_else(|| (INVALID_PARAMS, "Missing line".to_owned()))? as usize;
+        let character = position["character"].as_u64().ok_or_else(|| (INVALID_PARAMS, "Missing character".to_owned()))? as usize;
+
+        // LSP counts UTF-16 code units
+        let column = match self.lines.get(line) {
+            Some(text) => {
+                let mut units = 0;
+                text.chars().take_while(|c| {
+                    units += c.len_utf16();
+                    units <= character
+                }).count()
+            }
+            None => 0
+        };
+
+        Ok((line, column))
+    }
+
+    fn lsp_position(&self, line: usize, column: usize) -> Value {
+        let character: usize = self.lines.get(line)
+            .map_or(0, |text| text.chars().take(column).map(char::len_utf16).sum());
+
+        json!({ "line": line, "character": character })
+    }
+
+    fn range(&self, line: usize, start: usize, end: usize) -> Value {
+        json!({ "start": self.lsp_position(line, start), "end": self.lsp_position(line, end) })
+    }
+
+
+    /// The token at a column of a line and the column it starts at
+    fn token_at(&self, line: usize, column: usize) -> Option<(usize, Token)> {
+        let mut start = 0;
+
+        for token in &self.tree.lines.get(line)?.tokens {
+            let width = token.text().chars().count();
+            if column < start + width {
+                return Some((start, token.clone()));
+            }
+
+            start += width;
+        }
+
+        None
+    }
+
+    /// The section and index of the first instruction parsed from a column
+    fn instruction_at(&self, line: usize, column: usize) -> Option<(Location, usize)> {
+        let sequences = self.sequences.as_ref()?;
+        let mut offset = self.tree.lines[line].instruction_columns().iter().position(|&c| c == column)?;
+
+        for (section, instructions) in sequences[line + 1].iter().enumerate() {
+            if offset < instructions.len() {
+                return Some((Location { sequence: line + 1, section }, offset));
+            }
+
+            offset -= instructions.len();
+        }
+
+        None
+    }
+
+    /// The column of the first command of a section
+    fn section_column(&self, sequences: &[Sequence], location: Location) -> usize {
+        if location.section == 0 {
+            return 0;
+        }
+
+        let offset: usize = sequences[location.sequence][..location.section].iter().map(Vec::len).sum();
+        let line = &self.tree.lines[location.sequence - 1];
+
+        match line.instruction_columns().get(offset) {
+            Some(&column) => column,
+            None => line.code().trim_end().chars().count()
+        }
+    }
+}
+
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics }
+    })
+}
+
+
+fn describe_depths(depths: &Depths) -> String {
+    format!("Stack: {}  \nDeque: {}", describe_depth(&depths.stack), describe_depth(&depths.deque))
+}
+
+fn describe_depth(depth: &Depth) -> String {
+    let values = |count: usize| if count == 1 { "1 value".to_owned() } else { format!("{} values", count) };
+
+    match depth.max {
+        Some(max) if max == depth.min => values(max),
+        Some(max) => format!("{} to {}", depth.min, values(max)),
+        None => format!("at least {}", values(depth.min)),
+    }
+}
+
+
+/// What a command does
+fn meaning(command: char) -> &'static str {
+    match command {
+        '+' => "Add the top value to the second value and push the sum",
+        '-' => "Subtract the second value from the top value and push the difference",
+        '*' => "Multiply the top two values",
+        '/' => "Divide the top value by the second value",
+        '%' => "Push the remainder of dividing the top value by the second value",
+        '&' => "Bitwise AND of the top two values",
+        '|' => "Bitwise OR of the top two values",
+        '^' => "Bitwise XOR of the top two values",
+        '~' => "Bitwise NOT of the top value",
+        '!' => "Push 1 if the top value is 0, otherwise 0",
+        '=' => "Pop a value and push 1 if it equals the front of the deque, otherwise 0",
+        '>' => "Pop a value and push 1 if it is greater than the front of the deque, otherwise 0",
+        '@' => "Pop a value and skip the next command unless it is 1",
+        '}' => "Pop a value and insert it at the front of the deque",
+        '{' => "Remove the front of the deque and push it",
+        '[' => "Pop a value and insert it at the back of the deque",
+        ']' => "Remove the back of the deque and push it",
+        '#' => "Destroy the top value",
+        '\\' => "Push a copy of the top value",
+        '?' => "Read a character and push it",
+        ':' => "Pop a value and print it as a character",
+        ';' => "Pop a value and print it as a number",
+        ',' => "Jump to the start of the next line",
+        '.' => "Continue the next line where it was left",
+        '\'' => "Continue the previous line where it was left",
+        '<' => "Jump to the start of this line",
+        digit if digit.is_ascii_digit() => "Push the digit",
+        _ => "Unknown command"
+    }
+}
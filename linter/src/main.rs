@@ -0,0 +1,141 @@
+extern crate karma_parser;
+
+
+use std::{
+    env,
+    fs,
+    process::exit,
+};
+
+use karma_parser::{
+    Dialect,
+    lint::{self, Diagnostic, Severity},
+    syntax::SyntaxTree,
+};
+
+
+fn main() {
+    let arguments = match parse_arguments() {
+        Ok(arguments) => arguments,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            exit(2);
+        }
+    };
+
+    if arguments.list_rules {
+        for rule in lint::RULES {
+            println!("{} {:<24} {:<8} {}", rule.id, rule.name, rule.severity, rule.description);
+        }
+
+        return;
+    }
+
+    let mut results = Vec::new();
+    for path in &arguments.paths {
+        match fs::read_to_string(path) {
+            Ok(source) => results.push((path, lint::lint(&SyntaxTree::parse(&source, arguments.dialect)))),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                exit(2);
+            }
+        }
+    }
+
+    if arguments.json {
+        let objects: Vec<String> = results.iter()
+            .flat_map(|&(path, ref diagnostics)| diagnostics.iter().map(move |diagnostic| to_json(path, diagnostic)))
+            .collect();
+
+        println!("[{}]", objects.join(","));
+    } else {
+        for &(path, ref diagnostics) in &results {
+            for diagnostic in diagnostics {
+                println!("{}:{}", path, diagnostic);
+            }
+        }
+    }
+
+    let failed = results.iter()
+        .flat_map(|result| result.1.iter())
+        .any(|diagnostic| diagnostic.rule.severity == Severity::Error);
+
+    if failed {
+        exit(1);
+    }
+}
+
+
+fn to_json(path: &str, diagnostic: &Diagnostic) -> String {
+    format!(
+        "{{\"file\":{},\"line\":{},\"column\":{},\"rule\":{},\"name\":{},\"severity\":{},\"message\":{}}}",
+        json_string(path),
+        diagnostic.line,
+        diagnostic.column,
+        json_string(diagnostic.rule.id),
+        json_string(diagnostic.rule.name),
+        json_string(&diagnostic.rule.severity.to_string()),
+        json_string(&diagnostic.message),
+    )
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+
+    for character in text.chars() {
+        match character {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            '\n' => json += "\\n",
+            '\r' => json += "\\r",
+            '\t' => json += "\\t",
+            c if (c as u32) < 0x20 => json += &format!("\\u{:04x}", c as u32),
+            c => json.push(c)
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+
+struct Arguments {
+    paths: Vec<String>,
+    dialect: Dialect,
+
+    /// Print diagnostics as a JSON array
+    json: bool,
+
+    /// Print all rules instead of linting
+    list_rules: bool,
+}
+
+
+fn parse_arguments() -> Result<Arguments, String> {
+    let mut paths = Vec::new();
+    let mut dialect = Dialect::default();
+    let mut json = false;
+    let mut list_rules = false;
+
+    for argument in env::args().skip(1) {
+        if argument == "--format=json" {
+            json = true;
+        } else if argument == "--format=text" {
+            json = false;
+        } else if argument == "--list-rules" {
+            list_rules = true;
+        } else if let Some(name) = argument.strip_prefix("--dialect=") {
+            dialect = Dialect::from_name(name).map_err(|e| format!("{:?}", e))?;
+        } else if argument.starts_with('-') {
+            return Err(format!("Unknown option: {}", argument));
+        } else {
+            paths.push(argument);
+        }
+    }
+
+    if paths.is_empty() && !list_rules {
+        return Err("No source files in arguments".to_owned());
+    }
+
+    Ok(Arguments { paths, dialect, json, list_rules })
+}
@@ -0,0 +1,66 @@
+//! Print the most frequent runs of instructions in a set of Karma programs.
+//!
+//! Usage: `cargo run --example ngrams -- [-O<level>] <length> <files>...`
+
+extern crate karma_parser;
+
+use std::{env, process};
+
+use karma_parser::{parse_file, Instruction, Sequence, optimize::PassManager};
+
+
+fn main() {
+    let mut passes = PassManager::default();
+    let mut arguments = Vec::new();
+
+    for argument in env::args().skip(1) {
+        if !passes.parse_flag(&argument).unwrap_or_else(|e| fail(&format!("{:?}", e))) {
+            arguments.push(argument);
+        }
+    }
+
+    if arguments.len() < 2 {
+        fail("Usage: ngrams [-O<level>] <length> <files>...");
+    }
+
+    let length = arguments[0].parse().unwrap_or_else(|_| fail("Invalid length"));
+
+    let programs: Vec<_> = arguments[1..].iter()
+        .map(|path| parse_file(path).unwrap_or_else(|e| fail(&format!("{}: {:?}", path, e))))
+        .map(|sequences| passes.run(sequences).0)
+        .collect();
+
+    for (ngram, count) in count_ngrams(&programs, length).iter().take(40) {
+        println!("{:>6}  {:?}", count, ngram);
+    }
+}
+
+
+/// Count how often each run of `length` instructions occurs within a section,
+/// most frequent first. Runs that occur equally often are sorted by their
+/// first occurrence.
+fn count_ngrams(programs: &[Vec<Sequence>], length: usize) -> Vec<(Vec<Instruction>, usize)> {
+    let mut counts: Vec<(Vec<Instruction>, usize)> = Vec::new();
+
+    let sections = programs.iter()
+        .flat_map(|program| program.iter())
+        .flat_map(|sequence| sequence.iter());
+
+    for section in sections {
+        for window in section.windows(length) {
+            match counts.iter_mut().find(|&&mut (ref ngram, _)| ngram[..] == *window) {
+                Some(&mut (_, ref mut count)) => *count += 1,
+                None => counts.push((window.to_vec(), 1))
+            }
+        }
+    }
+
+    counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    counts
+}
+
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
@@ -0,0 +1,37 @@
+//! Rewrite a program written in a dialect as standard Karma, so that it can
+//! be run by implementations that only know the specification.
+//!
+//! Usage: `cargo run --example standardize -- [--dialect=<name>] <file>`
+
+extern crate karma_parser;
+
+use std::{env, process};
+
+use karma_parser::{parse_file_with_dialect, unparse::unparse, Dialect};
+
+
+fn main() {
+    let mut dialect = Dialect::Literals;
+    let mut path = None;
+
+    for argument in env::args().skip(1) {
+        match argument.strip_prefix("--dialect=") {
+            Some(name) => dialect = Dialect::from_name(name).unwrap_or_else(|e| fail(&format!("{:?}", e))),
+            None => path = Some(argument)
+        }
+    }
+
+    let path = path.unwrap_or_else(|| fail("Usage: standardize [--dialect=<name>] <file>"));
+    let sequences = parse_file_with_dialect(&path, dialect).unwrap_or_else(|e| fail(&format!("{}: {:?}", path, e)));
+
+    match unparse(&sequences) {
+        Some(source) => print!("{}", source),
+        None => fail("The program cannot be written as standard Karma")
+    }
+}
+
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
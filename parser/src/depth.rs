@@ -0,0 +1,305 @@
+use parse::{
+    *,
+    Instruction::*,
+    ValueSource::*,
+};
+use flow::{FlowGraph, Location};
+
+
+/// How often the entry of a section may change before its maximums are
+/// assumed to grow without bound
+const WIDEN_AFTER: usize = 4;
+
+
+/// The possible number of values in the stack or the deque
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Depth {
+    pub min: usize,
+
+    /// `None` if there is no known upper bound
+    pub max: Option<usize>,
+}
+
+/// The possible sizes of the stack and the deque at some point of a program
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Depths {
+    pub stack: Depth,
+    pub deque: Depth,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Container {
+    Stack,
+    Deque,
+}
+
+/// An instruction that always runs out of values when it is executed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Underflow {
+    pub location: Location,
+
+    /// The index of the instruction within its section
+    pub instruction: usize,
+
+    pub container: Container,
+}
+
+
+/// The sizes of the stack and deque when each section is entered.
+///
+/// Like `FlowGraph`, the analysis ignores data, so both outcomes of every skip
+/// are assumed possible. A section that is never entered has no depths.
+#[derive(Debug)]
+pub struct DepthAnalysis {
+    entries: Vec<Vec<Option<Depths>>>,
+    underflows: Vec<Underflow>,
+}
+
+
+impl Depth {
+    fn exactly(size: usize) -> Depth {
+        Depth { min: size, max: Some(size) }
+    }
+
+    fn join(&self, other: &Depth) -> Depth {
+        let max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None
+        };
+
+        Depth { min: self.min.min(other.min), max }
+    }
+
+    /// Forget the upper bound if it grew
+    fn widen(&self, previous: &Depth) -> Depth {
+        let max = if self.max == previous.max { self.max } else { None };
+        Depth { min: self.min, max }
+    }
+
+    /// Take a value, `false` if there is never one to take
+    fn take(&mut self) -> bool {
+        if self.max == Some(0) {
+            return false;
+        }
+
+        self.min = self.min.saturating_sub(1);
+        self.max = self.max.map(|max| max - 1);
+        true
+    }
+
+    /// Look at a value without taking it
+    fn peek(&mut self) -> bool {
+        if self.max == Some(0) {
+            return false;
+        }
+
+        self.min = self.min.max(1);
+        true
+    }
+
+    fn add(&mut self) {
+        self.min += 1;
+        self.max = self.max.map(|max| max + 1);
+    }
+}
+
+
+impl Depths {
+    /// The empty stack and deque a program starts with
+    pub fn start() -> Depths {
+        Depths { stack: Depth::exactly(0), deque: Depth::exactly(0) }
+    }
+
+    /// Any number of values, as when a section is looked at on its own
+    pub fn unknown() -> Depths {
+        let unknown = Depth { min: 0, max: None };
+        Depths { stack: unknown, deque: unknown }
+    }
+
+    fn join(&self, other: &Depths) -> Depths {
+        Depths { stack: self.stack.join(&other.stack), deque: self.deque.join(&other.deque) }
+    }
+
+    fn widen(&self, previous: &Depths) -> Depths {
+        Depths { stack: self.stack.widen(&previous.stack), deque: self.deque.widen(&previous.deque) }
+    }
+
+
+    /// Update the depths to after an instruction, or tell which container the
+    /// instruction always runs out of
+    pub fn execute(&mut self, instruction: &Instruction) -> Result<(), Container> {
+        match *instruction {
+            BitwiseNot | LogicalNot => {
+                self.take_stack()?;
+                self.stack.add();
+            }
+
+            Push(ref source) => {
+                self.evaluate(source)?;
+                self.stack.add();
+            }
+
+            Insert(ref source, _) => {
+                self.evaluate(source)?;
+                self.deque.add();
+            }
+
+            Destroy | SkipIfNotOne => self.take_stack()?,
+
+            Replace(ref source) => {
+                self.take_stack()?;
+                self.evaluate(source)?;
+                self.stack.add();
+            }
+
+            Discard(ref source) | OutputCharacter(ref source) | OutputNumber(ref source) => self.evaluate(source)?,
+
+            // the top of the stack is only replaced if the loop iterates
+            CountedLoop(_, _) => {
+                self.take_deque()?;
+                if !self.deque.peek() {
+                    return Err(Container::Deque);
+                }
+                self.deque.add();
+            }
+
+            Jump(_, _) | Exit | Instruction::Trap(_) => ()
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(&mut self, source: &ValueSource) -> Result<(), Container> {
+        match *source {
+            Constant(_) | Input => (),
+            Pop => self.take_stack()?,
+            Remove(_) => self.take_deque()?,
+
+            CloneTop => if !self.stack.peek() {
+                return Err(Container::Stack);
+            },
+
+            Equal | Greater => {
+                self.take_stack()?;
+                if !self.deque.peek() {
+                    return Err(Container::Deque);
+                }
+            }
+
+            Operate(ref lhs, _, ref rhs) => {
+                self.evaluate(lhs)?;
+                self.evaluate(rhs)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn take_stack(&mut self) -> Result<(), Container> {
+        if self.stack.take() { Ok(()) } else { Err(Container::Stack) }
+    }
+
+    fn take_deque(&mut self) -> Result<(), Container> {
+        if self.deque.take() { Ok(()) } else { Err(Container::Deque) }
+    }
+}
+
+
+impl DepthAnalysis {
+    /// Follow the control flow of a program from its first section
+    pub fn new(sequences: &[Sequence]) -> DepthAnalysis {
+        let graph = FlowGraph::new(sequences);
+
+        let mut entries: Vec<Vec<Option<Depths>>> = sequences.iter()
+            .map(|sequence| vec![None; sequence.len()])
+            .collect();
+        let mut changes: Vec<Vec<usize>> = sequences.iter()
+            .map(|sequence| vec![0; sequence.len()])
+            .collect();
+        let mut underflows = Vec::new();
+
+        let start = Location { sequence: 1, section: 0 };
+        if !graph.is_reachable(start) {
+            return DepthAnalysis { entries, underflows };
+        }
+
+        entries[1][0] = Some(Depths::start());
+        let mut work = vec![start];
+
+        while let Some(location) = work.pop() {
+            let mut depths = entries[location.sequence][location.section].unwrap();
+
+            let mut failed = false;
+            for (index, instruction) in sequences[location.sequence][location.section].iter().enumerate() {
+                if let Err(container) = depths.execute(instruction) {
+                    let underflow = Underflow { location, instruction: index, container };
+                    if !underflows.contains(&underflow) {
+                        underflows.push(underflow);
+                    }
+
+                    failed = true;
+                    break;
+                }
+            }
+
+            // the program stops at an underflow
+            if failed {
+                continue;
+            }
+
+            for successor in graph.successors(location) {
+                let entry = &mut entries[successor.sequence][successor.section];
+                let count = &mut changes[successor.sequence][successor.section];
+
+                let joined = match *entry {
+                    None => depths,
+                    Some(ref previous) => {
+                        let joined = previous.join(&depths);
+                        if joined == *previous {
+                            continue;
+                        }
+
+                        *count += 1;
+                        if *count > WIDEN_AFTER { joined.widen(previous) } else { joined }
+                    }
+                };
+
+                *entry = Some(joined);
+                if !work.contains(&successor) {
+                    work.push(successor);
+                }
+            }
+        }
+
+        // an underflow found with too narrow depths may not exist after all
+        underflows.retain(|underflow| {
+            let mut depths = entries[underflow.location.sequence][underflow.location.section].unwrap();
+            let section = &sequences[underflow.location.sequence][underflow.location.section];
+            section.iter().take(underflow.instruction + 1).any(|instruction| depths.execute(instruction).is_err())
+        });
+
+        DepthAnalysis { entries, underflows }
+    }
+
+
+    /// The depths when a section is entered, `None` if it is never entered
+    pub fn at_entry(&self, location: Location) -> Option<Depths> {
+        *self.entries.get(location.sequence)?.get(location.section)?
+    }
+
+    /// The depths right before an instruction in a section is executed
+    pub fn before(&self, sequences: &[Sequence], location: Location, instruction: usize) -> Option<Depths> {
+        let mut depths = self.at_entry(location)?;
+
+        for instruction in sequences[location.sequence][location.section].iter().take(instruction) {
+            depths.execute(instruction).ok()?;
+        }
+
+        Some(depths)
+    }
+
+    /// Instructions that always run out of values, in the order they were found
+    pub fn underflows(&self) -> &[Underflow] {
+        &self.underflows
+    }
+}
@@ -17,7 +17,28 @@ pub enum Error {
     InvalidCommand(char),
 
     /// A trailing skip on a specific line in source code
-    TrailingSkip(usize)
+    TrailingSkip(usize),
+
+    /// A string literal on a specific line is missing its closing quote
+    UnterminatedString(usize),
+
+    /// A number literal on a specific line does not fit in 64 bits
+    LiteralOutOfRange(usize),
+
+    /// No dialect has the given name
+    UnknownDialect(String),
+
+    /// No optimization pass has the given name
+    UnknownPass(String),
+
+    /// An optimization level that is not a number
+    InvalidOptimizationLevel(String),
+
+    /// An end of input behavior that is neither `error` nor a number
+    InvalidEof(String),
+
+    /// A limit that is not a number, given with the flag
+    InvalidLimit(String)
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,226 @@
+
+use parse::{
+    *,
+    Instruction::*,
+    Direction::*,
+    Start::*,
+};
+
+
+/// The position of a section within the program
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Location {
+    pub sequence: usize,
+    pub section: usize
+}
+
+/// How control leaves a section
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Terminator {
+    /// Execution continues with the following section
+    FallThrough,
+
+    /// Either the following section or the one after it is executed
+    Skip,
+
+    /// Control is transferred to another sequence
+    Jump(Direction, Start),
+
+    /// The program stops
+    Exit
+}
+
+
+/// Which sections of a program may be executed and where control goes from them.
+///
+/// The analysis ignores all data: both outcomes of a skip are assumed possible.
+/// Jumps that continue a sequence may resume at any section following a jump
+/// out of that sequence.
+#[derive(Debug)]
+pub struct FlowGraph {
+    terminators: Vec<Vec<Terminator>>,
+    reachable: Vec<Vec<bool>>
+}
+
+
+impl Terminator {
+    /// Find out how control leaves a section
+    pub fn of(section: &Section) -> Terminator {
+        for instruction in section {
+            match *instruction {
+                Exit | Instruction::Trap(_) => return Terminator::Exit,
+                Jump(ref direction, ref start) => return Terminator::Jump(direction.clone(), start.clone()),
+                SkipIfNotOne => return Terminator::Skip,
+                _ => ()
+            }
+        }
+
+        Terminator::FallThrough
+    }
+}
+
+
+impl FlowGraph {
+    /// Find all reachable sections, starting from the first section of the first line
+    pub fn new(sequences: &[Sequence]) -> FlowGraph {
+        let terminators = sequences.iter()
+            .map(|sequence| sequence.iter().map(Terminator::of).collect())
+            .collect();
+
+        let reachable = sequences.iter()
+            .map(|sequence| vec![false; sequence.len()])
+            .collect();
+
+        let mut graph = FlowGraph { terminators, reachable };
+
+        if graph.contains(Location { sequence: 1, section: 0 }) {
+            graph.reachable[1][0] = true;
+            graph.propagate();
+        }
+
+        graph
+    }
+
+
+    /// Is it possible to execute a section?
+    pub fn is_reachable(&self, location: Location) -> bool {
+        self.contains(location) && self.reachable[location.sequence][location.section]
+    }
+
+    /// Is it possible to execute any section in a sequence?
+    pub fn is_sequence_reachable(&self, sequence: usize) -> bool {
+        self.reachable.get(sequence).is_some_and(|sections| sections.contains(&true))
+    }
+
+    /// How control leaves a section
+    pub fn terminator(&self, location: Location) -> &Terminator {
+        &self.terminators[location.sequence][location.section]
+    }
+
+
+    /// All sections that may execute directly after a section
+    pub fn successors(&self, location: Location) -> Vec<Location> {
+        let Location { sequence, section } = location;
+        let local = |section| Location { sequence, section };
+
+        let successors = match *self.terminator(location) {
+            Terminator::Exit => Vec::new(),
+            Terminator::FallThrough => vec![local(section + 1)],
+            Terminator::Skip => vec![local(section + 1), local(section + 2)],
+
+            Terminator::Jump(Current, Continue) => vec![local(section + 1)],
+
+            Terminator::Jump(ref direction, Restart) => match self.target(sequence, direction) {
+                Some(target) => vec![Location { sequence: target, section: 0 }],
+                None => Vec::new()
+            },
+
+            Terminator::Jump(ref direction, Continue) => match self.target(sequence, direction) {
+                Some(target) => self.resume_points(target, direction),
+                None => Vec::new()
+            },
+        };
+
+        successors.into_iter().filter(|location| self.contains(*location)).collect()
+    }
+
+
+    /// The section a jump at the end of a section always lands in, if it is
+    /// known without running the program
+    pub fn jump_destination(&self, location: Location) -> Option<Location> {
+        match *self.terminator(location) {
+            Terminator::Jump(_, _) => {
+                let successors = self.successors(location);
+                if successors.len() == 1 {
+                    Some(successors[0])
+                } else {
+                    None
+                }
+            }
+
+            _ => None
+        }
+    }
+
+
+    /// The sections a jump continuing a sequence may resume at.
+    ///
+    /// A sequence is resumed at the section following the last jump out of it.
+    /// If the jump comes from the previous line the sequence might not have been
+    /// entered yet, in which case it starts from the beginning.
+    pub fn resume_points(&self, target: usize, direction: &Direction) -> Vec<Location> {
+        let mut points = Vec::new();
+
+        let never_entered = match *direction {
+            Next => target != 1,
+            Previous => target == 0,
+            Current => false
+        };
+
+        if never_entered {
+            points.push(Location { sequence: target, section: 0 });
+        }
+
+        for (section, terminator) in self.terminators[target].iter().enumerate() {
+            let leaves = match *terminator {
+                Terminator::Jump(ref direction, _) => self.target(target, direction) != Some(target),
+                _ => false
+            };
+
+            let resume = Location { sequence: target, section: section + 1 };
+            if leaves && self.reachable[target][section] && self.contains(resume) && !points.contains(&resume) {
+                points.push(resume);
+            }
+        }
+
+        points
+    }
+
+
+    /// The sequence a jump from a sequence ends up in
+    pub fn target(&self, sequence: usize, direction: &Direction) -> Option<usize> {
+        let target = match *direction {
+            Previous => sequence.checked_sub(1)?,
+            Current => sequence,
+            Next => sequence + 1
+        };
+
+        if target < self.terminators.len() {
+            Some(target)
+        } else {
+            None
+        }
+    }
+
+
+    fn contains(&self, location: Location) -> bool {
+        location.sequence < self.terminators.len() &&
+            location.section < self.terminators[location.sequence].len()
+    }
+
+    /// Mark sections reachable until nothing changes. Resume points depend on
+    /// which sections are reachable, so a single pass is not enough.
+    fn propagate(&mut self) {
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for sequence in 0..self.reachable.len() {
+                for section in 0..self.reachable[sequence].len() {
+                    if !self.reachable[sequence][section] {
+                        continue;
+                    }
+
+                    for successor in self.successors(Location { sequence, section }) {
+                        let reachable = &mut self.reachable[successor.sequence][successor.section];
+                        if !*reachable {
+                            *reachable = true;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use parse::Dialect;
+use syntax::{SyntaxTree, Line, Token};
+
+
+/// Spaces between the longest line of code in a block and its comments
+const COMMENT_GAP: usize = 2;
+
+
+/// Lay out a program consistently without changing what it does.
+///
+/// Whitespace between commands is collapsed into single spaces and removed at
+/// the start and end of the code. Trailing comments on consecutive lines are
+/// aligned into a column. A line starting with `,` is left as soon as it is
+/// entered from above, so if its comment follows a single space it is taken
+/// to be a heading, such as `, === Init sum ===`, and is not aligned.
+/// Everything after the blank line that ends the program is left untouched.
+pub fn format(tree: &SyntaxTree) -> SyntaxTree {
+    let headings: Vec<bool> = tree.lines.iter().map(is_heading).collect();
+    let mut lines: Vec<Line> = tree.lines.iter().map(normalize_line).collect();
+
+    align_comments(&mut lines, &headings);
+
+    if let Some(last) = lines.last_mut() {
+        if last.ending.is_empty() {
+            last.ending = "\n".to_owned();
+        }
+    }
+
+    SyntaxTree {
+        dialect: tree.dialect,
+        lines,
+        trailer: tree.trailer.clone(),
+    }
+}
+
+/// Format source code, see `format`
+pub fn format_str(source_code: &str, dialect: Dialect) -> String {
+    format(&SyntaxTree::parse(source_code, dialect)).to_string()
+}
+
+
+fn normalize_line(line: &Line) -> Line {
+    let mut tokens = Vec::new();
+
+    for token in &line.tokens {
+        match *token {
+            // a single space is kept because it may separate literals, as in `#1 2`
+            Token::Whitespace(_) => if !tokens.is_empty() && !is_whitespace(tokens.last()) {
+                tokens.push(Token::Whitespace(" ".to_owned()));
+            },
+            _ => tokens.push(token.clone())
+        }
+    }
+
+    if is_whitespace(tokens.last()) {
+        tokens.pop();
+    }
+
+    let comment = line.comment.trim_end_matches([' ', '\t']).to_owned();
+
+    // a line without anything in it would end the program
+    if tokens.is_empty() && comment.is_empty() {
+        tokens.push(Token::Whitespace(" ".to_owned()));
+    }
+
+    Line { tokens, comment, ending: line.ending.clone() }
+}
+
+
+fn align_comments(lines: &mut [Line], headings: &[bool]) {
+    let is_trailing_comment = |index: usize, line: &Line| {
+        !line.comment.is_empty() && !line.tokens.is_empty() && !headings[index]
+    };
+
+    let mut start = 0;
+
+    while start < lines.len() {
+        if !is_trailing_comment(start, &lines[start]) {
+            if !lines[start].comment.is_empty() && !lines[start].tokens.is_empty() {
+                lines[start].tokens.push(Token::Whitespace(" ".to_owned()));
+            }
+
+            start += 1;
+            continue;
+        }
+
+        let length = lines[start..].iter().enumerate()
+            .take_while(|&(offset, line)| is_trailing_comment(start + offset, line))
+            .count();
+        let block = &mut lines[start..start + length];
+
+        let column = block.iter().map(code_width).max().unwrap_or(0) + COMMENT_GAP;
+        for line in block.iter_mut() {
+            let padding = column - code_width(line);
+            line.tokens.push(Token::Whitespace(" ".repeat(padding)));
+        }
+
+        start += length;
+    }
+}
+
+
+fn is_heading(line: &Line) -> bool {
+    let single_space = match line.tokens.last() {
+        Some(Token::Whitespace(whitespace)) => whitespace == " ",
+        _ => true
+    };
+
+    line.tokens.first() == Some(&Token::Command(',')) && single_space
+}
+
+fn code_width(line: &Line) -> usize {
+    line.code().chars().count()
+}
+
+fn is_whitespace(token: Option<&Token>) -> bool {
+    matches!(token, Some(&Token::Whitespace(_)))
+}
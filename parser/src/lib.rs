@@ -3,7 +3,14 @@ mod parse;
 mod error;
 mod load;
 
+pub mod depth;
+pub mod flow;
+pub mod format;
+pub mod lint;
 pub mod optimize;
+pub mod symbolic;
+pub mod syntax;
+pub mod unparse;
 
 pub use parse::*;
 pub use error::*;
@@ -15,9 +22,14 @@ use std::path::Path;
 
 /// Load a file from a file and parse it's contents into an abstract syntax tree
 pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<Vec<parse::Sequence>> {
+    parse_file_with_dialect(path, Dialect::Standard)
+}
+
+/// Load a file written in a dialect and parse it's contents
+pub fn parse_file_with_dialect<P: AsRef<Path>>(path: P, dialect: Dialect) -> Result<Vec<parse::Sequence>> {
     let source = load_file_text(path)?;
 
-    parse_str(&source)
+    parse_str_with_dialect(&source, dialect)
 }
 
 
@@ -28,6 +40,7 @@ mod tests {
     use parse::{
         Instruction::*,
         ValueSource::*,
+        Operator::*,
         Direction::*,
         Start::*,
         QueueEnd::*
@@ -83,4 +96,591 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn fold_division_of_zero() {
+        for source in &["50/", "90%"] {
+            let sequences = optimize::compute_constants(parse_str(source).unwrap());
+            assert_eq!(sequences[1], vec![vec![Push(Constant(0)), Exit]]);
+        }
+    }
+
+    #[test]
+    fn fold_division_by_zero_into_trap() {
+        for source in &["05/;", "09%;"] {
+            let sequences = optimize::compute_constants(parse_str(source).unwrap());
+            assert_eq!(sequences[1], vec![vec![Trap(parse::Trap::DivisionByZero), OutputNumber(Pop), Exit]]);
+        }
+
+        let source = unparse::unparse_number(-1) + &unparse::unparse_number(i64::MIN) + "/";
+        let sequences = optimize::compute_constants(parse_str(&source).unwrap());
+        assert_eq!(sequences[1], vec![vec![Trap(parse::Trap::DivisionOverflow), Exit]]);
+    }
+
+    #[test]
+    fn keep_overflowing_constants() {
+        let max = unparse::unparse_number(i64::MAX);
+        let min = unparse::unparse_number(i64::MIN);
+
+        for (source, operator) in &[(max.clone() + "1+", Add), (max + "2*", Mul), ("1".to_owned() + &min + "-", Sub)] {
+            let sequences = optimize::compute_constants(parse_str(source).unwrap());
+            let section = &sequences[1][0];
+
+            assert_eq!(section[section.len() - 2], Push(Operate(Box::new(Pop), operator.clone(), Box::new(Pop))));
+            assert_eq!(optimize::PassManager::default().validate(&parse_str(source).unwrap()), Ok(()));
+        }
+    }
+
+    #[test]
+    fn recognize_counting_loop() {
+        let sequences = optimize::recognize_loops(parse_str("5}0}0,\n{1+\\>@,\\}+<\n{##;").unwrap());
+        assert_eq!(sequences[2][0][0], CountedLoop(1, Add));
+
+        let sequences = optimize::all(parse_file("../karma/sum.kar").unwrap());
+        assert!(sequences.iter().flat_map(|sequence| sequence.iter()).any(|section| section[0] == CountedLoop(1, Add)));
+    }
+
+    #[test]
+    fn keep_loops_with_side_effects() {
+        for source in &["5}0}0,\n{1+\\>@,\\}\\;+<\n{##;", "5}0}0,\n{1+\\>@,\\}+<\n{##'"] {
+            let sequences = parse_str(source).unwrap();
+            assert_eq!(optimize::recognize_loops(sequences.clone()), sequences);
+        }
+    }
+
+    #[test]
+    fn run_counted_loop_in_closed_form() {
+        let naive = |step: i64, operator: &Operator, mut top: i64, mut counter: i64, bound: i64| {
+            while counter.wrapping_add(step) <= bound {
+                counter = counter.wrapping_add(step);
+                top = operator.apply(counter, top).unwrap();
+            }
+
+            (top, counter)
+        };
+
+        let cases = [(1, 0, 0, 123), (3, 7, -50, 49), (2, 1, 10, 5), (1, 0, i64::MAX - 5, i64::MAX - 1), (4, 0, i64::MIN, i64::MIN + 1000)];
+        for &(step, top, counter, bound) in &cases {
+            for operator in &[Add, Sub, Xor] {
+                assert_eq!(optimize::run_counted_loop(step, operator, top, counter, bound), Ok(naive(step, operator, top, counter, bound)));
+            }
+        }
+
+        assert_eq!(optimize::run_counted_loop(1, &Add, 0, 0, 123456789), Ok((7620789436823655, 123456789)));
+    }
+
+    #[test]
+    fn remove_deque_round_trips() {
+        let optimize = |source| optimize::remove_round_trips(optimize::compute_constants(parse_str(source).unwrap()));
+
+        for source in &["1}{", "1[]"] {
+            assert_eq!(optimize(source)[1], vec![vec![Push(Constant(1)), Exit]]);
+        }
+
+        assert_eq!(optimize("?}{")[1], vec![vec![Push(Input), Exit]]);
+        assert_eq!(optimize("?}?={}")[1], vec![vec![Push(Input), Insert(Pop, Front), Push(Input), Push(Equal), Exit]]);
+
+        // an empty stack or deque still stops the program
+        for source in &["}{", "[]", "{}", "]["] {
+            assert_eq!(optimize(source), optimize::compute_constants(parse_str(source).unwrap()), "{}", source);
+        }
+
+        assert_eq!(optimize("5}#{")[1], vec![vec![Destroy, Push(Constant(5)), Exit]]);
+        assert_eq!(optimize("3}4=3>")[1], vec![vec![Insert(Constant(3), Front), Push(Constant(0)), Push(Constant(0)), Exit]]);
+
+        // the comparison looks at the deque in between
+        assert_eq!(optimize("5}=#{")[1], vec![vec![Insert(Constant(5), Front), Push(Equal), Destroy, Push(Remove(Front)), Exit]]);
+    }
+
+    #[test]
+    fn peephole_windows_keep_their_effect() {
+        let alphabet = [
+            Insert(Pop, Front), Insert(Pop, Back), Push(Remove(Front)), Push(Remove(Back)),
+            Insert(Constant(1), Front), Insert(Constant(2), Back), Push(Constant(1)), Push(Constant(2)),
+            Push(Equal), Push(Greater), Destroy, Push(CloneTop), OutputNumber(Pop), Push(Input),
+        ];
+
+        for window in windows(&alphabet, 4) {
+            let optimized = optimize::remove_round_trips_section(window.clone());
+            assert_eq!(symbolic::execute(&optimized), symbolic::execute(&window), "{:?}", window);
+        }
+    }
+
+    #[test]
+    fn fuse_superinstructions() {
+        let fuse = |source| optimize::fuse_instructions(parse_str(source).unwrap());
+
+        assert_eq!(fuse("{1+")[1][0][0], Push(Operate(Box::new(Constant(1)), Add, Box::new(Remove(Front)))));
+        assert_eq!(fuse("\\:")[1][0][0], OutputCharacter(CloneTop));
+        assert_eq!(fuse("{#")[1][0][0], Discard(Remove(Front)));
+        assert_eq!(fuse("#{")[1][0][0], Replace(Remove(Front)));
+    }
+
+    #[test]
+    fn fused_windows_keep_their_effect() {
+        let alphabet = [
+            Push(Remove(Front)), Push(Constant(1)), Push(CloneTop), Push(Input), Push(Greater),
+            Push(Operate(Box::new(Pop), Sub, Box::new(Pop))), Insert(Pop, Back), OutputNumber(Pop), Destroy,
+        ];
+
+        for window in windows(&alphabet, 4) {
+            let section = optimize::fuse_instructions(vec![vec![window.clone()]]).remove(0).remove(0);
+            assert_eq!(symbolic::execute(&section), symbolic::execute(&window), "{:?}", window);
+        }
+    }
+
+    #[test]
+    fn parse_literals() {
+        let parse = |source| parse_str_with_dialect(source, Dialect::Literals).unwrap();
+
+        assert_eq!(parse("#123;")[1], vec![vec![Push(Constant(123)), OutputNumber(Pop), Exit]]);
+        assert_eq!(parse("\"Hi\" ::")[1], vec![vec![Push(Constant(105)), Push(Constant(72)), OutputCharacter(Pop), OutputCharacter(Pop), Exit]]);
+        assert_eq!(parse("#12#")[1], vec![vec![Push(Constant(12)), Destroy, Exit]]);
+
+        // the standard dialect has no literals
+        assert_eq!(parse_str("#1").unwrap()[1], vec![vec![Destroy, Push(Constant(1)), Exit]]);
+        assert_eq!(parse_str("1\"2").unwrap()[1], vec![vec![Push(Constant(1)), Exit]]);
+
+        assert!(matches!(parse_str_with_dialect("\"Hi", Dialect::Literals), Err(Error::UnterminatedString(1))));
+        assert!(matches!(parse_str_with_dialect("1\n#9999999999999999999", Dialect::Literals), Err(Error::LiteralOutOfRange(2))));
+    }
+
+    #[test]
+    fn unparse_literals() {
+        let source = "\"Hello, world!\":::::::::::::\n#1000;,\n#255\"a b\"<\n";
+        let sequences = parse_str_with_dialect(source, Dialect::Literals).unwrap();
+
+        let standard = unparse::unparse(&sequences).unwrap();
+        assert!(!standard.contains('"'));
+        assert_eq!(optimize::compute_constants(parse_str(&standard).unwrap()), optimize::compute_constants(sequences));
+    }
+
+    #[test]
+    fn keep_source_in_syntax_tree() {
+        let examples = [
+            include_str!("../../karma/fib.kar"),
+            include_str!("../../karma/fib_ugly.kar"),
+            include_str!("../../karma/echo.kar"),
+            include_str!("../../karma/sum.kar"),
+            include_str!("../../karma/hello_world_literals.kar"),
+            "1 2 3+  , comment\r\n\t4;\n\nignored\n",
+            "\"unterminated\n",
+        ];
+
+        for source in examples.iter() {
+            for dialect in [Dialect::Standard, Dialect::Literals].iter() {
+                let tree = syntax::SyntaxTree::parse(source, *dialect);
+                assert_eq!(tree.to_string(), *source);
+            }
+        }
+    }
+
+    #[test]
+    fn split_lines_into_code_and_comments() {
+        use syntax::Token;
+
+        let tree = syntax::SyntaxTree::parse("0, \"The sum\"  #12\r\n\n?\n", Dialect::Standard);
+        assert_eq!(tree.lines.len(), 1);
+        assert_eq!(tree.lines[0].tokens, vec![Token::Command('0'), Token::Command(','), Token::Whitespace(" ".to_owned())]);
+        assert_eq!(tree.lines[0].comment, "\"The sum\"  #12");
+        assert_eq!(tree.lines[0].ending, "\r\n");
+        assert_eq!(tree.trailer, "\n?\n");
+
+        let tree = syntax::SyntaxTree::parse("0, \"The sum\"  #12", Dialect::Literals);
+        assert_eq!(tree.lines[0].tokens[3], Token::Literal("\"The sum\"".to_owned()));
+        assert_eq!(tree.lines[0].tokens[5], Token::Literal("#12".to_owned()));
+        assert_eq!(tree.lines[0].comment, "");
+
+        let tree = syntax::SyntaxTree::parse("0 \"ab\"#12", Dialect::Literals);
+        assert_eq!(tree.lines[0].instruction_spans(), vec![0..1, 2..6, 2..6, 6..9]);
+    }
+
+    #[test]
+    fn format_source() {
+        let source = "  1 \t2+ ;  The sum\n\\10-}>{#!@, }55+>[{]@',  Check\n, === Heading ===  \n \t \nx\n\n  ignored \n";
+        let expected = "1 2+ ;                   The sum\n\\10-}>{#!@, }55+>[{]@',  Check\n, === Heading ===\n \nx\n\n  ignored \n";
+
+        assert_eq!(format::format_str(source, Dialect::Standard), expected);
+        assert_eq!(format::format_str("#1  2 \"a  b\"  c", Dialect::Literals), "#1 2 \"a  b\"  c\n");
+    }
+
+    #[test]
+    fn formatting_keeps_sequences() {
+        let mut sources: Vec<String> = [
+            include_str!("../../karma/fib.kar"),
+            include_str!("../../karma/fib_ugly.kar"),
+            include_str!("../../karma/echo.kar"),
+            include_str!("../../karma/hello_world.kar"),
+            include_str!("../../karma/sum.kar"),
+            include_str!("../../karma/hello_world_literals.kar"),
+        ].iter().map(|source| source.to_string()).collect();
+
+        // random lines made of commands, literals, whitespace and comments
+        let pieces = ["1", "#", "#2", "@", ",", "'", "{", "\"a b\"", " ", "  ", "\t", "x y", "\n", "\r\n"];
+        let mut seed: u64 = 1;
+        for _ in 0..2000 {
+            let mut source = String::new();
+            for _ in 0..12 {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                source += pieces[(seed >> 33) as usize % pieces.len()];
+            }
+
+            sources.push(source);
+        }
+
+        for source in &sources {
+            for dialect in [Dialect::Standard, Dialect::Literals].iter() {
+                let tree = syntax::SyntaxTree::parse(source, *dialect);
+                let formatted = format::format(&tree);
+
+                assert_eq!(formatted.to_sequences().ok(), tree.to_sequences().ok(), "{:?}", source);
+                assert_eq!(format::format(&formatted), formatted, "{:?}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn lint_rules() {
+        let rules = |source| -> Vec<(&str, usize, usize)> {
+            lint::lint(&syntax::SyntaxTree::parse(source, Dialect::Standard)).iter()
+                .map(|diagnostic| (diagnostic.rule.id, diagnostic.line, diagnostic.column))
+                .collect()
+        };
+
+        assert_eq!(rules("1@ ,\n"), vec![("K001", 1, 2)]);
+        assert_eq!(rules("'\n"), vec![("K002", 1, 1)]);
+        assert_eq!(rules("1,\n.\n"), vec![("K003", 2, 1)]);
+        assert_eq!(rules("12+x3+;\n12+ The sum\n"), vec![("K004", 1, 4)]);
+        assert_eq!(rules("1;\n\n2;\n"), vec![("K005", 2, 1)]);
+        assert_eq!(rules("1 ##\n"), vec![("K006", 1, 4)]);
+        assert_eq!(rules("1,\n\\}{{\n"), vec![("K007", 2, 4)]);
+        assert_eq!(rules("1@\n"), vec![("K000", 1, 1)]);
+
+        assert_eq!(rules("0,\n1@<\n"), vec![("K001", 2, 2)]);
+
+        // loops that grow the stack, skips in the middle of a line, skips of
+        // other commands and dead code are fine
+        assert_eq!(rules("0,\n1+\\@<,\n;\n"), vec![]);
+        assert_eq!(rules("11@;\n"), vec![]);
+        assert_eq!(rules("1,\n.\n'#\n"), vec![]);
+
+        for source in [
+            include_str!("../../karma/fib.kar"),
+            include_str!("../../karma/fib_ugly.kar"),
+            include_str!("../../karma/echo.kar"),
+            include_str!("../../karma/hello_world.kar"),
+            include_str!("../../karma/sum.kar"),
+        ].iter() {
+            assert_eq!(rules(source), vec![]);
+        }
+    }
+
+    #[test]
+    fn suppress_lints() {
+        let count = |source| lint::lint(&syntax::SyntaxTree::parse(source, Dialect::Standard)).len();
+
+        assert_eq!(count("1 ## karmalint: allow(K006)\n"), 0);
+        assert_eq!(count("1 ## karmalint: allow(stack-underflow, K001)\n"), 0);
+        assert_eq!(count("1 ## karmalint: allow(K001)\n"), 1);
+        assert_eq!(count("1,  karmalint: allow-file(K006)\n##\n"), 0);
+        assert_eq!(count("1,  karmalint: allow(K006)\n##\n"), 1);
+    }
+
+    #[test]
+    fn track_stack_and_deque_depths() {
+        use depth::{DepthAnalysis, Depth};
+
+        let sequences = parse_str("12}0,\n\\<\n").unwrap();
+        let analysis = DepthAnalysis::new(&sequences);
+
+        let first = analysis.at_entry(flow::Location { sequence: 1, section: 0 }).unwrap();
+        assert_eq!(first.stack, Depth { min: 0, max: Some(0) });
+
+        let before = analysis.before(&sequences, flow::Location { sequence: 1, section: 0 }, 3).unwrap();
+        assert_eq!(before.stack, Depth { min: 1, max: Some(1) });
+        assert_eq!(before.deque, Depth { min: 1, max: Some(1) });
+
+        // the loop pushes a value every time, so there is no upper bound
+        let second = analysis.at_entry(flow::Location { sequence: 2, section: 0 }).unwrap();
+        assert_eq!(second.stack, Depth { min: 2, max: None });
+        assert_eq!(second.deque, Depth { min: 1, max: Some(1) });
+
+        assert_eq!(analysis.at_entry(flow::Location { sequence: 3, section: 0 }), None);
+    }
+
+    /// Every sequence of up to `length` instructions from an alphabet
+    fn windows(alphabet: &[Instruction], length: usize) -> Vec<Section> {
+        let mut windows = vec![Vec::new()];
+        let mut all = Vec::new();
+
+        for _ in 0..length {
+            windows = windows.iter()
+                .flat_map(|window| alphabet.iter().map(move |instruction| {
+                    let mut window = window.clone();
+                    window.push(instruction.clone());
+                    window
+                }))
+                .collect();
+
+            all.extend(windows.iter().cloned());
+        }
+
+        all
+    }
+
+    #[test]
+    fn remove_code_after_loop() {
+        let sequences = parse_str("1<\n2").unwrap();
+        let removals = optimize::find_unreachable(&sequences);
+        let sequences = optimize::remove_unreachable(sequences);
+
+        assert_eq!(
+            removals,
+            vec![
+                optimize::Removal::Section { sequence: 1, section: 1, reason: optimize::Reason::FollowsJump },
+                optimize::Removal::Sequence { sequence: 2, reason: optimize::Reason::NeverJumpedTo },
+                optimize::Removal::Sequence { sequence: 3, reason: optimize::Reason::NeverJumpedTo },
+            ]
+        );
+
+        assert_eq!(
+            sequences,
+            vec![
+                vec![vec![Exit]],
+
+                vec![vec![Push(Constant(1)), Jump(Current, Restart)]],
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_resumed_sections() {
+        let sequences = parse_str("0,\n1'").unwrap();
+        let sequences = optimize::remove_unreachable(sequences);
+
+        assert_eq!(
+            sequences,
+            vec![
+                vec![vec![Exit]],
+
+                vec![vec![Push(Constant(0)), Jump(Next, Restart)], vec![Exit]],
+
+                vec![vec![Push(Constant(1)), Jump(Previous, Continue)]],
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_both_sides_of_skip() {
+        let sequences = parse_str("1@,2\n3").unwrap();
+        let removals = optimize::find_unreachable(&sequences);
+
+        assert_eq!(
+            removals,
+            vec![optimize::Removal::Sequence { sequence: 3, reason: optimize::Reason::NeverJumpedTo }]
+        );
+    }
+
+    #[test]
+    fn resolve_jumps_statically() {
+        use flow::{FlowGraph, Location};
+
+        let sequences = parse_str("0,\n1.2'\n3,").unwrap();
+        let graph = FlowGraph::new(&sequences);
+        let at = |sequence, section| Location { sequence, section };
+
+        assert_eq!(graph.jump_destination(at(1, 0)), Some(at(2, 0)));
+        assert_eq!(graph.jump_destination(at(2, 1)), Some(at(1, 1)));
+
+        // the third line may be entered for the first time or resumed after ','
+        assert_eq!(graph.jump_destination(at(2, 0)), None);
+        assert_eq!(graph.resume_points(3, &Next), vec![at(3, 0), at(3, 1)]);
+    }
+
+    #[test]
+    fn run_selected_passes() {
+        let manager = optimize::PassManager::with_passes(&["dead-code"]).unwrap();
+        let (sequences, statistics) = manager.run(parse_str("12+;").unwrap());
+
+        assert_eq!(manager.enabled(), vec!["dead-code"]);
+        assert_eq!(statistics.len(), 1);
+        assert_eq!(sequences[1], vec![vec![
+            Push(Constant(1)), Push(Constant(2)), Push(Operate(Box::new(Pop), Add, Box::new(Pop))), OutputNumber(Pop), Exit
+        ]]);
+    }
+
+    #[test]
+    fn count_saved_stack_operations() {
+        let mut manager = optimize::PassManager::new(1);
+        manager.parse_flag("--disable-pass=dead-code").unwrap();
+
+        let (_, statistics) = manager.run(parse_str("12+;").unwrap());
+
+        assert_eq!(
+            statistics,
+            vec![optimize::Statistics { pass: "constants", instructions_removed: 3, pushes_saved: 3, pops_saved: 3, report: vec![] }]
+        );
+    }
+
+    #[test]
+    fn report_removed_code() {
+        let manager = optimize::PassManager::with_passes(&["dead-code"]).unwrap();
+        let (_, statistics) = manager.run(parse_str("1<\n2").unwrap());
+
+        assert_eq!(statistics[0].report, vec!["line 1, section 1: follows a jump and is never resumed", "line 2: no reachable jump leads here"]);
+        assert_eq!(statistics[0].to_string(), "dead-code: 4 instructions removed, 1 pushes saved, 0 pops saved\n\
+                                               \x20   line 1, section 1: follows a jump and is never resumed\n\
+                                               \x20   line 2: no reachable jump leads here");
+    }
+
+    #[test]
+    fn parse_optimization_flags() {
+        let mut manager = optimize::PassManager::default();
+
+        assert!(manager.parse_flag("-O0").unwrap());
+        assert!(manager.enabled().is_empty());
+
+        assert!(manager.parse_flag("--passes=constants").unwrap());
+        assert_eq!(manager.enabled(), vec!["constants"]);
+
+        assert!(!manager.parse_flag("program.kar").unwrap());
+
+        match manager.parse_flag("--disable-pass=nonexistent") {
+            Err(Error::UnknownPass(name)) => assert_eq!(name, "nonexistent"),
+            _ => panic!("expected an unknown pass")
+        }
+    }
+
+    #[test]
+    fn symbolic_round_trip_leaves_no_trace() {
+        let section = vec![Insert(Pop, Front), Push(Remove(Front)), Push(CloneTop), Destroy];
+        let effect = symbolic::execute(&section).unwrap();
+
+        // only the value that has to be on the stack is left
+        assert_eq!(effect, symbolic::execute(&[Discard(CloneTop)]).unwrap());
+        assert_eq!((effect.stack_required, effect.deque_required), (1, 0));
+    }
+
+    #[test]
+    fn unparse_numbers() {
+        for &value in &[0, 7, 10, 81, 1000, -1, -9, -10, -12345, i64::MAX, i64::MIN] {
+            let sequences = optimize::compute_constants(parse_str(&unparse::unparse_number(value)).unwrap());
+            assert_eq!(sequences[1], vec![vec![Push(Constant(value)), Exit]], "{}", value);
+        }
+    }
+
+    #[test]
+    fn unparse_program() {
+        let sequences = optimize::all(parse_file("../karma/fib.kar").unwrap());
+        let source = unparse::unparse(&sequences).unwrap();
+
+        assert_eq!(optimize::all(parse_str(&source).unwrap()), sequences);
+    }
+
+    #[test]
+    fn validate_default_passes() {
+        for example in &["sum", "fib", "echo", "hello_world", "simple"] {
+            let sequences = parse_file(format!("../karma/{}.kar", example)).unwrap();
+            assert_eq!(optimize::PassManager::default().validate(&sequences), Ok(()));
+        }
+    }
+
+    #[test]
+    fn report_miscompiling_pass() {
+        // turns every subtraction into an addition
+        fn broken(sequences: Vec<Sequence>) -> Vec<Sequence> {
+            sequences.into_iter().map(|sequence| sequence.into_iter().map(|section| {
+                section.into_iter().map(|instruction| match instruction {
+                    Push(Operate(lhs, Sub, rhs)) => Push(Operate(lhs, Add, rhs)),
+                    instruction => instruction
+                }).collect()
+            }).collect()).collect()
+        }
+
+        let pass = optimize::Pass { name: "broken", description: "", level: 0, run: broken, report: None };
+        let sequences = parse_str("12+;\n?5-;").unwrap();
+        let counterexample = optimize::validate_pass(&pass, &sequences).unwrap_err();
+
+        assert_eq!(counterexample.problem, optimize::Problem::ChangedSection { sequence: 2, section: 0 });
+        assert_eq!(counterexample.program, Some("-".to_owned()));
+    }
+
+    #[test]
+    fn report_hidden_underflow() {
+        // removes every value taken from the deque and put back, even from an empty deque
+        fn cancels(sequences: Vec<Sequence>) -> Vec<Sequence> {
+            sequences.into_iter().map(|sequence| sequence.into_iter().map(|section| {
+                let mut instructions = Section::new();
+                for instruction in section {
+                    match (instructions.last(), instruction) {
+                        (Some(&Push(Remove(Front))), Insert(Pop, Front)) => { instructions.pop(); }
+                        (_, instruction) => instructions.push(instruction)
+                    }
+                }
+                instructions
+            }).collect()).collect()
+        }
+
+        let pass = optimize::Pass { name: "cancels", description: "", level: 0, run: cancels, report: None };
+        let counterexample = optimize::validate_pass(&pass, &parse_str("{}1;").unwrap()).unwrap_err();
+
+        assert_eq!(counterexample.problem, optimize::Problem::ChangedSection { sequence: 1, section: 0 });
+        assert_eq!(counterexample.program, Some("{}".to_owned()));
+
+        // printing before running out is not the same as running out first
+        let effects = (symbolic::execute(&[OutputNumber(Constant(1)), Destroy]), symbolic::execute(&[Destroy, OutputNumber(Constant(1))]));
+        assert_ne!(effects.0, effects.1);
+    }
+
+    #[test]
+    fn check_counted_loops() {
+        // puts a loop in front of every line that looks like a counting loop
+        fn adds_loops(sequences: Vec<Sequence>) -> Vec<Sequence> {
+            sequences.into_iter().map(|mut sequence| {
+                if sequence.len() >= 3 {
+                    sequence[0].insert(0, CountedLoop(1, Add));
+                }
+                sequence
+            }).collect()
+        }
+
+        let pass = optimize::Pass { name: "adds-loops", description: "", level: 0, run: adds_loops, report: None };
+        assert_eq!(optimize::validate_pass(&pass, &parse_str("3}5},\n{1+\\>@, \\}+<\n;").unwrap()), Ok(()));
+
+        // the body subtracts instead of adding, and the head needs the stack
+        for source in &["{1+\\>@,\\}-<", "#{1+\\>@,\\}+<"] {
+            let counterexample = optimize::validate_pass(&pass, &parse_str(source).unwrap()).unwrap_err();
+            assert_eq!(counterexample.problem, optimize::Problem::ChangedSection { sequence: 1, section: 0 }, "{}", source);
+        }
+
+        // a loop in the middle of a line cannot be executed symbolically
+        let pass = optimize::Pass { name: "moves-loops", description: "", level: 0, run: |sequences| {
+            sequences.into_iter().map(|mut sequence| {
+                if sequence.len() >= 3 {
+                    sequence[2].insert(0, CountedLoop(1, Add));
+                }
+                sequence
+            }).collect()
+        }, report: None };
+        let counterexample = optimize::validate_pass(&pass, &parse_str("{1+\\>@,\\}+<").unwrap()).unwrap_err();
+        assert_eq!(counterexample.problem, optimize::Problem::Inconclusive { sequence: 1, section: 2 });
+    }
+
+    #[test]
+    fn report_panicking_pass() {
+        fn panics(sequences: Vec<Sequence>) -> Vec<Sequence> {
+            if sequences.iter().flat_map(|sequence| sequence.iter()).any(|section| section.contains(&Destroy)) {
+                panic!("destroyed");
+            }
+
+            sequences
+        }
+
+        let pass = optimize::Pass { name: "panics", description: "", level: 0, run: panics, report: None };
+        let sequences = parse_str("12#;").unwrap();
+        let counterexample = optimize::validate_pass(&pass, &sequences).unwrap_err();
+
+        assert_eq!(counterexample.problem, optimize::Problem::Panicked("destroyed".to_owned()));
+        assert_eq!(counterexample.program, Some("#".to_owned()));
+    }
 }
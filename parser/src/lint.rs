@@ -0,0 +1,293 @@
+use parse::{
+    *,
+    Instruction::*,
+    Direction::*,
+    Start::*,
+};
+use syntax::{SyntaxTree, Token};
+use flow::{FlowGraph, Location};
+use depth::{DepthAnalysis, Container};
+use error::Error;
+
+use std::fmt;
+
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A mistake the linter looks for
+#[derive(Debug)]
+pub struct Rule {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub severity: Severity,
+    pub description: &'static str,
+}
+
+/// All rules in the order they are checked
+pub const RULES: &[Rule] = &[
+    Rule {
+        id: "K000",
+        name: "parse-error",
+        severity: Severity::Error,
+        description: "The program cannot be parsed"
+    },
+
+    Rule {
+        id: "K001",
+        name: "skip-into-exit",
+        severity: Severity::Warning,
+        description: "A `@` skips a jump that ends its line, so not jumping ends the program"
+    },
+
+    Rule {
+        id: "K002",
+        name: "jump-before-first-line",
+        severity: Severity::Error,
+        description: "`'` on the first line leaves the program"
+    },
+
+    Rule {
+        id: "K003",
+        name: "jump-after-last-line",
+        severity: Severity::Warning,
+        description: "`.` on the last line ends the program"
+    },
+
+    Rule {
+        id: "K004",
+        name: "unintended-comment",
+        severity: Severity::Warning,
+        description: "A character that is not a command directly follows code and hides the rest of the line"
+    },
+
+    Rule {
+        id: "K005",
+        name: "truncated-program",
+        severity: Severity::Warning,
+        description: "A blank line ends the program, but code follows it"
+    },
+
+    Rule {
+        id: "K006",
+        name: "stack-underflow",
+        severity: Severity::Error,
+        description: "A command always runs out of values on the stack"
+    },
+
+    Rule {
+        id: "K007",
+        name: "deque-underflow",
+        severity: Severity::Error,
+        description: "A command always runs out of values in the deque"
+    },
+];
+
+
+/// A problem found in a program
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub rule: &'static Rule,
+
+    /// The line of the problem, counted from 1
+    pub line: usize,
+
+    /// The column of the problem in characters, counted from 1
+    pub column: usize,
+
+    pub message: String,
+}
+
+
+/// Find mistakes in a program. Diagnostics are sorted by position, and those
+/// suppressed by a `karmalint: allow(<rule>, ...)` comment on their line, or
+/// a `karmalint: allow-file(<rule>, ...)` comment anywhere, are left out.
+/// Rules can be named by id or by name.
+pub fn lint(tree: &SyntaxTree) -> Vec<Diagnostic> {
+    let mut linter = Linter { tree, diagnostics: Vec::new() };
+
+    match tree.to_sequences() {
+        Ok(sequences) => linter.check_sequences(&sequences),
+        Err(error) => linter.report("K000", error_line(&error).unwrap_or(1), 1, format!("{:?}", error)),
+    }
+
+    linter.check_comments();
+    linter.check_trailer();
+
+    let mut diagnostics: Vec<Diagnostic> = linter.diagnostics.into_iter()
+        .filter(|diagnostic| !is_suppressed(tree, diagnostic))
+        .collect();
+
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    diagnostics
+}
+
+/// Find a rule by id or name
+pub fn find_rule(name: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|rule| rule.id == name || rule.name == name)
+}
+
+
+struct Linter<'a> {
+    tree: &'a SyntaxTree,
+    diagnostics: Vec<Diagnostic>,
+}
+
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, id: &str, line: usize, column: usize, message: String) {
+        let rule = find_rule(id).unwrap();
+        self.diagnostics.push(Diagnostic { rule, line, column, message });
+    }
+
+    fn check_sequences(&mut self, sequences: &[Sequence]) {
+        let graph = FlowGraph::new(sequences);
+        let last_line = sequences.len() - 2;
+
+        for (line, sequence) in sequences.iter().enumerate().skip(1).take(last_line) {
+            for (index, section) in sequence.iter().enumerate() {
+                let location = Location { sequence: line, section: index };
+                if !graph.is_reachable(location) {
+                    continue;
+                }
+
+                for (position, instruction) in section.iter().enumerate() {
+                    let column = self.column(sequences, location, position);
+
+                    match *instruction {
+                        Jump(Previous, _) if line == 1 => {
+                            self.report("K002", line, column, "`'` jumps to before the first line and leaves the program".to_owned())
+                        }
+
+                        Jump(Next, Continue) if line == last_line => {
+                            self.report("K003", line, column, "`.` jumps past the last line and ends the program".to_owned())
+                        }
+
+                        SkipIfNotOne if skips_jump_into_exit(sequence, index) => {
+                            self.report("K001", line, column, "skipping the jump after this ends the program".to_owned())
+                        }
+
+                        _ => ()
+                    }
+                }
+            }
+        }
+
+        for underflow in DepthAnalysis::new(sequences).underflows() {
+            let (id, container) = match underflow.container {
+                Container::Stack => ("K006", "stack"),
+                Container::Deque => ("K007", "deque"),
+            };
+
+            let location = underflow.location;
+            let column = self.column(sequences, location, underflow.instruction);
+            self.report(id, location.sequence, column, format!("the {} is always empty here", container));
+        }
+    }
+
+    fn check_comments(&mut self) {
+        for (index, line) in self.tree.lines.iter().enumerate() {
+            let directly_after_code = match line.tokens.last() {
+                Some(&Token::Whitespace(_)) | None => false,
+                Some(_) => true
+            };
+
+            if directly_after_code && !line.comment.is_empty() {
+                let column = line.code().chars().count() + 1;
+                let character = line.comment.chars().next().unwrap();
+                let message = format!("`{}` is not a command, so the rest of the line is ignored", character);
+                self.report("K004", index + 1, column, message);
+            }
+        }
+    }
+
+    fn check_trailer(&mut self) {
+        let has_code = self.tree.trailer.lines().any(|line| {
+            SyntaxTree::parse(line, self.tree.dialect).lines.iter()
+                .any(|line| line.tokens.iter().any(|token| !matches!(*token, Token::Whitespace(_))))
+        });
+
+        if has_code {
+            let line = self.tree.lines.len() + 1;
+            self.report("K005", line, 1, "this blank line ends the program and the code after it is ignored".to_owned());
+        }
+    }
+
+
+    /// The column of an instruction in the source
+    fn column(&self, sequences: &[Sequence], location: Location, instruction: usize) -> usize {
+        let offset: usize = sequences[location.sequence][..location.section].iter()
+            .map(|section| section.len())
+            .sum::<usize>() + instruction;
+
+        let line = &self.tree.lines[location.sequence - 1];
+        match line.instruction_columns().get(offset) {
+            Some(column) => column + 1,
+            None => line.code().chars().count() + 1
+        }
+    }
+}
+
+
+/// Is the command following a skip a jump that ends the line, so that the
+/// program falls through to its end when the jump is skipped?
+fn skips_jump_into_exit(sequence: &Sequence, skip: usize) -> bool {
+    let skips_jump = matches!(sequence[skip + 1][..], [Jump(_, _)]);
+    skips_jump && sequence.len() == skip + 3 && sequence[skip + 2] == vec![Exit]
+}
+
+fn error_line(error: &Error) -> Option<usize> {
+    match *error {
+        Error::TrailingSkip(line) | Error::UnterminatedString(line) | Error::LiteralOutOfRange(line) => Some(line),
+        _ => None
+    }
+}
+
+
+fn is_suppressed(tree: &SyntaxTree, diagnostic: &Diagnostic) -> bool {
+    let allows = |line: &str, directive: &str| {
+        allowed_rules(line, directive).iter().any(|name| find_rule(name).is_some_and(|rule| rule.id == diagnostic.rule.id))
+    };
+
+    let on_line = tree.lines.get(diagnostic.line - 1)
+        .is_some_and(|line| allows(&line.comment, "allow"));
+
+    on_line || tree.lines.iter().any(|line| allows(&line.comment, "allow-file"))
+}
+
+/// The rules listed in a `karmalint: <directive>(<rule>, ...)` comment
+fn allowed_rules<'a>(comment: &'a str, directive: &str) -> Vec<&'a str> {
+    let rest = match comment.find("karmalint:") {
+        Some(start) => comment[start + "karmalint:".len()..].trim_start(),
+        None => return Vec::new()
+    };
+
+    let list = rest.strip_prefix(directive)
+        .and_then(|rest| rest.strip_prefix('('))
+        .and_then(|rest| rest.split(')').next());
+
+    match list {
+        Some(list) => list.split(',').map(str::trim).collect(),
+        None => Vec::new()
+    }
+}
+
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Warning => f.pad("warning"),
+            Severity::Error => f.pad("error"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}[{}/{}]: {}",
+               self.line, self.column, self.rule.severity, self.rule.id, self.rule.name, self.message)
+    }
+}
@@ -0,0 +1,134 @@
+
+use std::fmt;
+
+use parse::*;
+use flow::{FlowGraph, Location, Terminator};
+
+
+/// A piece of code removed by `remove_unreachable`. Positions refer to the
+/// program before anything was removed.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Removal {
+    Sequence { sequence: usize, reason: Reason },
+    Section { sequence: usize, section: usize, reason: Reason },
+}
+
+/// Why a piece of code can never be executed
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Reason {
+    /// No reachable jump leads into the sequence
+    NeverJumpedTo,
+
+    /// The previous section exits the program
+    FollowsExit,
+
+    /// The previous section always jumps away and no jump resumes here
+    FollowsJump,
+
+    /// The previous section is unreachable itself
+    FollowsUnreachable,
+}
+
+
+/// Remove all sections and sequences that can never be executed
+pub fn remove_unreachable(sequences: Vec<Sequence>) -> Vec<Sequence> {
+    let graph = FlowGraph::new(&sequences);
+
+    sequences.into_iter().enumerate()
+        .filter(|&(sequence, _)| is_kept(&graph, sequence))
+        .map(|(sequence, sections)| {
+            let reachable = graph.is_sequence_reachable(sequence);
+
+            sections.into_iter().enumerate()
+                .filter(|&(section, _)| !reachable || graph.is_reachable(Location { sequence, section }))
+                .map(|(_, section)| section)
+                .collect()
+        })
+        .collect()
+}
+
+
+/// List everything `remove_unreachable` would remove
+pub fn find_unreachable(sequences: &[Sequence]) -> Vec<Removal> {
+    let graph = FlowGraph::new(sequences);
+    let mut removals = Vec::new();
+
+    for (sequence, sections) in sequences.iter().enumerate() {
+        if !is_kept(&graph, sequence) {
+            removals.push(Removal::Sequence { sequence, reason: Reason::NeverJumpedTo });
+            continue;
+        }
+
+        if !graph.is_sequence_reachable(sequence) {
+            continue;
+        }
+
+        for section in 0..sections.len() {
+            let location = Location { sequence, section };
+            if graph.is_reachable(location) {
+                continue;
+            }
+
+            let reason = if section == 0 {
+                Reason::NeverJumpedTo
+            } else {
+                let previous = Location { sequence, section: section - 1 };
+
+                match *graph.terminator(previous) {
+                    _ if !graph.is_reachable(previous) => Reason::FollowsUnreachable,
+                    Terminator::Exit => Reason::FollowsExit,
+                    _ => Reason::FollowsJump,
+                }
+            };
+
+            removals.push(Removal::Section { sequence, section, reason });
+        }
+    }
+
+    removals
+}
+
+
+/// Describe everything `remove_unreachable` would remove, leaving out the exit
+/// after the last line that is only there to end the program
+pub fn report_unreachable(sequences: &[Sequence]) -> Vec<String> {
+    find_unreachable(sequences).iter()
+        .filter(|removal| **removal != Removal::Sequence { sequence: sequences.len() - 1, reason: Reason::NeverJumpedTo })
+        .map(|removal| removal.to_string())
+        .collect()
+}
+
+
+/// The exit in front of the first line is kept as it is, even when it is
+/// unreachable, otherwise the program would start on the wrong line.
+fn is_kept(graph: &FlowGraph, sequence: usize) -> bool {
+    sequence == 0 || graph.is_sequence_reachable(sequence)
+}
+
+
+impl fmt::Display for Removal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Removal::Sequence { sequence, ref reason } => {
+                write!(f, "line {}: {}", sequence, reason)
+            }
+
+            Removal::Section { sequence, section, ref reason } => {
+                write!(f, "line {}, section {}: {}", sequence, section, reason)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match *self {
+            Reason::NeverJumpedTo => "no reachable jump leads here",
+            Reason::FollowsExit => "follows an exit",
+            Reason::FollowsJump => "follows a jump and is never resumed",
+            Reason::FollowsUnreachable => "follows unreachable code",
+        };
+
+        write!(f, "{}", description)
+    }
+}
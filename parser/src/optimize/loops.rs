@@ -0,0 +1,187 @@
+use parse::{
+    self,
+    *,
+    Instruction::*,
+    Direction::*,
+    Start::*,
+};
+use flow::{FlowGraph, Location};
+use symbolic::{self, Effect, Value};
+
+
+/// Find counting loops and let them run natively.
+///
+/// A counting loop is a line made of three reachable sections: a head that increases
+/// the counter at the front of the deque and compares it to the value behind
+/// it, an exit jump taken when the counter became larger, and a body that
+/// stores the new counter and combines it with the top of the stack before
+/// restarting the line. Such a line is the loop in `karma/sum.kar`:
+/// ```text
+/// {1+\>@, \}+<
+/// ```
+///
+/// The head of a recognized loop is prefixed with a `CountedLoop`, which runs
+/// all iterations that do not exit. The original code then only runs once,
+/// to take the exit.
+pub fn recognize_loops(sequences: Vec<Sequence>) -> Vec<Sequence> {
+    let graph = FlowGraph::new(&sequences);
+
+    let loops: Vec<_> = (0..sequences.len())
+        .map(|sequence| find_counted_loop(&sequences, &graph, sequence))
+        .collect();
+
+    sequences.into_iter().zip(loops)
+        .map(|(mut sections, counted_loop)| {
+            if let Some(counted_loop) = counted_loop {
+                sections[0].insert(0, counted_loop);
+            }
+
+            sections
+        })
+        .collect()
+}
+
+
+/// Run the iterations of a `CountedLoop`, returning the new top of the stack
+/// and counter. Additions with a positive step are computed in closed form.
+pub fn run_counted_loop(step: i64, operator: &Operator, top: i64, counter: i64, bound: i64)
+    -> ::std::result::Result<(i64, i64), parse::Trap> {
+    if let Some(result) = sum_counted_loop(step, operator, top, counter, bound) {
+        return Ok(result);
+    }
+
+    let mut top = top;
+    let mut counter = counter;
+
+    loop {
+        let next = counter.wrapping_add(step);
+        if next > bound {
+            return Ok((top, counter));
+        }
+
+        counter = next;
+        top = operator.apply(counter, top)?;
+    }
+}
+
+
+/// `top + (counter + step) + (counter + 2 * step) + ...`, `None` if the
+/// counter would wrap around before passing the bound
+fn sum_counted_loop(step: i64, operator: &Operator, top: i64, counter: i64, bound: i64) -> Option<(i64, i64)> {
+    if *operator != Operator::Add || step <= 0 {
+        return None;
+    }
+
+    let (step, counter, bound) = (step as i128, counter as i128, bound as i128);
+
+    let iterations = if counter + step > bound { 0 } else { (bound - counter) / step };
+    let last = counter + iterations * step;
+
+    if last + step > i64::MAX as i128 {
+        return None;
+    }
+
+    // there are less than 2^64 iterations, and the sum only matters modulo 2^64
+    let iterations = iterations as u128;
+    let triangle = iterations * (iterations + 1) / 2;
+
+    let sum = (iterations as i64).wrapping_mul(counter as i64)
+        .wrapping_add((triangle as i64).wrapping_mul(step as i64));
+
+    Some((top.wrapping_add(sum), last as i64))
+}
+
+
+fn find_counted_loop(sequences: &[Sequence], graph: &FlowGraph, sequence: usize) -> Option<Instruction> {
+    let sections = &sequences[sequence];
+    if sections.len() < 3 || !graph.is_reachable(Location { sequence, section: 0 }) {
+        return None;
+    }
+
+    // the exit at the end of the line is never reached
+    if (3..sections.len()).any(|section| graph.is_reachable(Location { sequence, section })) {
+        return None;
+    }
+
+    let (head, exit, body) = (&sections[0], &sections[1], &sections[2]);
+
+    match exit[..] {
+        [Jump(Next, _)] | [Jump(Previous, _)] => (),
+        _ => return None
+    }
+
+    if head.last() != Some(&SkipIfNotOne) || body.last() != Some(&Jump(Current, Restart)) {
+        return None;
+    }
+
+    // the body must only be run right after the head
+    if !only_entered_from_head(sequences, graph, sequence) {
+        return None;
+    }
+
+    // the loop takes the counter and looks at the bound before the stack
+    let head_effect = symbolic::execute(head).ok()?;
+    if (head_effect.stack_required, head_effect.deque_required) != (0, 2) {
+        return None;
+    }
+
+    let condition = match head_effect.exit {
+        symbolic::Exit::Skip(condition) => condition,
+        _ => return None
+    };
+
+    let mut iteration = head[..head.len() - 1].to_vec();
+    iteration.push(Destroy);
+    iteration.extend(body.iter().cloned());
+
+    let (step, operator, counter) = match_iteration(&symbolic::execute(&iteration).ok()?)?;
+
+    // the loop exits once the new counter is larger than the value behind it
+    if condition != Value::Greater(Box::new(counter), Box::new(Value::Front(1))) {
+        return None;
+    }
+
+    Some(CountedLoop(step, operator))
+}
+
+
+/// Check that an iteration increases the counter at the front of the deque by
+/// a constant and combines the new counter with the top of the stack
+fn match_iteration(effect: &Effect) -> Option<(i64, Operator, Value)> {
+    if effect.stack_required != 1 || effect.deque_required != 2 ||
+        effect.stack_removed != 1 || effect.stack_pushed.len() != 1 ||
+        effect.front_removed != 1 || effect.front_inserted.len() != 1 ||
+        effect.back_removed != 0 || !effect.back_inserted.is_empty() ||
+        !effect.events.is_empty() || effect.exit != symbolic::Exit::Jump(Current, Restart) {
+        return None;
+    }
+
+    let counter = effect.front_inserted[0].clone();
+    let step = match counter {
+        Value::Operate(ref lhs, Operator::Add, ref rhs) => match (&**lhs, &**rhs) {
+            (&Value::Front(0), &Value::Constant(step)) | (&Value::Constant(step), &Value::Front(0)) => step,
+            _ => return None
+        },
+
+        _ => return None
+    };
+
+    match effect.stack_pushed[0] {
+        Value::Operate(ref lhs, ref operator, ref rhs) if **lhs == counter && **rhs == Value::Stack(0) => {
+            Some((step, operator.clone(), counter))
+        }
+
+        _ => None
+    }
+}
+
+
+fn only_entered_from_head(sequences: &[Sequence], graph: &FlowGraph, sequence: usize) -> bool {
+    let head = Location { sequence, section: 0 };
+    let body = Location { sequence, section: 2 };
+
+    sequences.iter().enumerate()
+        .flat_map(|(other, sections)| (0..sections.len()).map(move |section| Location { sequence: other, section }))
+        .filter(|&location| location != head && graph.is_reachable(location))
+        .all(|location| !graph.successors(location).contains(&body))
+}
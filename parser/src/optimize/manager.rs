@@ -0,0 +1,271 @@
+
+use std::fmt;
+
+use error::*;
+use parse::{
+    *,
+    Instruction::*,
+    ValueSource::*,
+};
+
+use super::{compute_constants, fuse_instructions, remove_round_trips, remove_unreachable, report_unreachable, recognize_loops, validate_pass, Counterexample};
+
+
+/// The optimization level used when none is given
+pub const DEFAULT_LEVEL: u32 = 2;
+
+/// Explains what a pass is about to change in a program, a line for each change
+pub type Report = fn(&[Sequence]) -> Vec<String>;
+
+/// A named optimization pass
+pub struct Pass {
+    pub name: &'static str,
+    pub description: &'static str,
+
+    /// The lowest optimization level the pass is enabled at
+    pub level: u32,
+
+    pub run: fn(Vec<Sequence>) -> Vec<Sequence>,
+
+    pub report: Option<Report>
+}
+
+/// All passes in the order they are run
+pub const PASSES: &[Pass] = &[
+    Pass {
+        name: "constants",
+        description: "Precompute arithmetic on constant values",
+        level: 1,
+        run: compute_constants,
+        report: None
+    },
+
+    Pass {
+        name: "peephole",
+        description: "Remove values parked in the deque and compare known constants",
+        level: 2,
+        run: remove_round_trips,
+        report: None
+    },
+
+    Pass {
+        name: "dead-code",
+        description: "Remove unreachable sections and sequences",
+        level: 1,
+        run: remove_unreachable,
+        report: Some(report_unreachable)
+    },
+
+    Pass {
+        name: "loops",
+        description: "Run counting loops natively",
+        level: 2,
+        run: recognize_loops,
+        report: None
+    },
+
+    Pass {
+        name: "fuse",
+        description: "Fuse frequent instruction sequences into superinstructions",
+        level: 2,
+        run: fuse_instructions,
+        report: None
+    },
+];
+
+
+/// Runs a selection of passes over a program
+pub struct PassManager {
+    passes: Vec<&'static Pass>
+}
+
+/// What a single pass changed in a program
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Statistics {
+    pub pass: &'static str,
+
+    pub instructions_removed: i64,
+    pub pushes_saved: i64,
+    pub pops_saved: i64,
+
+    /// What the pass changed and why, if it explains itself
+    pub report: Vec<String>
+}
+
+
+impl PassManager {
+    /// Enable all passes up to an optimization level
+    pub fn new(level: u32) -> PassManager {
+        PassManager {
+            passes: PASSES.iter().filter(|pass| pass.level <= level).collect()
+        }
+    }
+
+    /// Enable only the named passes. They are still run in their usual order.
+    pub fn with_passes(names: &[&str]) -> Result<PassManager> {
+        for name in names {
+            find_pass(name)?;
+        }
+
+        Ok(PassManager {
+            passes: PASSES.iter().filter(|pass| names.contains(&pass.name)).collect()
+        })
+    }
+
+
+    /// Stop a pass from running
+    pub fn disable(&mut self, name: &str) -> Result<()> {
+        let pass = find_pass(name)?;
+        self.passes.retain(|enabled| enabled.name != pass.name);
+        Ok(())
+    }
+
+    /// The names of all enabled passes
+    pub fn enabled(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|pass| pass.name).collect()
+    }
+
+
+    /// Configure the manager from a command line flag: `-O<level>`,
+    /// `--passes=<a>,<b>` or `--disable-pass=<a>`. Returns `false` if the flag
+    /// has nothing to do with optimization.
+    pub fn parse_flag(&mut self, flag: &str) -> Result<bool> {
+        if let Some(level) = flag.strip_prefix("-O") {
+            let level = level.parse().map_err(|_| Error::InvalidOptimizationLevel(level.to_owned()))?;
+            *self = PassManager::new(level);
+        } else if let Some(names) = flag.strip_prefix("--passes=") {
+            let names: Vec<_> = names.split(',').filter(|name| !name.is_empty()).collect();
+            *self = PassManager::with_passes(&names)?;
+        } else if let Some(name) = flag.strip_prefix("--disable-pass=") {
+            self.disable(name)?;
+        } else {
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+
+    /// Run all enabled passes, recording what each one changed
+    pub fn run(&self, sequences: Vec<Sequence>) -> (Vec<Sequence>, Vec<Statistics>) {
+        let mut sequences = sequences;
+        let mut statistics = Vec::new();
+
+        for pass in &self.passes {
+            let before = Counts::of(&sequences);
+            let report = pass.report.map_or_else(Vec::new, |report| report(&sequences));
+            sequences = (pass.run)(sequences);
+            let after = Counts::of(&sequences);
+
+            statistics.push(Statistics {
+                pass: pass.name,
+                instructions_removed: before.instructions - after.instructions,
+                pushes_saved: before.pushes - after.pushes,
+                pops_saved: before.pops - after.pops,
+                report
+            });
+        }
+
+        (sequences, statistics)
+    }
+
+
+    /// Run all enabled passes, checking after each one that it did not change
+    /// what any section does
+    pub fn validate(&self, sequences: &[Sequence]) -> ::std::result::Result<(), Counterexample> {
+        let mut sequences = sequences.to_vec();
+
+        for pass in &self.passes {
+            validate_pass(pass, &sequences)?;
+            sequences = (pass.run)(sequences);
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> PassManager {
+        PassManager::new(DEFAULT_LEVEL)
+    }
+}
+
+
+fn find_pass(name: &str) -> Result<&'static Pass> {
+    PASSES.iter()
+        .find(|pass| pass.name == name)
+        .ok_or_else(|| Error::UnknownPass(name.to_owned()))
+}
+
+
+/// The amount of work a program does on the stack
+struct Counts {
+    instructions: i64,
+    pushes: i64,
+    pops: i64
+}
+
+impl Counts {
+    fn of(sequences: &[Sequence]) -> Counts {
+        let mut counts = Counts { instructions: 0, pushes: 0, pops: 0 };
+
+        for instruction in sequences.iter().flat_map(|sequence| sequence.iter()).flat_map(|section| section.iter()) {
+            counts.instructions += 1;
+
+            match *instruction {
+                Push(ref source) => {
+                    counts.pushes += 1;
+                    counts.count_source(source);
+                }
+
+                Replace(ref source) => {
+                    counts.pushes += 1;
+                    counts.pops += 1;
+                    counts.count_source(source);
+                }
+
+                Insert(ref source, _) | Discard(ref source) | OutputCharacter(ref source) | OutputNumber(ref source) => {
+                    counts.count_source(source);
+                }
+
+                BitwiseNot | LogicalNot => {
+                    counts.pushes += 1;
+                    counts.pops += 1;
+                }
+
+                Destroy | SkipIfNotOne => counts.pops += 1,
+
+                Jump(_, _) | Exit | Instruction::Trap(_) | CountedLoop(_, _) => ()
+            }
+        }
+
+        counts
+    }
+
+    fn count_source(&mut self, source: &ValueSource) {
+        match *source {
+            Pop | Equal | Greater => self.pops += 1,
+
+            Operate(ref lhs, _, ref rhs) => {
+                self.count_source(lhs);
+                self.count_source(rhs);
+            }
+
+            Constant(_) | CloneTop | Remove(_) | Input => ()
+        }
+    }
+}
+
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} instructions removed, {} pushes saved, {} pops saved",
+               self.pass, self.instructions_removed, self.pushes_saved, self.pops_saved)?;
+
+        for line in &self.report {
+            write!(f, "\n    {}", line)?;
+        }
+
+        Ok(())
+    }
+}
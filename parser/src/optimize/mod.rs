@@ -1,14 +1,33 @@
 
 use parse::{
+    self,
     *,
     Instruction::*,
     ValueSource::*,
 };
 
+mod dead_code;
+pub use self::dead_code::*;
 
-/// Apply all optimizations to the sequence
+mod loops;
+pub use self::loops::*;
+
+mod peephole;
+pub use self::peephole::*;
+
+mod superinstructions;
+pub use self::superinstructions::*;
+
+mod manager;
+pub use self::manager::*;
+
+mod validate;
+pub use self::validate::*;
+
+
+/// Apply all optimizations of the default level to the sequence
 pub fn all(sequences: Vec<Sequence>) -> Vec<Sequence> {
-    compute_constants(sequences)
+    PassManager::default().run(sequences).0
 }
 
 
@@ -31,8 +50,16 @@ fn compute_constants_section(section: Section) -> Section {
                 if lhs == &Box::new(Pop) && rhs == &Box::new(Pop) => {
                 match (instructions.pop(), instructions.pop()) {
                     (Some(Push(Constant(lhs))), Some(Push(Constant(rhs)))) => {
-                        let result = compute_constant_operation(lhs, operator, rhs);
-                        instructions.push(Push(Constant(result)));
+                        match compute_constant_operation(lhs, operator, rhs) {
+                            Ok(Some(result)) => instructions.push(Push(Constant(result))),
+                            Err(trap) => instructions.push(Trap(trap)),
+
+                            Ok(None) => {
+                                instructions.push(Push(Constant(rhs)));
+                                instructions.push(Push(Constant(lhs)));
+                                instructions.push(instruction.clone());
+                            }
+                        }
                     }
 
                     (a, b) => {
@@ -104,16 +131,15 @@ fn compute_constants_section(section: Section) -> Section {
 }
 
 
-fn compute_constant_operation(lhs: i64, operator: &Operator, rhs: i64) -> i64 {
+/// The result of an operation on constants. Operations that overflow are left
+/// for runtime, those that always fail become a trap.
+#[allow(clippy::match_ref_pats)]
+fn compute_constant_operation(lhs: i64, operator: &Operator, rhs: i64) -> ::std::result::Result<Option<i64>, parse::Trap> {
     match operator {
-        &Operator::Add => lhs + rhs,
-        &Operator::Sub => lhs - rhs,
-        &Operator::Mul => lhs * rhs,
-        &Operator::Div => lhs / rhs,
-        &Operator::Mod => lhs % rhs,
-        &Operator::And => lhs & rhs,
-        &Operator::Or  => lhs | rhs,
-        &Operator::Xor => lhs ^ rhs,
+        &Operator::Add => Ok(lhs.checked_add(rhs)),
+        &Operator::Sub => Ok(lhs.checked_sub(rhs)),
+        &Operator::Mul => Ok(lhs.checked_mul(rhs)),
+        _ => operator.apply(lhs, rhs).map(Some)
     }
 }
 
@@ -0,0 +1,173 @@
+use parse::{
+    *,
+    Instruction::*,
+    ValueSource::*,
+    QueueEnd::*,
+};
+use depth::Depths;
+
+
+/// Remove values that are parked in the deque and immediately taken back, and
+/// compare constants at compile time.
+///
+/// An insert followed by a removal at the same end becomes a push, as long as
+/// nothing in between looks at the deque: `5}#{` becomes `#5`, and `}{` and
+/// `[]` disappear entirely once the stack is known to hold a value. A removal
+/// directly followed by an insert at the same end, such as `{}`, is removed
+/// only when the deque is known to hold a value, so that an empty one still
+/// stops the program. `=` and `>` are computed when both the top of the stack
+/// and the front of the deque are known constants.
+pub fn remove_round_trips(sequences: Vec<Sequence>) -> Vec<Sequence> {
+    sequences.into_iter().map(|sequence| {
+        sequence.into_iter().map(remove_round_trips_section).collect()
+    }).collect()
+}
+
+
+/// Apply the peephole rules to a section until none of them matches
+pub fn remove_round_trips_section(section: Section) -> Section {
+    let mut section = section;
+
+    loop {
+        let optimized = apply_rules(&section);
+        if optimized == section {
+            return section;
+        }
+
+        section = optimized;
+    }
+}
+
+
+fn apply_rules(section: &[Instruction]) -> Section {
+    let mut instructions = Section::new();
+
+    for instruction in section {
+        match *instruction {
+            Push(Remove(ref end)) => {
+                match find_insert(&instructions, end) {
+                    Some(index) => {
+                        let source = match instructions.remove(index) {
+                            Insert(source, _) => source,
+                            _ => unreachable!()
+                        };
+
+                        // popping a value and pushing it back does nothing
+                        if source != Pop {
+                            instructions.push(Push(source));
+                        }
+                    }
+
+                    None => instructions.push(instruction.clone())
+                }
+            }
+
+            Insert(Pop, ref end) if instructions.last() == Some(&Push(Remove(end.clone())))
+                && known_depths(&instructions[..instructions.len() - 1]).deque.min > 0 => {
+                instructions.pop();
+            }
+
+            Push(Equal) | Push(Greater) => {
+                match (find_constant_top(&instructions), find_constant_front(&instructions)) {
+                    (Some((index, top)), Some(front)) => {
+                        instructions.remove(index);
+
+                        let result = if *instruction == Push(Equal) { top == front } else { top > front };
+                        instructions.push(Push(Constant(result as i64)));
+                    }
+
+                    _ => instructions.push(instruction.clone())
+                }
+            }
+
+            _ => instructions.push(instruction.clone())
+        }
+    }
+
+    instructions
+}
+
+
+/// The insert at an end that a removal from that end would take back. Inserts
+/// of constants can be delayed until the removal, others only if they come
+/// right before it.
+fn find_insert(instructions: &[Instruction], end: &QueueEnd) -> Option<usize> {
+    let index = instructions.iter().rposition(uses_deque)?;
+
+    match instructions[index] {
+        Insert(Constant(_), ref inserted) if inserted == end => Some(index),
+
+        // popping an empty stack has to stop the program
+        Insert(Pop, _) if known_depths(&instructions[..index]).stack.min == 0 => None,
+
+        Insert(_, ref inserted) if inserted == end && index + 1 == instructions.len() => Some(index),
+        _ => None
+    }
+}
+
+/// The fewest values the stack and deque hold after some instructions of a
+/// section, if they did not run out
+fn known_depths(instructions: &[Instruction]) -> Depths {
+    let mut depths = Depths::unknown();
+    for instruction in instructions {
+        // only a container that is known to be empty runs out
+        depths.execute(instruction).unwrap();
+    }
+
+    depths
+}
+
+/// The constant on top of the stack and the instruction that pushed it
+fn find_constant_top(instructions: &[Instruction]) -> Option<(usize, i64)> {
+    let index = instructions.iter().rposition(uses_stack)?;
+
+    match instructions[index] {
+        Push(Constant(value)) => Some((index, value)),
+        _ => None
+    }
+}
+
+/// The constant at the front of the deque
+fn find_constant_front(instructions: &[Instruction]) -> Option<i64> {
+    let index = instructions.iter().rposition(uses_deque)?;
+
+    match instructions[index] {
+        Insert(Constant(value), Front) => Some(value),
+        _ => None
+    }
+}
+
+
+fn uses_stack(instruction: &Instruction) -> bool {
+    match *instruction {
+        Push(_) | Replace(_) | Discard(_) | BitwiseNot | LogicalNot | Destroy | SkipIfNotOne | CountedLoop(_, _) => true,
+        Insert(ref source, _) | OutputCharacter(ref source) | OutputNumber(ref source) => source_uses_stack(source),
+        Jump(_, _) | Exit | Instruction::Trap(_) => false
+    }
+}
+
+fn uses_deque(instruction: &Instruction) -> bool {
+    match *instruction {
+        Insert(_, _) | CountedLoop(_, _) => true,
+        Push(ref source) | Replace(ref source) | Discard(ref source) | OutputCharacter(ref source) | OutputNumber(ref source) => {
+            source_uses_deque(source)
+        }
+        BitwiseNot | LogicalNot | Destroy | SkipIfNotOne | Jump(_, _) | Exit | Instruction::Trap(_) => false
+    }
+}
+
+fn source_uses_stack(source: &ValueSource) -> bool {
+    match *source {
+        Pop | CloneTop | Equal | Greater => true,
+        Operate(ref lhs, _, ref rhs) => source_uses_stack(lhs) || source_uses_stack(rhs),
+        Constant(_) | Remove(_) | Input => false
+    }
+}
+
+fn source_uses_deque(source: &ValueSource) -> bool {
+    match *source {
+        Remove(_) | Equal | Greater => true,
+        Operate(ref lhs, _, ref rhs) => source_uses_deque(lhs) || source_uses_deque(rhs),
+        Constant(_) | Pop | CloneTop | Input => false
+    }
+}
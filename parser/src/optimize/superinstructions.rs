@@ -0,0 +1,95 @@
+use parse::{
+    *,
+    Instruction::*,
+    ValueSource::*,
+};
+
+
+/// Fuse instructions that push a value with the instruction that pops it.
+///
+/// `{1+` is three instructions that push and pop twice, but a single
+/// `Push(Operate(Constant(1), Add, Remove(Front)))` without touching the stack
+/// in between. Both backends evaluate nested values directly, so a fused
+/// instruction costs one dispatch instead of several.
+///
+/// The rules are fixed rather than a table of frequent runs: nesting works
+/// for any value, however often it occurs. Only two pairs that cannot be
+/// nested but were frequent in the examples, as counted by the `ngrams`
+/// example, got their own instructions: a value that is pushed only to be
+/// destroyed, as in `{#`, becomes a `Discard`, and destroying the top of the
+/// stack before pushing, as in `#{`, becomes a `Replace`.
+pub fn fuse_instructions(sequences: Vec<Sequence>) -> Vec<Sequence> {
+    sequences.into_iter().map(|sequence| {
+        sequence.into_iter().map(fuse_section).collect()
+    }).collect()
+}
+
+
+fn fuse_section(section: Section) -> Section {
+    let mut instructions = Section::new();
+
+    for instruction in section {
+        let mut instruction = instruction;
+
+        // a fused instruction may in turn pop what was pushed before it
+        while let Some(fused) = instructions.last().and_then(|previous| fuse(previous, &instruction)) {
+            instructions.pop();
+            instruction = fused;
+        }
+
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+
+fn fuse(previous: &Instruction, instruction: &Instruction) -> Option<Instruction> {
+    match *previous {
+        Push(ref source) if *instruction == Destroy => Some(Discard(source.clone())),
+        Push(ref pushed) => substitute_pop(instruction, pushed),
+        Destroy => match *instruction {
+            Push(ref source) => Some(Replace(source.clone())),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+
+/// Replace the value an instruction pops first with the value pushed right
+/// before it, if nothing observable happens before the pop
+fn substitute_pop(instruction: &Instruction, pushed: &ValueSource) -> Option<Instruction> {
+    let instruction = match *instruction {
+        Push(ref source) => Push(substitute_source(source, pushed)?),
+        Insert(ref source, ref end) => Insert(substitute_source(source, pushed)?, end.clone()),
+        OutputCharacter(ref source) => OutputCharacter(substitute_source(source, pushed)?),
+        OutputNumber(ref source) => OutputNumber(substitute_source(source, pushed)?),
+        Discard(ref source) => Discard(substitute_source(source, pushed)?),
+        _ => return None
+    };
+
+    Some(instruction)
+}
+
+fn substitute_source(source: &ValueSource, pushed: &ValueSource) -> Option<ValueSource> {
+    match *source {
+        Pop => Some(pushed.clone()),
+
+        // the left hand side is evaluated first
+        Operate(ref lhs, ref operator, ref rhs) => match substitute_source(lhs, pushed) {
+            Some(lhs) => Some(Operate(Box::new(lhs), operator.clone(), rhs.clone())),
+            None if is_constant(lhs) => {
+                let rhs = substitute_source(rhs, pushed)?;
+                Some(Operate(lhs.clone(), operator.clone(), Box::new(rhs)))
+            }
+            None => None
+        },
+
+        _ => None
+    }
+}
+
+fn is_constant(source: &ValueSource) -> bool {
+    matches!(*source, Constant(_))
+}
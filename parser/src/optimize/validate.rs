@@ -0,0 +1,286 @@
+
+use std::{
+    cell::Cell,
+    fmt,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::Once,
+};
+
+use parse::{
+    *,
+    Instruction::*,
+};
+use symbolic::{self, Effect, Exit, Value};
+use unparse::unparse;
+
+use super::Pass;
+
+
+thread_local! {
+    /// Whether a panic on this thread is caught by `run_quietly`
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+}
+
+
+/// Proof that a pass changed what a program does
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Counterexample {
+    pub pass: &'static str,
+    pub problem: Problem,
+
+    /// The smallest program found that shows the problem when only this pass
+    /// is run on it, `None` if the program could not be written as Karma
+    pub program: Option<String>
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Problem {
+    /// The pass panicked with a message
+    Panicked(String),
+
+    /// A section after the pass does not do the same as the section it came from
+    ChangedSection { sequence: usize, section: usize },
+
+    /// A section after the pass could not be compared to the section it came
+    /// from, because one of them is too complex to execute symbolically
+    Inconclusive { sequence: usize, section: usize },
+
+    /// The pass added sequences that were not there before
+    AddedSequences,
+}
+
+
+/// Check that a pass leaves the effect of every section unchanged.
+///
+/// Sections may be removed by the pass, but every remaining section has to do
+/// exactly the same as a section in the same place in the original program.
+/// A `CountedLoop` added to the start of a line has to run the iterations the
+/// line would have run.
+pub fn validate_pass(pass: &Pass, sequences: &[Sequence]) -> Result<(), Counterexample> {
+    let problem = match find_problem(pass, sequences) {
+        Some(problem) => problem,
+        None => return Ok(())
+    };
+
+    Err(Counterexample {
+        pass: pass.name,
+        program: unparse(sequences).map(|source| shrink(pass, source, &problem)),
+        problem
+    })
+}
+
+
+/// Run a pass on a program and compare the result to the original
+fn find_problem(pass: &Pass, sequences: &[Sequence]) -> Option<Problem> {
+    let transformed = match run_quietly(pass, sequences.to_vec()) {
+        Ok(transformed) => transformed,
+        Err(message) => return Some(Problem::Panicked(message))
+    };
+
+    if transformed.len() > sequences.len() {
+        return Some(Problem::AddedSequences);
+    }
+
+    for (index, (original, transformed)) in sequences.iter().zip(transformed.iter()).enumerate() {
+        let effects: Vec<_> = original.iter().map(|section| symbolic::execute(split_loop(section).1)).collect();
+        let mut candidates = original.iter().zip(&effects);
+
+        for (section, transformed) in transformed.iter().enumerate() {
+            let (counted_loop, code) = split_loop(transformed);
+            let effect = symbolic::execute(code);
+            let mut inconclusive = false;
+
+            let found = candidates.any(|(candidate, candidate_effect)| {
+                let (candidate_loop, candidate_code) = split_loop(candidate);
+
+                let loop_kept = match (counted_loop, candidate_loop) {
+                    (None, _) => true,
+                    (Some(counted_loop), Some(candidate_loop)) => counted_loop == candidate_loop,
+                    (Some(&CountedLoop(step, ref operator)), None) if section == 0 => runs_same_iterations(original, step, operator),
+
+                    // only the loop of a whole line can be checked
+                    (Some(_), None) => {
+                        inconclusive = true;
+                        false
+                    }
+                };
+
+                loop_kept && match (candidate_effect, &effect) {
+                    _ if candidate_code == code => true,
+                    (Ok(original), Ok(transformed)) => original == transformed,
+                    _ => {
+                        inconclusive = true;
+                        false
+                    }
+                }
+            });
+
+            if !found && inconclusive {
+                return Some(Problem::Inconclusive { sequence: index, section });
+            } else if !found {
+                return Some(Problem::ChangedSection { sequence: index, section });
+            }
+        }
+    }
+
+    None
+}
+
+
+/// The `CountedLoop` at the start of a section, and the rest of the section
+fn split_loop(section: &[Instruction]) -> (Option<&Instruction>, &[Instruction]) {
+    match section.first() {
+        Some(counted_loop @ &CountedLoop(_, _)) => (Some(counted_loop), &section[1..]),
+        _ => (None, section)
+    }
+}
+
+/// Check that a `CountedLoop` at the start of a line only does what the line
+/// would do. The line has to be a head that ends by skipping the section that
+/// leaves the loop, followed by a body that restarts the line. One iteration
+/// through the head and body then has to do what the `CountedLoop` does while
+/// the head would not exit.
+fn runs_same_iterations(sections: &[Section], step: i64, operator: &Operator) -> bool {
+    if sections.len() < 3 {
+        return false;
+    }
+
+    let (head, body) = (split_loop(&sections[0]).1, &sections[2]);
+    if head.last() != Some(&SkipIfNotOne) || body.last() != Some(&Jump(Direction::Current, Start::Restart)) {
+        return false;
+    }
+
+    let mut iteration = head[..head.len() - 1].to_vec();
+    iteration.push(Destroy);
+    iteration.extend(body.iter().cloned());
+
+    let (head, iteration) = match (symbolic::execute(head), symbolic::execute(&iteration)) {
+        (Ok(head), Ok(iteration)) => (head, iteration),
+        _ => return false
+    };
+
+    // the step may be added to the counter either way around
+    let counters = [(Value::Front(0), Value::Constant(step)), (Value::Constant(step), Value::Front(0))];
+
+    counters.iter().any(|(lhs, rhs)| {
+        let counter = Value::Operate(Box::new(lhs.clone()), Operator::Add, Box::new(rhs.clone()));
+        let exits = Value::Greater(Box::new(counter.clone()), Box::new(Value::Front(1)));
+
+        // the loop takes the counter and looks at the bound before the stack
+        head.exit == Exit::Skip(exits) && (head.stack_required, head.deque_required) == (0, 2) &&
+            iteration == counted_iteration(counter, operator)
+    })
+}
+
+/// What a `CountedLoop` does in an iteration, with the new counter
+fn counted_iteration(counter: Value, operator: &Operator) -> Effect {
+    Effect {
+        stack_required: 1,
+        deque_required: 2,
+
+        stack_removed: 1,
+        stack_pushed: vec![Value::Operate(Box::new(counter.clone()), operator.clone(), Box::new(Value::Stack(0)))],
+
+        front_removed: 1,
+        front_inserted: vec![counter],
+
+        back_removed: 0,
+        back_inserted: Vec::new(),
+
+        events: Vec::new(),
+        exit: Exit::Jump(Direction::Current, Start::Restart)
+    }
+}
+
+
+/// Remove characters from a program for as long as the pass still has the
+/// same kind of problem with it
+fn shrink(pass: &Pass, source: String, problem: &Problem) -> String {
+    let fails = |source: &str| match parse_str(source) {
+        Ok(sequences) => find_problem(pass, &sequences).map(|found| mem::discriminant(&found)) == Some(mem::discriminant(problem)),
+        Err(_) => false
+    };
+
+    if !fails(&source) {
+        return source;
+    }
+
+    let mut source: Vec<char> = source.chars().collect();
+    let mut shrunk = true;
+
+    // removing one character can make it possible to remove an earlier one
+    while shrunk {
+        shrunk = false;
+        let mut index = 0;
+
+        while index < source.len() {
+            let mut candidate = source.clone();
+            candidate.remove(index);
+
+            let candidate_source: String = candidate.iter().collect();
+            if fails(&candidate_source) {
+                source = candidate;
+                shrunk = true;
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    source.into_iter().collect()
+}
+
+
+/// Run a pass, catching any panic without printing it. Panics on other
+/// threads are printed as usual.
+fn run_quietly(pass: &Pass, sequences: Vec<Sequence>) -> Result<Vec<Sequence>, String> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !QUIET.with(Cell::get) {
+                hook(info);
+            }
+        }));
+    });
+
+    QUIET.with(|quiet| quiet.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| (pass.run)(sequences)));
+    QUIET.with(|quiet| quiet.set(false));
+
+    result.map_err(|payload| {
+        if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic".to_owned()
+        }
+    })
+}
+
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.problem {
+            Problem::Panicked(ref message) => write!(f, "pass '{}' panicked: {}", self.pass, message)?,
+
+            Problem::ChangedSection { sequence, section } => {
+                write!(f, "pass '{}' changed the behaviour of line {}, section {}", self.pass, sequence, section)?
+            }
+
+            Problem::Inconclusive { sequence, section } => {
+                write!(f, "pass '{}' changed line {}, section {}, which is too complex to check", self.pass, sequence, section)?
+            }
+
+            Problem::AddedSequences => write!(f, "pass '{}' added lines to the program", self.pass)?,
+        }
+
+        if let Some(ref program) = self.program {
+            write!(f, "\nminimal program:\n{}", program)?;
+        }
+
+        Ok(())
+    }
+}
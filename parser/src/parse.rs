@@ -1,6 +1,13 @@
 
 use error::*;
-use std;
+use syntax::{SyntaxTree, Line};
+use std::{
+    self,
+    fmt,
+    iter::Peekable,
+    str::Chars,
+    time::Duration,
+};
 
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -21,6 +28,12 @@ pub enum Instruction {
     /// Destroy the value at top of stack
     Destroy,
 
+    /// Destroy the value at top of stack, then push a value
+    Replace(ValueSource),
+
+    /// Get a value and throw it away
+    Discard(ValueSource),
+
     /// Output a unicode character
     OutputCharacter(ValueSource),
 
@@ -34,7 +47,17 @@ pub enum Instruction {
     Jump(Direction, Start),
 
     /// Exits the program
-    Exit
+    Exit,
+
+    /// Stops the program with a runtime error
+    Trap(Trap),
+
+    /// Runs a counting loop for as long as it would not exit: while the
+    /// counter at the front of the deque plus the step is at most the value
+    /// behind it, add the step to the counter and replace the top of the stack
+    /// with `counter <operator> top`. The stack is left alone if the loop
+    /// exits right away.
+    CountedLoop(i64, Operator)
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -84,6 +107,44 @@ pub enum Operator {
 }
 
 
+/// A runtime error that stops the program
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Trap {
+    /// Division or remainder by zero
+    DivisionByZero,
+
+    /// Division or remainder of the smallest integer by -1
+    DivisionOverflow,
+
+    /// Input was read after the end of it, with `Eof::Error`
+    EndOfInput,
+
+    /// A value was taken from an empty deque
+    EmptyDeque,
+
+    /// A value was taken from an empty stack
+    EmptyStack,
+
+    /// More instructions were executed than `Limits::steps`
+    StepLimit,
+
+    /// The program ran for longer than `Limits::time`
+    TimeLimit,
+
+    /// A value was pushed onto a stack that holds `Limits::stack` values
+    StackLimit,
+
+    /// A value was inserted into a deque that holds `Limits::deque` values
+    DequeLimit,
+
+    /// The program printed more bytes than `Limits::output`
+    OutputLimit,
+
+    /// The program was asked to stop, usually by pressing Ctrl-C
+    Interrupted,
+}
+
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum QueueEnd {
     Front,
@@ -113,6 +174,191 @@ pub type Sequence = Vec<Section>;
 pub type Section = Vec<Instruction>;
 
 
+impl Operator {
+    /// Perform the operation the way it is done at runtime: addition,
+    /// subtraction and multiplication wrap around on overflow.
+    pub fn apply(&self, lhs: i64, rhs: i64) -> std::result::Result<i64, Trap> {
+        match *self {
+            Operator::Add => Ok(lhs.wrapping_add(rhs)),
+            Operator::Sub => Ok(lhs.wrapping_sub(rhs)),
+            Operator::Mul => Ok(lhs.wrapping_mul(rhs)),
+
+            Operator::Div | Operator::Mod if rhs == 0 => Err(Trap::DivisionByZero),
+            Operator::Div | Operator::Mod if lhs == i64::MIN && rhs == -1 => Err(Trap::DivisionOverflow),
+            Operator::Div => Ok(lhs / rhs),
+            Operator::Mod => Ok(lhs % rhs),
+
+            Operator::And => Ok(lhs & rhs),
+            Operator::Or => Ok(lhs | rhs),
+            Operator::Xor => Ok(lhs ^ rhs),
+        }
+    }
+}
+
+
+impl Trap {
+    /// The exit code of a program stopped by the trap
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Trap::DivisionByZero => 15,
+            Trap::DivisionOverflow => 16,
+            Trap::EndOfInput => 17,
+            Trap::EmptyDeque => 13,
+            Trap::EmptyStack => 14,
+            Trap::StepLimit => 18,
+            Trap::TimeLimit => 19,
+            Trap::StackLimit => 20,
+            Trap::DequeLimit => 21,
+            Trap::OutputLimit => 22,
+            Trap::Interrupted => 130,
+        }
+    }
+}
+
+/// What `?` reads once there is no input left
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Eof {
+    /// Stop the program with `Trap::EndOfInput`
+    Error,
+
+    /// Read a value instead, -1 by default like C's `getchar`
+    Value(i64),
+}
+
+/// What a program may use before it is stopped with a trap, nothing by
+/// default
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub struct Limits {
+    /// Instructions executed
+    pub steps: Option<u64>,
+
+    /// Time since the program started
+    pub time: Option<Duration>,
+
+    /// Values on the stack
+    pub stack: Option<usize>,
+
+    /// Values in the deque
+    pub deque: Option<usize>,
+
+    /// Bytes printed
+    pub output: Option<u64>,
+}
+
+/// The flavour of Karma to accept
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum Dialect {
+    /// Only the commands in the specification
+    #[default]
+    Standard,
+
+    /// Standard Karma with literals: `#123` pushes a decimal number, and
+    /// `"Hello"` pushes each character so that the first one ends up on top
+    Literals,
+}
+
+
+impl Eof {
+    /// Read the setting used on the command line: `error` or a number
+    pub fn from_name(name: &str) -> Result<Eof> {
+        match name {
+            "error" => Ok(Eof::Error),
+            _ => name.parse().map(Eof::Value).map_err(|_| Error::InvalidEof(name.to_owned()))
+        }
+    }
+}
+
+impl Default for Eof {
+    fn default() -> Eof {
+        Eof::Value(-1)
+    }
+}
+
+
+impl Limits {
+    /// Set a limit from a command line flag: `--max-steps=<n>`,
+    /// `--max-time=<seconds>`, `--max-stack=<n>`, `--max-deque=<n>` or
+    /// `--max-output=<bytes>`. Returns `false` for other flags.
+    pub fn parse_flag(&mut self, flag: &str) -> Result<bool> {
+        let (name, value) = match flag.find('=') {
+            Some(index) => (&flag[..index], &flag[index + 1..]),
+            None => return Ok(false)
+        };
+
+        let invalid = || Error::InvalidLimit(flag.to_owned());
+
+        match name {
+            "--max-steps" => self.steps = Some(value.parse().map_err(|_| invalid())?),
+            "--max-stack" => self.stack = Some(value.parse().map_err(|_| invalid())?),
+            "--max-deque" => self.deque = Some(value.parse().map_err(|_| invalid())?),
+            "--max-output" => self.output = Some(value.parse().map_err(|_| invalid())?),
+            "--max-time" => {
+                let seconds = value.parse().ok().filter(|seconds: &f64| *seconds >= 0.0).ok_or_else(invalid)?;
+                self.time = Some(Duration::from_secs_f64(seconds));
+            }
+
+            _ => return Ok(false)
+        }
+
+        Ok(true)
+    }
+
+    /// The flags that set these limits
+    pub fn flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+
+        if let Some(steps) = self.steps {
+            flags.push(format!("--max-steps={}", steps));
+        }
+        if let Some(time) = self.time {
+            flags.push(format!("--max-time={}", time.as_secs_f64()));
+        }
+        if let Some(stack) = self.stack {
+            flags.push(format!("--max-stack={}", stack));
+        }
+        if let Some(deque) = self.deque {
+            flags.push(format!("--max-deque={}", deque));
+        }
+        if let Some(output) = self.output {
+            flags.push(format!("--max-output={}", output));
+        }
+
+        flags
+    }
+}
+
+
+impl Dialect {
+    /// Find a dialect by the name used on the command line
+    pub fn from_name(name: &str) -> Result<Dialect> {
+        match name {
+            "standard" => Ok(Dialect::Standard),
+            "literals" => Ok(Dialect::Literals),
+            _ => Err(Error::UnknownDialect(name.to_owned()))
+        }
+    }
+}
+
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Trap::DivisionByZero => write!(f, "division by zero"),
+            Trap::DivisionOverflow => write!(f, "division overflow"),
+            Trap::EndOfInput => write!(f, "read past the end of input"),
+            Trap::EmptyDeque => write!(f, "the deque is empty"),
+            Trap::EmptyStack => write!(f, "the stack is empty"),
+            Trap::StepLimit => write!(f, "the step limit was reached"),
+            Trap::TimeLimit => write!(f, "the time limit was reached"),
+            Trap::StackLimit => write!(f, "the stack is full"),
+            Trap::DequeLimit => write!(f, "the deque is full"),
+            Trap::OutputLimit => write!(f, "the output limit was reached"),
+            Trap::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+
 enum ControlFlow {
     Continue,
     Break
@@ -120,6 +366,7 @@ enum ControlFlow {
 
 
 impl Instruction {
+    #[allow(clippy::is_digit_ascii_radix)]
     fn from(character: char) -> std::result::Result<Instruction, ControlFlow> {
         match character {
             // Math
@@ -175,6 +422,7 @@ impl Instruction {
         }
     }
 
+    #[allow(clippy::match_like_matches_macro, clippy::match_ref_pats)]
     fn breaks_section(&self) -> bool {
         match self {
             &Instruction::Jump(_, _) | &Instruction::SkipIfNotOne => true,
@@ -184,6 +432,13 @@ impl Instruction {
 }
 
 
+/// Whether a character is a command, as opposed to whitespace or the start of
+/// a comment
+pub(crate) fn is_command(character: char) -> bool {
+    Instruction::from(character).is_ok()
+}
+
+
 
 
 use self::Instruction::*;
@@ -196,13 +451,24 @@ use self::Start::*;
 
 /// Parse the source code into multiple sequences of commands
 pub fn parse_str(source_code: &str) -> Result<Vec<Sequence>> {
+    parse_str_with_dialect(source_code, Dialect::Standard)
+}
+
+/// Parse source code written in a dialect. Literals are lowered to pushes of
+/// constants, so the result can be run and unparsed like standard Karma.
+pub fn parse_str_with_dialect(source_code: &str, dialect: Dialect) -> Result<Vec<Sequence>> {
+    SyntaxTree::parse(source_code, dialect).to_sequences()
+}
+
+
+/// Parse the lines of a syntax tree into sequences
+pub(crate) fn parse_lines(lines: &[Line], dialect: Dialect) -> Result<Vec<Sequence>> {
     let mut sequences = Vec::new();
 
     sequences.push(vec![vec![Exit]]);
 
-    for sequence in source_code.lines().take_while(|line| !line.is_empty()).enumerate()
-        .map(|(n, l)| parse_line(&mut l.chars(), n + 1)) {
-        sequences.push(sequence?)
+    for (index, line) in lines.iter().enumerate() {
+        sequences.push(parse_line(&mut line.code().chars().peekable(), index + 1, dialect)?);
     }
 
     sequences.push(vec![vec![Exit]]);
@@ -211,11 +477,19 @@ pub fn parse_str(source_code: &str) -> Result<Vec<Sequence>> {
 }
 
 
-fn parse_line(characters: &mut impl Iterator<Item=char>, line_number: usize) -> Result<Sequence> {
+#[allow(clippy::len_zero)]
+fn parse_line(characters: &mut Peekable<Chars>, line_number: usize, dialect: Dialect) -> Result<Sequence> {
     let mut sequence = Vec::new();
     let mut section = Vec::new();
 
     while let Some(character) = characters.next() {
+        if dialect == Dialect::Literals {
+            if let Some(values) = parse_literal(character, characters, line_number)? {
+                section.extend(values.into_iter().map(|value| Push(Constant(value))));
+                continue;
+            }
+        }
+
         let instruction = Instruction::from(character);
 
         match instruction {
@@ -227,7 +501,7 @@ fn parse_line(characters: &mut impl Iterator<Item=char>, line_number: usize) ->
 
                 sequence.push(section);
 
-                let mut following_sections = parse_line(characters, line_number).unwrap();
+                let mut following_sections = parse_line(characters, line_number, dialect)?;
                 let if_one = following_sections[0].remove(0);
                 
                 if following_sections[0].len() == 0 {
@@ -262,3 +536,41 @@ fn parse_line(characters: &mut impl Iterator<Item=char>, line_number: usize) ->
     Ok(sequence)
 }
 
+
+/// The values pushed by a literal starting with a character, `None` if the
+/// character does not start a literal. A `@` in front of a literal skips only
+/// the first push, which for a string is its last character.
+fn parse_literal(character: char, characters: &mut Peekable<Chars>, line_number: usize) -> Result<Option<Vec<i64>>> {
+    match character {
+        '#' if characters.peek().is_some_and(|c| c.is_ascii_digit()) => {
+            let mut value: i64 = 0;
+
+            while let Some(digit) = characters.peek().and_then(|c| c.to_digit(10)) {
+                characters.next();
+
+                value = value.checked_mul(10)
+                    .and_then(|value| value.checked_add(i64::from(digit)))
+                    .ok_or(Error::LiteralOutOfRange(line_number))?;
+            }
+
+            Ok(Some(vec![value]))
+        }
+
+        '"' => {
+            let mut values = Vec::new();
+
+            loop {
+                match characters.next() {
+                    Some('"') => break,
+                    Some(character) => values.push(character as i64),
+                    None => return Err(Error::UnterminatedString(line_number))
+                }
+            }
+
+            values.reverse();
+            Ok(Some(values))
+        }
+
+        _ => Ok(None)
+    }
+}
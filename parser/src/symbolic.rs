@@ -0,0 +1,423 @@
+
+use parse::{
+    self,
+    *,
+    Instruction::*,
+    Operator::*,
+    QueueEnd::*,
+};
+
+
+/// The largest value, counted in nodes, that is tracked before giving up
+const MAX_VALUE_SIZE: usize = 4096;
+
+
+/// A value computed by a section, in terms of what the section started with
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Value {
+    Constant(i64),
+
+    /// A value on the stack when the section started, 0 being the top
+    Stack(usize),
+
+    /// A value in the deque when the section started, 0 being the front
+    Front(usize),
+
+    /// A value in the deque when the section started, 0 being the back
+    Back(usize),
+
+    /// The n:th character read by the section
+    Input(usize),
+
+    Operate(Box<Value>, Operator, Box<Value>),
+    BitwiseNot(Box<Value>),
+    LogicalNot(Box<Value>),
+    Equal(Box<Value>, Box<Value>),
+    Greater(Box<Value>, Box<Value>),
+}
+
+/// Something a section does that can be observed from outside
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Event {
+    Read,
+    OutputCharacter(Value),
+    OutputNumber(Value),
+
+    /// The stack and the deque had to hold this many values to get here. It
+    /// comes before the events that depend on it, so that a section that
+    /// prints before running out does not look like one that runs out first.
+    Require { stack: usize, deque: usize },
+}
+
+/// Where a section sends control when it is done
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Exit {
+    FallThrough,
+
+    /// Skip the following section unless the value is 1
+    Skip(Value),
+
+    Jump(Direction, Start),
+    Exit,
+
+    /// The program stops with a runtime error
+    Trap(parse::Trap),
+}
+
+/// The complete effect of executing a section.
+///
+/// The values are those computed when the stack and deque hold every value the
+/// section takes from them. How many values they need for the section not to
+/// run out, and which events happen before it would, are part of the effect
+/// as well.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Effect {
+    /// How many values the stack has to hold for the section to run to its end
+    pub stack_required: usize,
+    /// How many values the deque has to hold, counting both ends
+    pub deque_required: usize,
+
+    /// How many of the values on the stack were removed
+    pub stack_removed: usize,
+    /// The values pushed on top of the remaining stack, bottom first
+    pub stack_pushed: Vec<Value>,
+
+    pub front_removed: usize,
+    /// The values inserted at the front of the deque, outermost last
+    pub front_inserted: Vec<Value>,
+
+    pub back_removed: usize,
+    /// The values inserted at the back of the deque, outermost last
+    pub back_inserted: Vec<Value>,
+
+    pub events: Vec<Event>,
+    pub exit: Exit
+}
+
+/// A section could not be executed symbolically within the bounds
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Inconclusive;
+
+/// Why symbolic execution of a section stopped early
+enum Stop {
+    Inconclusive,
+    Trap(parse::Trap),
+}
+
+
+/// Execute a section with unknown stack, deque and input
+pub fn execute(section: &[Instruction]) -> Result<Effect, Inconclusive> {
+    let mut effect = Effect::new();
+
+    for instruction in section {
+        match effect.execute_instruction(instruction) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(Stop::Inconclusive) => return Err(Inconclusive),
+
+            // what is left on the stack and deque is never seen again
+            Err(Stop::Trap(trap)) => {
+                effect = Effect {
+                    stack_required: effect.stack_required,
+                    deque_required: effect.deque_required,
+                    events: effect.events,
+                    ..Effect::new()
+                };
+                effect.exit = Exit::Trap(trap);
+                break;
+            }
+        }
+    }
+
+    effect.cancel_restored_values();
+
+    Ok(effect)
+}
+
+
+impl Effect {
+    fn new() -> Effect {
+        Effect {
+            stack_required: 0,
+            deque_required: 0,
+
+            stack_removed: 0,
+            stack_pushed: Vec::new(),
+
+            front_removed: 0,
+            front_inserted: Vec::new(),
+
+            back_removed: 0,
+            back_inserted: Vec::new(),
+
+            events: Vec::new(),
+            exit: Exit::FallThrough
+        }
+    }
+
+    /// Execute an instruction, returns `false` if it ends the section
+    fn execute_instruction(&mut self, instruction: &Instruction) -> Result<bool, Stop> {
+        let effect = self;
+
+        match *instruction {
+            BitwiseNot => {
+                let value = effect.pop();
+                effect.push(Value::BitwiseNot(Box::new(value)).simplify())?;
+            }
+
+            LogicalNot => {
+                let value = effect.pop();
+                effect.push(Value::LogicalNot(Box::new(value)).simplify())?;
+            }
+
+            Push(ref source) => {
+                let value = effect.value_from_source(source)?;
+                effect.push(value)?;
+            }
+
+            Replace(ref source) => {
+                effect.pop();
+                let value = effect.value_from_source(source)?;
+                effect.push(value)?;
+            }
+
+            Discard(ref source) => {
+                effect.value_from_source(source)?;
+            }
+
+            Insert(ref source, ref end) => {
+                let value = effect.value_from_source(source)?;
+                match *end {
+                    Front => effect.front_inserted.push(value),
+                    Back => effect.back_inserted.push(value),
+                }
+            }
+
+            Destroy => {
+                effect.pop();
+            }
+
+            OutputCharacter(ref source) => {
+                let value = effect.value_from_source(source)?;
+                effect.record(Event::OutputCharacter(value));
+            }
+
+            OutputNumber(ref source) => {
+                let value = effect.value_from_source(source)?;
+                effect.record(Event::OutputNumber(value));
+            }
+
+            SkipIfNotOne => {
+                let value = effect.pop();
+                effect.exit = Exit::Skip(value);
+                return Ok(false);
+            }
+
+            Jump(ref direction, ref start) => {
+                effect.exit = Exit::Jump(direction.clone(), start.clone());
+                return Ok(false);
+            }
+
+            Instruction::Exit => {
+                effect.exit = Exit::Exit;
+                return Ok(false);
+            }
+
+            Instruction::Trap(trap) => return Err(Stop::Trap(trap)),
+
+            // the number of iterations depends on the data
+            CountedLoop(_, _) => return Err(Stop::Inconclusive),
+        }
+
+        Ok(true)
+    }
+
+
+    /// Add an event, after what had to be on the stack and deque to reach it
+    fn record(&mut self, event: Event) {
+        let required = (self.stack_required, self.deque_required);
+        let last = self.events.iter().rev()
+            .find_map(|event| match *event {
+                Event::Require { stack, deque } => Some((stack, deque)),
+                _ => None
+            })
+            .unwrap_or((0, 0));
+
+        if required != last {
+            self.events.push(Event::Require { stack: required.0, deque: required.1 });
+        }
+
+        self.events.push(event);
+    }
+
+    fn push(&mut self, value: Value) -> Result<(), Stop> {
+        if value.size() > MAX_VALUE_SIZE {
+            return Err(Stop::Inconclusive);
+        }
+
+        self.stack_pushed.push(value);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Value {
+        match self.stack_pushed.pop() {
+            Some(value) => value,
+            None => {
+                self.stack_removed += 1;
+                self.stack_required = self.stack_removed;
+                Value::Stack(self.stack_removed - 1)
+            }
+        }
+    }
+
+    fn top(&mut self) -> Value {
+        let value = self.pop();
+        self.stack_pushed.push(value.clone());
+        value
+    }
+
+    fn remove(&mut self, end: &QueueEnd) -> Value {
+        // a removal runs out once everything there was and everything inserted
+        // at either end is gone, even when the values are modeled as coming
+        // from the deque the section started with
+        let inserted = self.front_inserted.len() + self.back_inserted.len();
+        let removed = self.front_removed + self.back_removed;
+        self.deque_required = self.deque_required.max((removed + 1).saturating_sub(inserted));
+
+        let (inserted, removed) = match *end {
+            Front => (&mut self.front_inserted, &mut self.front_removed),
+            Back => (&mut self.back_inserted, &mut self.back_removed),
+        };
+
+        match inserted.pop() {
+            Some(value) => value,
+            None => {
+                *removed += 1;
+                match *end {
+                    Front => Value::Front(*removed - 1),
+                    Back => Value::Back(*removed - 1),
+                }
+            }
+        }
+    }
+
+    fn front(&mut self) -> Value {
+        let value = self.remove(&Front);
+        self.front_inserted.push(value.clone());
+        value
+    }
+
+
+    fn value_from_source(&mut self, source: &ValueSource) -> Result<Value, Stop> {
+        let value = match *source {
+            ValueSource::Constant(value) => Value::Constant(value),
+            ValueSource::Pop => self.pop(),
+            ValueSource::CloneTop => self.top(),
+            ValueSource::Remove(ref end) => self.remove(end),
+
+            ValueSource::Input => {
+                let count = self.events.iter().filter(|event| **event == Event::Read).count();
+                self.record(Event::Read);
+                Value::Input(count)
+            }
+
+            ValueSource::Operate(ref lhs, ref operator, ref rhs) => {
+                let lhs = self.value_from_source(lhs)?;
+                let rhs = self.value_from_source(rhs)?;
+
+                // dividing by zero fails no matter what is divided
+                if let (Div, &Value::Constant(0)) | (Mod, &Value::Constant(0)) = (operator.clone(), &rhs) {
+                    return Err(Stop::Trap(parse::Trap::DivisionByZero));
+                }
+
+                if let (&Value::Constant(lhs), &Value::Constant(rhs)) = (&lhs, &rhs) {
+                    return operator.apply(lhs, rhs).map(Value::Constant).map_err(Stop::Trap);
+                }
+
+                Value::Operate(Box::new(lhs), operator.clone(), Box::new(rhs))
+            }
+
+            ValueSource::Equal => {
+                let top = self.pop();
+                let front = self.front();
+                Value::Equal(Box::new(top), Box::new(front)).simplify()
+            }
+
+            ValueSource::Greater => {
+                let top = self.pop();
+                let front = self.front();
+                Value::Greater(Box::new(top), Box::new(front)).simplify()
+            }
+        };
+
+        if value.size() > MAX_VALUE_SIZE {
+            Err(Stop::Inconclusive)
+        } else {
+            Ok(value)
+        }
+    }
+
+
+    /// Values that are taken from the stack or deque and put back unchanged
+    /// leave no trace
+    fn cancel_restored_values(&mut self) {
+        while self.stack_removed > 0 && self.stack_pushed.first() == Some(&Value::Stack(self.stack_removed - 1)) {
+            self.stack_pushed.remove(0);
+            self.stack_removed -= 1;
+        }
+
+        while self.front_removed > 0 && self.front_inserted.first() == Some(&Value::Front(self.front_removed - 1)) {
+            self.front_inserted.remove(0);
+            self.front_removed -= 1;
+        }
+
+        while self.back_removed > 0 && self.back_inserted.first() == Some(&Value::Back(self.back_removed - 1)) {
+            self.back_inserted.remove(0);
+            self.back_removed -= 1;
+        }
+    }
+}
+
+
+impl Value {
+    /// The number of nodes in the value
+    pub fn size(&self) -> usize {
+        match *self {
+            Value::Constant(_) | Value::Stack(_) | Value::Front(_) | Value::Back(_) | Value::Input(_) => 1,
+
+            Value::BitwiseNot(ref value) | Value::LogicalNot(ref value) => 1 + value.size(),
+
+            Value::Operate(ref lhs, _, ref rhs) |
+            Value::Equal(ref lhs, ref rhs) |
+            Value::Greater(ref lhs, ref rhs) => 1 + lhs.size() + rhs.size(),
+        }
+    }
+
+    /// Compute comparisons and negations of constants
+    fn simplify(self) -> Value {
+        match self {
+            Value::BitwiseNot(value) => match *value {
+                Value::Constant(value) => Value::Constant(!value),
+                value => Value::BitwiseNot(Box::new(value))
+            },
+
+            Value::LogicalNot(value) => match *value {
+                Value::Constant(value) => Value::Constant((value == 0) as i64),
+                value => Value::LogicalNot(Box::new(value))
+            },
+
+            Value::Equal(lhs, rhs) => match (*lhs, *rhs) {
+                (Value::Constant(lhs), Value::Constant(rhs)) => Value::Constant((lhs == rhs) as i64),
+                (lhs, rhs) => Value::Equal(Box::new(lhs), Box::new(rhs))
+            },
+
+            Value::Greater(lhs, rhs) => match (*lhs, *rhs) {
+                (Value::Constant(lhs), Value::Constant(rhs)) => Value::Constant((lhs > rhs) as i64),
+                (lhs, rhs) => Value::Greater(Box::new(lhs), Box::new(rhs))
+            },
+
+            value => value
+        }
+    }
+}
+
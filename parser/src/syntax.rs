@@ -0,0 +1,205 @@
+use parse::{self, *};
+use error::*;
+
+use std::fmt;
+use std::ops::Range;
+
+
+/// A program exactly as it was written, including whitespace and comments.
+/// Printing the tree gives back the original source.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct SyntaxTree {
+    pub dialect: Dialect,
+
+    /// The lines of the program, up to the first blank line
+    pub lines: Vec<Line>,
+
+    /// Everything from the blank line that ends the program, which is ignored
+    pub trailer: String,
+}
+
+
+/// A single line of a program
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Line {
+    /// Commands and the whitespace between them
+    pub tokens: Vec<Token>,
+
+    /// Everything from the first character that is not a command, empty if
+    /// the line has no comment
+    pub comment: String,
+
+    /// `"\n"`, `"\r\n"` or, on the last line of a file, nothing
+    pub ending: String,
+}
+
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Token {
+    /// A single command
+    Command(char),
+
+    /// Spaces and tabs
+    Whitespace(String),
+
+    /// A number or string literal in the literals dialect, as written
+    Literal(String),
+}
+
+
+impl SyntaxTree {
+    /// Split source code into lines, commands and comments. This never fails:
+    /// invalid literals are kept as they are and reported by `to_sequences`.
+    pub fn parse(source_code: &str, dialect: Dialect) -> SyntaxTree {
+        let mut lines = Vec::new();
+        let mut trailer = String::new();
+
+        let mut rest = source_code;
+        while !rest.is_empty() {
+            let end = rest.find('\n').map_or(rest.len(), |index| index + 1);
+            let (text, remaining) = rest.split_at(end);
+
+            let content = text.trim_end_matches('\n');
+            let content = if content.len() < text.len() { content.trim_end_matches('\r') } else { content };
+
+            if content.is_empty() {
+                trailer = rest.to_owned();
+                break;
+            }
+
+            let mut line = Line::parse(content, dialect);
+            line.ending = text[content.len()..].to_owned();
+            lines.push(line);
+
+            rest = remaining;
+        }
+
+        SyntaxTree { dialect, lines, trailer }
+    }
+
+
+    /// Parse the program into sequences, like `parse_str_with_dialect`
+    pub fn to_sequences(&self) -> Result<Vec<Sequence>> {
+        parse::parse_lines(&self.lines, self.dialect)
+    }
+}
+
+
+impl Line {
+    fn parse(content: &str, dialect: Dialect) -> Line {
+        let mut tokens = Vec::new();
+        let mut characters = content.char_indices().peekable();
+        let mut comment = String::new();
+
+        while let Some((start, character)) = characters.next() {
+            let token = match character {
+                ' ' | '\t' => {
+                    let mut whitespace = character.to_string();
+                    while let Some(&(_, next)) = characters.peek().filter(|&&(_, c)| c == ' ' || c == '\t') {
+                        whitespace.push(next);
+                        characters.next();
+                    }
+
+                    Token::Whitespace(whitespace)
+                }
+
+                '#' if dialect == Dialect::Literals && characters.peek().is_some_and(|&(_, c)| c.is_ascii_digit()) => {
+                    let mut literal = character.to_string();
+                    while let Some(&(_, digit)) = characters.peek().filter(|&&(_, c)| c.is_ascii_digit()) {
+                        literal.push(digit);
+                        characters.next();
+                    }
+
+                    Token::Literal(literal)
+                }
+
+                '"' if dialect == Dialect::Literals => {
+                    let mut literal = character.to_string();
+                    for (_, next) in characters.by_ref() {
+                        literal.push(next);
+                        if next == '"' {
+                            break;
+                        }
+                    }
+
+                    Token::Literal(literal)
+                }
+
+                _ if parse::is_command(character) => Token::Command(character),
+
+                _ => {
+                    comment = content[start..].to_owned();
+                    break;
+                }
+            };
+
+            tokens.push(token);
+        }
+
+        Line { tokens, comment, ending: String::new() }
+    }
+
+
+    /// The commands of the line without its comment
+    pub fn code(&self) -> String {
+        self.tokens.iter().map(Token::text).collect()
+    }
+
+    /// The column, counted in characters from 0, of the token each parsed
+    /// instruction of the line comes from, in the order they are executed
+    pub fn instruction_columns(&self) -> Vec<usize> {
+        self.instruction_spans().into_iter().map(|span| span.start).collect()
+    }
+
+    /// The columns, counted in characters from 0, of the token each parsed
+    /// instruction of the line comes from, in the order they are executed.
+    /// Every push of a literal spans all of the literal.
+    pub fn instruction_spans(&self) -> Vec<Range<usize>> {
+        let mut spans = Vec::new();
+        let mut column = 0;
+
+        for token in &self.tokens {
+            let width = token.text().chars().count();
+
+            let instructions = match *token {
+                Token::Command(_) => 1,
+                Token::Whitespace(_) => 0,
+                Token::Literal(ref literal) if literal.starts_with('#') => 1,
+                Token::Literal(ref literal) => literal.chars().count().saturating_sub(2),
+            };
+
+            spans.extend(std::iter::repeat_n(column..column + width, instructions));
+            column += width;
+        }
+
+        spans
+    }
+}
+
+
+impl Token {
+    /// The token as it was written
+    pub fn text(&self) -> String {
+        match *self {
+            Token::Command(command) => command.to_string(),
+            Token::Whitespace(ref text) | Token::Literal(ref text) => text.clone(),
+        }
+    }
+}
+
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            write!(f, "{}", line)?;
+        }
+
+        write!(f, "{}", self.trailer)
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.code(), self.comment, self.ending)
+    }
+}
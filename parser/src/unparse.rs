@@ -0,0 +1,205 @@
+
+use parse::{
+    self,
+    *,
+    Instruction::*,
+    ValueSource::*,
+    Operator::*,
+    QueueEnd::*,
+    Direction::*,
+    Start::*,
+};
+
+
+/// Turn sequences back into standard Karma source code. The result does the
+/// same as the sequences but is not necessarily made up of the same
+/// instructions: constants, for example, are written as arithmetic on digits.
+///
+/// Returns `None` if an instruction has no equivalent in Karma source, such as
+/// an exit in the middle of a line.
+pub fn unparse(sequences: &[Sequence]) -> Option<String> {
+    let mut lines: Vec<&Sequence> = sequences.iter().skip(1).collect();
+
+    // the exit following the last line is added by the parser
+    if lines.len() > 1 && *lines[lines.len() - 1] == vec![vec![Exit]] {
+        lines.pop();
+    }
+
+    let mut source = String::new();
+    for sequence in lines {
+        let line = unparse_sequence(sequence)?;
+
+        // an empty line would end the program
+        if line.is_empty() {
+            source.push(' ');
+        }
+
+        source += &line;
+        source.push('\n');
+    }
+
+    Some(source)
+}
+
+
+/// Write an integer as Karma code that pushes it to the stack
+pub fn unparse_number(value: i64) -> String {
+    if (0..=9).contains(&value) {
+        return value.to_string();
+    }
+
+    if (-9..0).contains(&value) {
+        return format!("{}0-", -value);
+    }
+
+    let mut code = unparse_number(value / 9) + "9*";
+    let remainder = value % 9;
+    if remainder != 0 {
+        code += &unparse_number(remainder);
+        code += "+";
+    }
+
+    code
+}
+
+
+fn unparse_sequence(sequence: &Sequence) -> Option<String> {
+    let mut line = String::new();
+    let mut after_skip = false;
+
+    for (index, section) in sequence.iter().enumerate() {
+        let last_section = index + 1 == sequence.len();
+
+        let mut code = String::new();
+        for (position, instruction) in section.iter().enumerate() {
+            let last_instruction = last_section && position + 1 == section.len();
+
+            match *instruction {
+                Exit if last_instruction => (),
+                _ => code += &unparse_instruction(instruction)?,
+            }
+        }
+
+        // the instruction following a skip must be a single command
+        if after_skip && code.chars().count() != 1 {
+            return None;
+        }
+
+        after_skip = section.last() == Some(&SkipIfNotOne);
+        line += &code;
+    }
+
+    Some(line)
+}
+
+
+fn unparse_instruction(instruction: &Instruction) -> Option<String> {
+    let code = match *instruction {
+        BitwiseNot => "~".to_owned(),
+        LogicalNot => "!".to_owned(),
+
+        Push(ref source) => unparse_value(source)?,
+
+        Insert(ref source, Front) => unparse_value(source)? + "}",
+        Insert(ref source, Back) => unparse_value(source)? + "[",
+
+        Destroy => "#".to_owned(),
+        Replace(ref source) => "#".to_owned() + &unparse_value(source)?,
+        Discard(ref source) => unparse_value(source)? + "#",
+
+        OutputCharacter(ref source) => unparse_value(source)? + ":",
+        OutputNumber(ref source) => unparse_value(source)? + ";",
+
+        SkipIfNotOne => "@".to_owned(),
+
+        Jump(Next, Restart) => ",".to_owned(),
+        Jump(Next, Continue) => ".".to_owned(),
+        Jump(Previous, Continue) => "'".to_owned(),
+        Jump(Current, Restart) => "<".to_owned(),
+        Jump(_, _) => return None,
+
+        Exit => return None,
+
+        // divide by values that always fail
+        Instruction::Trap(parse::Trap::DivisionByZero) => "00/".to_owned(),
+        Instruction::Trap(parse::Trap::DivisionOverflow) => unparse_number(-1) + &unparse_number(i64::MIN) + "/",
+        Instruction::Trap(_) => return None,
+
+        // the loop that follows does the same, only slower
+        CountedLoop(_, _) => String::new(),
+    };
+
+    Some(code)
+}
+
+
+/// Code that leaves a value on top of the stack
+fn unparse_value(source: &ValueSource) -> Option<String> {
+    let code = match *source {
+        Constant(value) => unparse_number(value),
+        Pop => String::new(),
+        CloneTop => "\\".to_owned(),
+        Remove(Front) => "{".to_owned(),
+        Remove(Back) => "]".to_owned(),
+        Input => "?".to_owned(),
+        Equal => "=".to_owned(),
+        Greater => ">".to_owned(),
+
+        Operate(ref lhs, ref operator, ref rhs) => {
+            let operator = operator_character(operator);
+
+            // the left hand side is evaluated first and ends up on top
+            if **rhs == Pop {
+                unparse_value(lhs)? + operator
+            } else if is_constant(lhs) {
+                unparse_value(rhs)? + &unparse_value(lhs)? + operator
+            } else if !uses_deque_front(rhs) {
+                unparse_value(lhs)? + "}" + &unparse_value(rhs)? + "{" + operator
+            } else if !uses_deque_back(rhs) {
+                unparse_value(lhs)? + "[" + &unparse_value(rhs)? + "]" + operator
+            } else {
+                return None;
+            }
+        }
+    };
+
+    Some(code)
+}
+
+
+fn operator_character(operator: &Operator) -> &'static str {
+    match *operator {
+        Add => "+",
+        Sub => "-",
+        Mul => "*",
+        Div => "/",
+        Mod => "%",
+        And => "&",
+        Or => "|",
+        Xor => "^",
+    }
+}
+
+fn is_constant(source: &ValueSource) -> bool {
+    match *source {
+        Constant(_) => true,
+        Operate(ref lhs, _, ref rhs) => is_constant(lhs) && is_constant(rhs),
+        _ => false
+    }
+}
+
+fn uses_deque_front(source: &ValueSource) -> bool {
+    match *source {
+        Remove(Front) | Equal | Greater => true,
+        Operate(ref lhs, _, ref rhs) => uses_deque_front(lhs) || uses_deque_front(rhs),
+        _ => false
+    }
+}
+
+fn uses_deque_back(source: &ValueSource) -> bool {
+    match *source {
+        Remove(Back) => true,
+        Operate(ref lhs, _, ref rhs) => uses_deque_back(lhs) || uses_deque_back(rhs),
+        _ => false
+    }
+}